@@ -0,0 +1,726 @@
+// the optional local HTTP API: lets editor plugins and scripts read and add to chats, and
+// serves the OpenAI-compatible proxy (see `proxy`).
+// Bound to 127.0.0.1 only, every request needs `Authorization: Bearer <token>`
+
+use crate::backend::ChatError;
+use crate::generation::{self, GenerationSlots, ReplyContext, ReplyHooks, SlotGuard};
+use crate::proxy;
+use crate::storage::{self, ChatErrorRecord, ChatMessage, Settings, StorageError};
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use uuid::Uuid;
+
+// a restarted server may find its old listener still closing
+const BIND_ATTEMPTS: u32 = 10;
+const BIND_RETRY_MS: u64 = 100;
+
+// a change made through the API, so the GUI can show it right away
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApiEvent {
+    // a chat was created, renamed or deleted
+    ChatsChanged,
+    // a message was stored in this chat
+    MessagesChanged(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct ApiConfig {
+    // database shared with the GUI; opened per request like everywhere else
+    pub db_path: String,
+    // 0 = any free port
+    pub port: u16,
+    pub token: String,
+    // Ollama server replies are generated with
+    pub base_url: String,
+    // shared with the GUI, so API and proxy requests count against the same generation limit
+    pub slots: GenerationSlots,
+}
+
+#[derive(Clone)]
+pub(crate) struct ApiState {
+    pub(crate) config: Arc<ApiConfig>,
+    pub(crate) client: Client,
+    events: broadcast::Sender<ApiEvent>,
+}
+
+impl ApiState {
+    pub(crate) fn db(&self) -> Result<rusqlite::Connection, ApiError> {
+        Ok(storage::open(&self.config.db_path)?)
+    }
+
+    pub(crate) fn notify(&self, event: ApiEvent) {
+        // nobody listening (GUI closed the receiver) is fine
+        let _ = self.events.send(event);
+    }
+}
+
+// a running server; dropping it shuts the server down
+pub struct ApiServer {
+    addr: SocketAddr,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl ApiServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+// a random token for a new installation
+pub fn generate_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+// bind 127.0.0.1:`config.port` and serve until the returned handle is dropped
+pub async fn start(config: ApiConfig, events: broadcast::Sender<ApiEvent>) -> std::io::Result<ApiServer> {
+    let mut attempt = 1;
+    let listener = loop {
+        match tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, config.port)).await {
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && attempt < BIND_ATTEMPTS => {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_millis(BIND_RETRY_MS)).await;
+            }
+            other => break other?,
+        }
+    };
+    let addr = listener.local_addr()?;
+    let state = ApiState {
+        config: Arc::new(config),
+        client: Client::new(),
+        events,
+    };
+    let (shutdown, stopped) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let served = axum::serve(listener, router(state))
+            .with_graceful_shutdown(async {
+                // resolves when the handle is dropped
+                let _ = stopped.await;
+            })
+            .await;
+        if let Err(e) = served {
+            eprintln!("API server stopped: {}", e);
+        }
+    });
+    Ok(ApiServer {
+        addr,
+        _shutdown: shutdown,
+    })
+}
+
+fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/api/chats", get(list_chats).post(create_chat))
+        .route("/api/chats/{id}", get(get_chat).patch(rename_chat).delete(delete_chat))
+        .route("/api/chats/{id}/messages", get(list_messages).post(post_message))
+        .merge(proxy::routes())
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+/* ================= AUTH AND ERRORS ================= */
+
+async fn require_token(State(state): State<ApiState>, request: Request, next: Next) -> Response {
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+    if state.config.token.is_empty() || !same_token(given, &state.config.token) {
+        return ApiError(StatusCode::UNAUTHORIZED, "Missing or wrong API token".to_string()).into_response();
+    }
+    next.run(request).await
+}
+
+// compares every byte so the answer time doesn't reveal how much of the token was right
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// an error answered as {"error": "..."}
+#[derive(Debug)]
+pub(crate) struct ApiError(pub(crate) StatusCode, pub(crate) String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<StorageError> for ApiError {
+    fn from(e: StorageError) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+fn not_found(id: &str) -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, format!("No chat with id {}", id))
+}
+
+/* ================= CHATS ================= */
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ApiChat {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Deserialize)]
+struct ChatTitle {
+    #[serde(default)]
+    title: Option<String>,
+}
+
+async fn list_chats(State(state): State<ApiState>) -> Result<Json<Vec<ApiChat>>, ApiError> {
+    let chats = storage::list_chats(&state.db()?)?;
+    Ok(Json(chats.into_iter().map(|(id, title)| ApiChat { id, title }).collect()))
+}
+
+async fn create_chat(
+    State(state): State<ApiState>,
+    body: Option<Json<ChatTitle>>,
+) -> Result<(StatusCode, Json<ApiChat>), ApiError> {
+    let title = body
+        .and_then(|Json(b)| b.title)
+        .map(|t| storage::clamp_title(t.trim()))
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| "New Chat".to_string());
+    let id = Uuid::new_v4().to_string();
+    storage::create_chat(&state.db()?, &id, &title, None)?;
+    state.notify(ApiEvent::ChatsChanged);
+    Ok((StatusCode::CREATED, Json(ApiChat { id, title })))
+}
+
+async fn get_chat(State(state): State<ApiState>, Path(id): Path<String>) -> Result<Json<ApiChat>, ApiError> {
+    storage::list_chats(&state.db()?)?
+        .into_iter()
+        .find(|(cid, _)| *cid == id)
+        .map(|(id, title)| Json(ApiChat { id, title }))
+        .ok_or_else(|| not_found(&id))
+}
+
+async fn rename_chat(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(body): Json<ChatTitle>,
+) -> Result<Json<ApiChat>, ApiError> {
+    let conn = state.db()?;
+    if !storage::chat_exists(&conn, &id)? {
+        return Err(not_found(&id));
+    }
+    let title = body.title.unwrap_or_default();
+    if title.trim().is_empty() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "title must not be empty".to_string()));
+    }
+    let title = storage::rename_chat(&conn, &id, title.trim())?;
+    state.notify(ApiEvent::ChatsChanged);
+    Ok(Json(ApiChat { id, title }))
+}
+
+async fn delete_chat(State(state): State<ApiState>, Path(id): Path<String>) -> Result<StatusCode, ApiError> {
+    let conn = state.db()?;
+    if !storage::chat_exists(&conn, &id)? {
+        return Err(not_found(&id));
+    }
+    // same as deleting in the app: the chat can still be restored from the Trash
+    storage::trash_chats(&conn, &[id])?;
+    state.notify(ApiEvent::ChatsChanged);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/* ================= MESSAGES ================= */
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ApiMessage {
+    pub role: String,
+    pub content: String,
+    // model that wrote an assistant reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    // persona the chat had when the reply was written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<String>,
+}
+
+impl From<ChatMessage> for ApiMessage {
+    fn from(m: ChatMessage) -> Self {
+        ApiMessage {
+            role: m.role,
+            content: m.content,
+            model: m.model,
+            persona: m.persona,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NewMessage {
+    content: String,
+    // defaults to the model chosen in Settings
+    #[serde(default)]
+    model: Option<String>,
+    // stream the reply as NDJSON (default) or answer once it's complete
+    #[serde(default = "default_stream")]
+    stream: bool,
+}
+
+fn default_stream() -> bool {
+    true
+}
+
+async fn list_messages(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<ApiMessage>>, ApiError> {
+    let conn = state.db()?;
+    if !storage::chat_exists(&conn, &id)? {
+        return Err(not_found(&id));
+    }
+    Ok(Json(
+        storage::load_chat_messages(&conn, &id)?
+            .into_iter()
+            // tool rounds are internal to a reply
+            .filter(|m| m.role == "user" || m.role == "assistant")
+            .map(ApiMessage::from)
+            .collect(),
+    ))
+}
+
+// store a user message and answer it the way the app would: with the chat's history, persona,
+// output format and knowledge base. Streamed replies are NDJSON: {"content": "<new text>"} lines,
+// then {"done": true, "message": {...}} or {"error": "..."}
+async fn post_message(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    Json(body): Json<NewMessage>,
+) -> Result<Response, ApiError> {
+    let conn = state.db()?;
+    if !storage::chat_exists(&conn, &id)? {
+        return Err(not_found(&id));
+    }
+    if body.content.trim().is_empty() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "content must not be empty".to_string()));
+    }
+    let settings = storage::load_settings(&conn)?;
+    // checked up front so a request that can't be answered stores nothing
+    let (reply_settings, _) = generation::reply_settings(&conn, &id, &settings, body.model.as_deref())?;
+    if reply_settings.model.trim().is_empty() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, ChatError::NoModel.to_string()));
+    }
+
+    storage::insert_message(&conn, &id, &ChatMessage::new("user", body.content.clone()), None)?;
+    storage::enforce_history_limit(&conn, &id);
+    // a new turn supersedes errors from earlier ones, as in the app
+    storage::clear_chat_errors(&conn, &id)?;
+    state.notify(ApiEvent::MessagesChanged(id.clone()));
+
+    if !body.stream {
+        let reply = generate(&state, &id, &body.content, &settings, body.model, |_| {})
+            .await
+            .map_err(reply_error)?;
+        return Ok(Json(ApiMessage::from(reply)).into_response());
+    }
+
+    let (lines, receiver) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        // the reply is stored even if the client stops reading
+        let mut sent = 0;
+        let result = generate(&state, &id, &body.content, &settings, body.model, |text| {
+            let _ = lines.send(ndjson(&json!({ "content": &text[sent..] })));
+            sent = text.len();
+        })
+        .await;
+        let last = match result {
+            Ok(reply) => json!({ "done": true, "message": ApiMessage::from(reply) }),
+            Err(e) => json!({ "error": e.to_string() }),
+        };
+        let _ = lines.send(ndjson(&last));
+    });
+    let stream = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        let line = receiver.recv().await?;
+        Some((Ok::<_, std::convert::Infallible>(line), receiver))
+    });
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(stream)).into_response())
+}
+
+fn ndjson(value: &serde_json::Value) -> String {
+    format!("{}\n", value)
+}
+
+// storage trouble is ours, everything else comes from Ollama or the model
+fn reply_error(err: ChatError) -> ApiError {
+    let status = match err {
+        ChatError::NotLoaded(_) | ChatError::NotStored(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_GATEWAY,
+    };
+    ApiError(status, err.to_string())
+}
+
+// follows a reply generated for an API request
+struct ApiReply<F> {
+    state: ApiState,
+    chat_id: String,
+    slot: Option<SlotGuard>,
+    on_text: F,
+}
+
+impl<F: FnMut(&str)> ReplyHooks for ApiReply<F> {
+    fn started(&mut self, slot: SlotGuard) {
+        self.slot = Some(slot);
+    }
+
+    fn partial(&mut self, text: Option<&str>) {
+        if let Some(text) = text {
+            (self.on_text)(text);
+        }
+    }
+
+    fn stored(&mut self, _message: &ChatMessage) {
+        self.state.notify(ApiEvent::MessagesChanged(self.chat_id.clone()));
+    }
+
+    fn failed(&mut self, _err: &ChatError, record: Result<ChatErrorRecord, StorageError>) {
+        if let Err(e) = record {
+            eprintln!("Could not store the failed request: {}", e);
+        }
+    }
+}
+
+// answer `user_message` in `chat_id` through the app's reply pipeline (no tools: nobody is
+// there to approve them); `on_text` sees the reply so far after every chunk
+async fn generate(
+    state: &ApiState,
+    chat_id: &str,
+    user_message: &str,
+    settings: &Settings,
+    model: Option<String>,
+    on_text: impl FnMut(&str),
+) -> Result<ChatMessage, ChatError> {
+    let ctx = ReplyContext {
+        client: &state.client,
+        db_path: &state.config.db_path,
+        base_url: &state.config.base_url,
+        slots: &state.config.slots,
+        settings,
+        model,
+        tools: vec![],
+    };
+    let mut hooks = ApiReply {
+        state: state.clone(),
+        chat_id: chat_id.to_string(),
+        slot: None,
+        on_text,
+    };
+    generation::generate_reply(&ctx, chat_id, user_message, &mut hooks)
+        .await?
+        // only a cancelled reply has none, and API replies aren't cancelled
+        .ok_or_else(|| ChatError::Request("the reply was cancelled".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{client, http_response, mock_server, mock_server_replies};
+
+    const TOKEN: &str = "test-token";
+
+    // a server on a free port over its own database file, answering from `ollama`
+    async fn test_server(name: &str, ollama: &str) -> (ApiServer, broadcast::Receiver<ApiEvent>, String) {
+        let path = std::env::temp_dir()
+            .join(format!("rusty-chat-api-{}-{}.db", name, std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let _ = std::fs::remove_file(&path);
+        let (events, receiver) = broadcast::channel(16);
+        let config = ApiConfig {
+            db_path: path.clone(),
+            port: 0,
+            token: TOKEN.to_string(),
+            base_url: ollama.to_string(),
+            slots: GenerationSlots::default(),
+        };
+        (start(config, events).await.unwrap(), receiver, path)
+    }
+
+    fn url(server: &ApiServer, path: &str) -> String {
+        format!("http://{}{}", server.addr(), path)
+    }
+
+    #[test]
+    fn tokens_are_compared_exactly() {
+        assert!(same_token("abc", "abc"));
+        assert!(!same_token("abd", "abc"));
+        assert!(!same_token("ab", "abc"));
+        assert_eq!(generate_token().len(), 32);
+        assert_ne!(generate_token(), generate_token());
+    }
+
+    #[tokio::test]
+    async fn requests_need_the_token() {
+        let (server, _, path) = test_server("auth", "http://127.0.0.1:9").await;
+        let http = client();
+        let missing = http.get(url(&server, "/api/chats")).send().await.unwrap();
+        assert_eq!(missing.status(), 401);
+        let wrong = http
+            .get(url(&server, "/api/chats"))
+            .bearer_auth("nope")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(wrong.status(), 401);
+        let ok = http
+            .get(url(&server, "/api/chats"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(ok.status(), 200);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn chats_can_be_created_renamed_and_deleted() {
+        let (server, mut events, path) = test_server("chats", "http://127.0.0.1:9").await;
+        let http = client();
+
+        let created: ApiChat = http
+            .post(url(&server, "/api/chats"))
+            .bearer_auth(TOKEN)
+            .json(&json!({ "title": "From the editor" }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(created.title, "From the editor");
+        assert_eq!(events.recv().await.unwrap(), ApiEvent::ChatsChanged);
+
+        let renamed: ApiChat = http
+            .patch(url(&server, &format!("/api/chats/{}", created.id)))
+            .bearer_auth(TOKEN)
+            .json(&json!({ "title": "Renamed" }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(renamed.title, "Renamed");
+
+        let listed: Vec<ApiChat> = http
+            .get(url(&server, "/api/chats"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(listed, vec![renamed]);
+
+        let chat_url = url(&server, &format!("/api/chats/{}", created.id));
+        let deleted = http.delete(&chat_url).bearer_auth(TOKEN).send().await.unwrap();
+        assert_eq!(deleted.status(), 204);
+        let gone = http.get(&chat_url).bearer_auth(TOKEN).send().await.unwrap();
+        assert_eq!(gone.status(), 404);
+        let trash = storage::list_trash(&storage::open(&path).unwrap()).unwrap();
+        assert_eq!(trash.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(), vec![created.id.as_str()]);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn posted_messages_stream_the_reply_and_store_both_turns() {
+        let chunks = [
+            r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#,
+            r#"{"message":{"role":"assistant","content":"lo"},"done":true}"#,
+        ]
+        .join("\n");
+        let (ollama, requests) = mock_server(vec![http_response("200 OK", &chunks)]);
+        let (server, _, path) = test_server("messages", &ollama).await;
+        let http = client();
+
+        let chat: ApiChat = http
+            .post(url(&server, "/api/chats"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let messages_url = url(&server, &format!("/api/chats/{}/messages", chat.id));
+        let body = http
+            .post(&messages_url)
+            .bearer_auth(TOKEN)
+            .json(&json!({ "content": "Say hello", "model": "llama3" }))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let lines: Vec<serde_json::Value> = body.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines[0]["content"], "Hel");
+        assert_eq!(lines[1]["content"], "lo");
+        assert_eq!(lines[2]["done"], true);
+        assert_eq!(lines[2]["message"]["content"], "Hello");
+
+        let sent = requests.recv().unwrap();
+        assert!(sent.contains(r#""model":"llama3""#));
+        assert!(sent.contains("Say hello"));
+
+        let stored: Vec<ApiMessage> = http
+            .get(&messages_url)
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].content, "Say hello");
+        assert_eq!(stored[1].content, "Hello");
+        assert_eq!(stored[1].model.as_deref(), Some("llama3"));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn replies_use_the_chats_persona_and_failures_are_recorded() {
+        let (ollama, requests) = mock_server_replies(vec![
+            http_response("200 OK", r#"{"message":{"role":"assistant","content":"Arr"},"done":true}"#),
+            http_response("404 Not Found", r#"{"error":"model 'pirate-llm' not found"}"#),
+        ]);
+        let (server, _, path) = test_server("persona", &ollama).await;
+        let http = client();
+        let chat: ApiChat = http
+            .post(url(&server, "/api/chats"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let conn = storage::open(&path).unwrap();
+        let persona = storage::Persona {
+            name: "Pirate".to_string(),
+            system_prompt: "Talk like a pirate.".to_string(),
+            model: "pirate-llm".to_string(),
+            ..Default::default()
+        };
+        let persona_id = storage::save_persona(&conn, &persona).unwrap();
+        storage::set_chat_persona(&conn, &chat.id, Some(persona_id)).unwrap();
+
+        // no model in the request or the settings: the persona's is used
+        let messages_url = url(&server, &format!("/api/chats/{}/messages", chat.id));
+        let reply: ApiMessage = http
+            .post(&messages_url)
+            .bearer_auth(TOKEN)
+            .json(&json!({ "content": "Hello", "stream": false }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(reply.content, "Arr");
+        assert_eq!(reply.model.as_deref(), Some("pirate-llm"));
+        assert_eq!(reply.persona.as_deref(), Some("Pirate"));
+        let sent = requests.recv().unwrap();
+        assert!(sent.contains(r#""model":"pirate-llm""#));
+        assert!(sent.contains("Talk like a pirate."));
+
+        let failed = http
+            .post(&messages_url)
+            .bearer_auth(TOKEN)
+            .json(&json!({ "content": "Again", "stream": false }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(failed.status(), 502);
+        let errors = storage::list_chat_errors(&conn, &chat.id).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ChatError::Status(404, String::new()).kind());
+        assert_eq!(errors[0].user_message.as_deref(), Some("Again"));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn posting_without_a_model_is_rejected() {
+        let (server, _, path) = test_server("no-model", "http://127.0.0.1:9").await;
+        let http = client();
+        let chat: ApiChat = http
+            .post(url(&server, "/api/chats"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let response = http
+            .post(url(&server, &format!("/api/chats/{}/messages", chat.id)))
+            .bearer_auth(TOKEN)
+            .json(&json!({ "content": "hi" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        let missing = http
+            .post(url(&server, "/api/chats/nope/messages"))
+            .bearer_auth(TOKEN)
+            .json(&json!({ "content": "hi", "model": "llama3" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), 404);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn unreadable_messages_are_an_error_not_an_empty_chat() {
+        let (server, _, path) = test_server("unreadable", "http://127.0.0.1:9").await;
+        let http = client();
+        let chat: ApiChat = http
+            .post(url(&server, "/api/chats"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        // a blob where the text should be can't be read back as a message
+        storage::open(&path)
+            .unwrap()
+            .execute(
+                "INSERT INTO messages (chat_id, role, content) VALUES (?1, 'user', X'00ff')",
+                [&chat.id],
+            )
+            .unwrap();
+        let response = http
+            .get(url(&server, &format!("/api/chats/{}/messages", chat.id)))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 500);
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,959 @@
+// Ollama HTTP client: request/response types, streamed replies and typed errors
+
+use futures_util::StreamExt;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::time::Duration;
+
+// Ollama server every request is sent to (also recorded on each assistant message)
+pub const OLLAMA_BASE_URL: &str = "http://localhost:11434";
+// attempts for a request that fails while Ollama is unreachable or still loading the model
+pub const MAX_SEND_ATTEMPTS: u32 = 5;
+const RETRY_BASE_DELAY_MS: u64 = 1000;
+const HEALTH_TIMEOUT_SECS: u64 = 3;
+
+/* ================= API STRUCTURES ================= */
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OllamaMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<OllamaToolCall>>,
+    // name of the tool a 'tool' role message answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+}
+
+impl OllamaMessage {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        OllamaMessage {
+            role: role.to_string(),
+            content: content.into(),
+            tool_calls: None,
+            tool_name: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OllamaToolCall {
+    pub function: OllamaFunctionCall,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OllamaFunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OllamaChatRequest {
+    pub model: String,
+    pub messages: Vec<OllamaMessage>,
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+    // sampling settings; Ollama's key names (see `conversation::sampling_options`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<Value>,
+    // function definitions the model may call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Value>>,
+    // "json" or a JSON Schema the reply must follow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Value>,
+}
+
+fn default_stream() -> bool {
+    false
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OllamaChatResponse {
+    pub message: OllamaMessage,
+    pub done: bool,
+    #[serde(default)]
+    pub response: String,
+}
+
+/* ================= ERRORS ================= */
+
+// why a reply could not be produced
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChatError {
+    NoModel,
+    // the server could not be reached (refused, timed out)
+    Connect(String),
+    // any other failure sending the request
+    Request(String),
+    // non-success HTTP status with the server's explanation, if it gave one
+    Status(u16, String),
+    // the reply stream broke off or reported an error
+    Stream(String),
+    // structured output still invalid after `retries` re-prompts
+    Format { retries: usize, errors: Vec<String> },
+    // the reply arrived but writing it to the history failed
+    NotStored(String),
+    // the chat's history (or its settings) could not be read, so nothing was sent
+    NotLoaded(String),
+}
+
+impl ChatError {
+    pub fn from_reqwest(e: &reqwest::Error) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        let unreachable = e.is_connect() || e.is_timeout();
+        // the browser doesn't say why a fetch failed: refused and blocked by CORS look the same
+        #[cfg(target_arch = "wasm32")]
+        let unreachable = e.is_request() || e.is_timeout();
+        if unreachable {
+            ChatError::Connect(e.to_string())
+        } else {
+            ChatError::Request(e.to_string())
+        }
+    }
+
+    pub async fn from_response(resp: reqwest::Response) -> Self {
+        let status = resp.status().as_u16();
+        let body = resp.text().await.unwrap_or_default();
+        // Ollama answers errors with {"error": "..."}
+        let detail = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(str::to_string))
+            .unwrap_or(body);
+        ChatError::Status(status, detail.trim().to_string())
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ChatError::NoModel => "no_model",
+            ChatError::Connect(_) => "connect",
+            ChatError::Request(_) => "request",
+            ChatError::Status(..) => "status",
+            ChatError::Stream(_) => "stream",
+            ChatError::Format { .. } => "format",
+            ChatError::NotStored(_) => "not_stored",
+            ChatError::NotLoaded(_) => "not_loaded",
+        }
+    }
+
+    // refused connections and 5xx usually mean Ollama is starting up or loading the model
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ChatError::Connect(_) => true,
+            ChatError::Status(code, _) => *code >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatError::NoModel => write!(
+                f,
+                "No model selected. Please open Settings and choose a model before sending messages."
+            ),
+            #[cfg(not(target_arch = "wasm32"))]
+            ChatError::Connect(_) => write!(
+                f,
+                "Could not connect to Ollama. Make sure Ollama is running at {}",
+                OLLAMA_BASE_URL
+            ),
+            #[cfg(target_arch = "wasm32")]
+            ChatError::Connect(_) => write!(
+                f,
+                "Could not connect to Ollama. Make sure Ollama is running at {} and allows this page \
+                 (start it with OLLAMA_ORIGINS set to this page's origin)",
+                OLLAMA_BASE_URL
+            ),
+            ChatError::Request(e) => write!(f, "The request to Ollama failed: {}", e),
+            ChatError::Status(code, detail) if detail.is_empty() => {
+                write!(f, "Ollama API returned status {}", code)
+            }
+            ChatError::Status(code, detail) => {
+                write!(f, "Ollama API returned status {}: {}", code, detail)
+            }
+            ChatError::Stream(e) => write!(f, "Failed to read the response from Ollama: {}", e),
+            ChatError::Format { retries, errors } => write!(
+                f,
+                "Reply does not match the required JSON format after {} retries:\n- {}",
+                retries,
+                errors.join("\n- ")
+            ),
+            ChatError::NotStored(e) => write!(f, "The reply arrived but could not be saved to the history: {}", e),
+            ChatError::NotLoaded(e) => write!(f, "The chat could not be read, so nothing was sent: {}", e),
+        }
+    }
+}
+
+// 1s, 2s, 4s, ... before attempt `attempt + 1`
+pub fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_millis(RETRY_BASE_DELAY_MS << attempt.saturating_sub(1).min(5))
+}
+
+// readable message for a failed model management call
+pub async fn ollama_error(resp: reqwest::Response) -> String {
+    let status = resp.status();
+    match resp.json::<Value>().await {
+        Ok(json) => json
+            .get("error")
+            .and_then(|v| v.as_str())
+            .map(|e| format!("{} ({})", e, status))
+            .unwrap_or_else(|| format!("Ollama API returned status {}", status)),
+        Err(_) => format!("Ollama API returned status {}", status),
+    }
+}
+
+/* ================= REQUESTS ================= */
+
+// POST one /api/chat request; a non-success status comes back as an error
+pub async fn send_chat(
+    client: &Client,
+    base_url: &str,
+    request: &OllamaChatRequest,
+) -> Result<reqwest::Response, ChatError> {
+    let url = format!("{}/api/chat", base_url);
+    match client.post(&url).json(request).send().await {
+        Ok(response) if response.status().is_success() => Ok(response),
+        Ok(response) => Err(ChatError::from_response(response).await),
+        Err(e) => Err(ChatError::from_reqwest(&e)),
+    }
+}
+
+// read a newline-delimited JSON body chunk by chunk, calling `on_line` for every complete object
+pub async fn read_ndjson(
+    resp: reqwest::Response,
+    mut on_line: impl FnMut(Value),
+) -> Result<(), String> {
+    let mut buf: Vec<u8> = Vec::new();
+    // a byte stream rather than `chunk()`, which the browser build of reqwest doesn't have
+    let mut body = resp.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        buf.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+        while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=pos).collect();
+            if let Ok(v) = serde_json::from_slice::<Value>(&line) {
+                on_line(v);
+            }
+        }
+    }
+    // last object may not be newline-terminated
+    if let Ok(v) = serde_json::from_slice::<Value>(&buf) {
+        on_line(v);
+    }
+    Ok(())
+}
+
+// collect a streamed /api/chat reply; `on_text` sees the reply text so far after every chunk
+pub async fn read_chat_stream(
+    resp: reqwest::Response,
+    mut on_text: impl FnMut(&str),
+) -> Result<OllamaChatResponse, String> {
+    let mut message = OllamaMessage::new("assistant", "");
+    let mut done = false;
+    let mut error: Option<String> = None;
+    read_ndjson(resp, |line| {
+        if let Some(e) = line.get("error").and_then(|e| e.as_str()) {
+            error = Some(e.to_string());
+            return;
+        }
+        let Ok(chunk) = serde_json::from_value::<OllamaChatResponse>(line) else {
+            return;
+        };
+        if !chunk.message.content.is_empty() {
+            message.content.push_str(&chunk.message.content);
+            on_text(&message.content);
+        }
+        if let Some(calls) = chunk.message.tool_calls {
+            message.tool_calls.get_or_insert_with(Vec::new).extend(calls);
+        }
+        done |= chunk.done;
+    })
+    .await?;
+    match error {
+        Some(e) => Err(e),
+        None => Ok(OllamaChatResponse {
+            message,
+            done,
+            response: String::new(),
+        }),
+    }
+}
+
+// extract model names from an /api/tags reply; tolerates the older plain-array shape
+pub fn parse_model_names(json: &Value) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+
+    // Newer Ollama returns {"models":[{...}]}
+    if let Some(models_arr) = json.get("models").and_then(|v| v.as_array()) {
+        for item in models_arr {
+            if let Some(m) = item
+                .get("model")
+                .or(item.get("name"))
+                .and_then(|v| v.as_str())
+            {
+                names.push(m.to_string());
+            }
+        }
+    } else if let Some(arr) = json.as_array() {
+        // older shape: plain array
+        for item in arr {
+            if let Some(s) = item.as_str() {
+                names.push(s.to_string());
+            } else if let Some(n) = item.get("name").and_then(|v| v.as_str()) {
+                names.push(n.to_string());
+            } else if let Some(n) = item.get("model").and_then(|v| v.as_str()) {
+                names.push(n.to_string());
+            }
+        }
+    }
+
+    // dedupe preserving order
+    let mut seen = HashSet::new();
+    names.retain(|n| seen.insert(n.clone()));
+    names
+}
+
+pub async fn fetch_model_names(client: &Client, base_url: &str) -> Result<Vec<String>, String> {
+    let url = format!("{}/api/tags", base_url);
+    let resp = client.get(&url).send().await.map_err(|e| e.to_string())?;
+    let json = resp.json::<Value>().await.map_err(|e| e.to_string())?;
+    Ok(parse_model_names(&json))
+}
+
+// version string reported by /api/version; any failure means the server isn't usable
+pub async fn fetch_backend_version(client: &Client, base_url: &str) -> Result<String, String> {
+    let url = format!("{}/api/version", base_url);
+    let resp = client
+        .get(&url)
+        .timeout(Duration::from_secs(HEALTH_TIMEOUT_SECS))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("status {}", resp.status()));
+    }
+    let json = resp.json::<Value>().await.map_err(|e| e.to_string())?;
+    Ok(json
+        .get("version")
+        .and_then(|v| v.as_str())
+        .unwrap_or("unknown version")
+        .to_string())
+}
+
+/* ================= MODEL MANAGEMENT ================= */
+
+// an installed model as listed by /api/tags
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstalledModel {
+    pub name: String,
+    pub size: u64,
+    pub modified_at: String,
+}
+
+// the interesting parts of /api/show for one model
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct ModelDetails {
+    pub parameters: String,
+    pub template: String,
+    pub license: String,
+    pub modelfile: String,
+    pub context_length: Option<u64>,
+    pub quantization: String,
+    pub parameter_size: String,
+    pub family: String,
+}
+
+// a model currently loaded into memory, from /api/ps
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadedModel {
+    pub name: String,
+    pub size: u64,
+    pub size_vram: u64,
+    pub expires_at: String,
+}
+
+// JSON body of a successful reply; anything else becomes a readable error
+async fn json_reply(request: reqwest::RequestBuilder) -> Result<Value, String> {
+    let resp = request.send().await.map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(ollama_error(resp).await);
+    }
+    resp.json::<Value>().await.map_err(|e| e.to_string())
+}
+
+// for calls whose reply carries nothing but success or an error
+async fn empty_reply(request: reqwest::RequestBuilder) -> Result<(), String> {
+    let resp = request.send().await.map_err(|e| e.to_string())?;
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(ollama_error(resp).await)
+    }
+}
+
+// POST `body` and hand every line of the streamed NDJSON reply to `on_line`
+async fn post_ndjson(
+    client: &Client,
+    url: &str,
+    body: &impl Serialize,
+    on_line: impl FnMut(Value),
+) -> Result<(), String> {
+    let resp = client.post(url).json(body).send().await.map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(ollama_error(resp).await);
+    }
+    read_ndjson(resp, on_line).await
+}
+
+pub async fn fetch_installed_models(client: &Client, base_url: &str) -> Result<Vec<InstalledModel>, String> {
+    let json = json_reply(client.get(format!("{}/api/tags", base_url))).await?;
+    let models = json
+        .get("models")
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|item| {
+                    let name = item
+                        .get("model")
+                        .or(item.get("name"))
+                        .and_then(|v| v.as_str())?;
+                    Some(InstalledModel {
+                        name: name.to_string(),
+                        size: item.get("size").and_then(|v| v.as_u64()).unwrap_or(0),
+                        modified_at: item
+                            .get("modified_at")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or_default()
+                            .to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(models)
+}
+
+pub async fn show_model(client: &Client, base_url: &str, name: &str) -> Result<ModelDetails, String> {
+    let json = json_reply(
+        client
+            .post(format!("{}/api/show", base_url))
+            .json(&serde_json::json!({ "model": name })),
+    )
+    .await?;
+
+    let text = |key: &str| {
+        json.get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let detail = |key: &str| {
+        json.get("details")
+            .and_then(|d| d.get(key))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    // the context length key is prefixed with the architecture, e.g. "llama.context_length"
+    let context_length = json
+        .get("model_info")
+        .and_then(|v| v.as_object())
+        .and_then(|info| {
+            info.iter()
+                .find(|(k, _)| k.ends_with(".context_length"))
+                .and_then(|(_, v)| v.as_u64())
+        });
+
+    Ok(ModelDetails {
+        parameters: text("parameters"),
+        template: text("template"),
+        license: text("license"),
+        modelfile: text("modelfile"),
+        context_length,
+        quantization: detail("quantization_level"),
+        parameter_size: detail("parameter_size"),
+        family: detail("family"),
+    })
+}
+
+pub async fn delete_model(client: &Client, base_url: &str, name: &str) -> Result<(), String> {
+    empty_reply(
+        client
+            .delete(format!("{}/api/delete", base_url))
+            .json(&serde_json::json!({ "model": name })),
+    )
+    .await
+}
+
+// duplicate an installed model under another name (shares its blobs, so it's instant)
+pub async fn copy_model(client: &Client, base_url: &str, source: &str, destination: &str) -> Result<(), String> {
+    empty_reply(
+        client
+            .post(format!("{}/api/copy", base_url))
+            .json(&serde_json::json!({ "source": source, "destination": destination })),
+    )
+    .await
+}
+
+pub async fn list_loaded_models(client: &Client, base_url: &str) -> Result<Vec<LoadedModel>, String> {
+    let json = json_reply(client.get(format!("{}/api/ps", base_url))).await?;
+    let models = json
+        .get("models")
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|item| {
+                    let name = item
+                        .get("model")
+                        .or(item.get("name"))
+                        .and_then(|v| v.as_str())?;
+                    Some(LoadedModel {
+                        name: name.to_string(),
+                        size: item.get("size").and_then(|v| v.as_u64()).unwrap_or(0),
+                        size_vram: item.get("size_vram").and_then(|v| v.as_u64()).unwrap_or(0),
+                        expires_at: item
+                            .get("expires_at")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or_default()
+                            .to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(models)
+}
+
+// ask Ollama to drop a model from memory right away (empty generate with keep_alive = 0)
+pub async fn unload_model(client: &Client, base_url: &str, name: &str) -> Result<(), String> {
+    empty_reply(
+        client
+            .post(format!("{}/api/generate", base_url))
+            .json(&serde_json::json!({ "model": name, "keep_alive": 0 })),
+    )
+    .await
+}
+
+// download a model; `on_line` sees every progress line ({"status", "total", "completed"} or {"error"})
+pub async fn pull_model(
+    client: &Client,
+    base_url: &str,
+    name: &str,
+    on_line: impl FnMut(Value),
+) -> Result<(), String> {
+    let request = serde_json::json!({ "model": name, "stream": true });
+    post_ndjson(client, &format!("{}/api/pull", base_url), &request, on_line).await
+}
+
+// create `name` from a parsed Modelfile (see modelfile::parse_modelfile); newer servers read the
+// structured fields, older ones the raw `modelfile`. `on_line` sees every status line
+pub async fn create_model(
+    client: &Client,
+    base_url: &str,
+    name: &str,
+    fields: serde_json::Map<String, Value>,
+    modelfile: &str,
+    on_line: impl FnMut(Value),
+) -> Result<(), String> {
+    let mut request = fields;
+    request.insert("model".into(), Value::String(name.to_string()));
+    request.insert("modelfile".into(), Value::String(modelfile.to_string()));
+    request.insert("stream".into(), Value::Bool(true));
+    post_ndjson(client, &format!("{}/api/create", base_url), &request, on_line).await
+}
+
+// one vector per input, in order
+pub async fn embed_texts(
+    client: &Client,
+    base_url: &str,
+    model: &str,
+    inputs: &[String],
+) -> Result<Vec<Vec<f32>>, String> {
+    let json = json_reply(
+        client
+            .post(format!("{}/api/embed", base_url))
+            .json(&serde_json::json!({ "model": model, "input": inputs })),
+    )
+    .await?;
+    let embeddings: Vec<Vec<f32>> = json
+        .get("embeddings")
+        .and_then(|v| v.as_array())
+        .map(|list| {
+            list.iter()
+                .map(|e| {
+                    e.as_array()
+                        .map(|xs| xs.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect())
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if embeddings.len() != inputs.len() {
+        return Err(format!(
+            "expected {} embeddings from {}, got {}",
+            inputs.len(),
+            model,
+            embeddings.len()
+        ));
+    }
+    Ok(embeddings)
+}
+
+// send a streamed /api/chat request and hand every raw line to `on_line`, for callers that want
+// more than the text (the compare view reads eval_count and eval_duration from the last one)
+pub async fn stream_chat_lines(
+    client: &Client,
+    base_url: &str,
+    request: &OllamaChatRequest,
+    on_line: impl FnMut(Value),
+) -> Result<(), String> {
+    let response = send_chat(client, base_url, request).await.map_err(|e| e.to_string())?;
+    read_ndjson(response, on_line).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{client, http_response, mock_server};
+    use std::net::TcpListener;
+
+    fn chat_request() -> OllamaChatRequest {
+        OllamaChatRequest {
+            model: "llama3".to_string(),
+            messages: vec![OllamaMessage::new("user", "hi")],
+            stream: true,
+            options: None,
+            tools: None,
+            format: None,
+        }
+    }
+
+    #[test]
+    fn parse_model_names_reads_both_shapes() {
+        let current = serde_json::json!({
+            "models": [{ "model": "llama3:latest" }, { "name": "qwen2" }, { "model": "llama3:latest" }]
+        });
+        assert_eq!(parse_model_names(&current), vec!["llama3:latest", "qwen2"]);
+
+        let older = serde_json::json!(["mistral", { "name": "phi3" }]);
+        assert_eq!(parse_model_names(&older), vec!["mistral", "phi3"]);
+
+        assert!(parse_model_names(&serde_json::json!({})).is_empty());
+    }
+
+    #[test]
+    fn sampling_settings_are_sent_as_options() {
+        let request = OllamaChatRequest {
+            options: Some(crate::conversation::sampling_options(0.2, 0.5, 64)),
+            ..chat_request()
+        };
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["options"], serde_json::json!({ "temperature": 0.2, "top_p": 0.5, "num_predict": 64 }));
+        assert!(body.get("parameters").is_none());
+    }
+
+    #[test]
+    fn retry_delay_doubles_and_is_capped() {
+        assert_eq!(retry_delay(1), Duration::from_secs(1));
+        assert_eq!(retry_delay(2), Duration::from_secs(2));
+        assert_eq!(retry_delay(4), Duration::from_secs(8));
+        assert_eq!(retry_delay(40), Duration::from_secs(32));
+    }
+
+    #[test]
+    fn only_connection_and_server_errors_are_transient() {
+        assert!(ChatError::Connect("refused".into()).is_transient());
+        assert!(ChatError::Status(503, "loading".into()).is_transient());
+        assert!(!ChatError::Status(404, "model not found".into()).is_transient());
+        assert!(!ChatError::NoModel.is_transient());
+        assert!(!ChatError::Stream("eof".into()).is_transient());
+    }
+
+    #[test]
+    fn errors_display_the_server_explanation() {
+        assert_eq!(
+            ChatError::Status(404, "model 'x' not found".into()).to_string(),
+            "Ollama API returned status 404: model 'x' not found"
+        );
+        assert_eq!(
+            ChatError::Status(500, String::new()).to_string(),
+            "Ollama API returned status 500"
+        );
+        let format = ChatError::Format {
+            retries: 2,
+            errors: vec!["missing field a".into(), "b is not a number".into()],
+        };
+        assert_eq!(
+            format.to_string(),
+            "Reply does not match the required JSON format after 2 retries:\n- missing field a\n- b is not a number"
+        );
+        assert_eq!(ChatError::NotStored("disk I/O error".into()).kind(), "not_stored");
+        assert_eq!(ChatError::NotLoaded("disk I/O error".into()).kind(), "not_loaded");
+    }
+
+    #[tokio::test]
+    async fn fetch_model_names_lists_tags() {
+        let (url, request) = mock_server(vec![http_response(
+            "200 OK",
+            r#"{"models":[{"name":"llama3:latest","model":"llama3:latest"},{"name":"qwen2:7b"}]}"#,
+        )]);
+        let names = fetch_model_names(&client(), &url).await.unwrap();
+        assert_eq!(names, vec!["llama3:latest", "qwen2:7b"]);
+        assert!(request.recv().unwrap().starts_with("GET /api/tags "));
+    }
+
+    #[tokio::test]
+    async fn fetch_backend_version_reads_version_or_fails() {
+        let (url, _) = mock_server(vec![http_response("200 OK", r#"{"version":"0.12.6"}"#)]);
+        assert_eq!(fetch_backend_version(&client(), &url).await.unwrap(), "0.12.6");
+
+        let (url, _) = mock_server(vec![http_response("502 Bad Gateway", "")]);
+        assert!(fetch_backend_version(&client(), &url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_chat_posts_the_request() {
+        let (url, request) = mock_server(vec![http_response(
+            "200 OK",
+            r#"{"message":{"role":"assistant","content":"hello"},"done":true}"#,
+        )]);
+        let resp = send_chat(&client(), &url, &chat_request()).await.unwrap();
+        let reply = read_chat_stream(resp, |_| {}).await.unwrap();
+        assert_eq!(reply.message.content, "hello");
+
+        let received = request.recv().unwrap();
+        assert!(received.starts_with("POST /api/chat "));
+        assert!(received.contains(r#""model":"llama3""#));
+        assert!(received.contains(r#""stream":true"#));
+    }
+
+    #[tokio::test]
+    async fn send_chat_turns_statuses_into_typed_errors() {
+        let (url, _) = mock_server(vec![http_response(
+            "503 Service Unavailable",
+            r#"{"error":"model is loading"}"#,
+        )]);
+        let err = send_chat(&client(), &url, &chat_request()).await.unwrap_err();
+        assert_eq!(err, ChatError::Status(503, "model is loading".into()));
+        assert!(err.is_transient());
+
+        let (url, _) = mock_server(vec![http_response("400 Bad Request", "invalid options")]);
+        let err = send_chat(&client(), &url, &chat_request()).await.unwrap_err();
+        assert_eq!(err, ChatError::Status(400, "invalid options".into()));
+        assert!(!err.is_transient());
+    }
+
+    #[tokio::test]
+    async fn send_chat_reports_refused_connections() {
+        // a port that was free a moment ago and has nothing listening now
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let url = format!("http://127.0.0.1:{}", port);
+        let err = send_chat(&client(), &url, &chat_request()).await.unwrap_err();
+        assert!(matches!(err, ChatError::Connect(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn read_chat_stream_joins_chunks_split_mid_line() {
+        let body = concat!(
+            r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#,
+            "\n",
+            r#"{"message":{"role":"assistant","content":"lo"},"done":false}"#,
+            "\n",
+            r#"{"message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"current_time","arguments":{}}}]},"done":false}"#,
+            "\n",
+            r#"{"message":{"role":"assistant","content":""},"done":true}"#,
+        );
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        );
+        let (first, second) = body.split_at(30);
+        let (url, _) = mock_server(vec![head + first, second.to_string()]);
+
+        let resp = client().get(&url).send().await.unwrap();
+        let mut seen = Vec::new();
+        let reply = read_chat_stream(resp, |text| seen.push(text.to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(seen, vec!["Hel", "Hello"]);
+        assert_eq!(reply.message.content, "Hello");
+        assert!(reply.done);
+        let calls = reply.message.tool_calls.unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function.name, "current_time");
+    }
+
+    #[tokio::test]
+    async fn read_chat_stream_fails_on_error_lines() {
+        let body = concat!(
+            r#"{"message":{"role":"assistant","content":"par"},"done":false}"#,
+            "\n",
+            r#"{"error":"out of memory"}"#,
+            "\n"
+        );
+        let (url, _) = mock_server(vec![http_response("200 OK", body)]);
+        let resp = client().get(&url).send().await.unwrap();
+        let err = read_chat_stream(resp, |_| {}).await.unwrap_err();
+        assert_eq!(err, "out of memory");
+    }
+
+    // a 200 reply with an NDJSON body split into lines
+    fn ndjson_response(lines: &[&str]) -> String {
+        let body = lines.join("\n");
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    #[tokio::test]
+    async fn installed_and_loaded_models_are_listed() {
+        let (url, request) = mock_server(vec![http_response(
+            "200 OK",
+            r#"{"models":[{"name":"llama3:latest","size":4661224676,"modified_at":"2025-01-02T10:00:00Z"},{"size":1}]}"#,
+        )]);
+        let installed = fetch_installed_models(&client(), &url).await.unwrap();
+        assert_eq!(
+            installed,
+            vec![InstalledModel {
+                name: "llama3:latest".into(),
+                size: 4661224676,
+                modified_at: "2025-01-02T10:00:00Z".into(),
+            }]
+        );
+        assert!(request.recv().unwrap().starts_with("GET /api/tags "));
+
+        let (url, request) = mock_server(vec![http_response(
+            "200 OK",
+            r#"{"models":[{"model":"qwen2:7b","size":10,"size_vram":8,"expires_at":"soon"}]}"#,
+        )]);
+        let loaded = list_loaded_models(&client(), &url).await.unwrap();
+        assert_eq!(
+            loaded,
+            vec![LoadedModel { name: "qwen2:7b".into(), size: 10, size_vram: 8, expires_at: "soon".into() }]
+        );
+        assert!(request.recv().unwrap().starts_with("GET /api/ps "));
+    }
+
+    #[tokio::test]
+    async fn show_model_reads_details_and_context_length() {
+        let (url, request) = mock_server(vec![http_response(
+            "200 OK",
+            r#"{"modelfile":"FROM x","parameters":"stop \"<|end|>\"","template":"{{ .Prompt }}","details":{"family":"llama","parameter_size":"8B","quantization_level":"Q4_0"},"model_info":{"llama.context_length":8192}}"#,
+        )]);
+        let details = show_model(&client(), &url, "llama3").await.unwrap();
+        assert_eq!(details.modelfile, "FROM x");
+        assert_eq!(details.context_length, Some(8192));
+        assert_eq!((details.family.as_str(), details.parameter_size.as_str()), ("llama", "8B"));
+        assert_eq!(details.quantization, "Q4_0");
+        let sent = request.recv().unwrap();
+        assert!(sent.starts_with("POST /api/show "));
+        assert!(sent.contains(r#""model":"llama3""#));
+
+        let (url, _) = mock_server(vec![http_response("404 Not Found", r#"{"error":"model 'nope' not found"}"#)]);
+        let err = show_model(&client(), &url, "nope").await.unwrap_err();
+        assert_eq!(err, "model 'nope' not found (404 Not Found)");
+    }
+
+    #[tokio::test]
+    async fn delete_copy_and_unload_send_their_requests() {
+        let (url, request) = mock_server(vec![http_response("200 OK", "")]);
+        delete_model(&client(), &url, "old").await.unwrap();
+        let sent = request.recv().unwrap();
+        assert!(sent.starts_with("DELETE /api/delete "));
+        assert!(sent.contains(r#""model":"old""#));
+
+        let (url, request) = mock_server(vec![http_response("200 OK", "")]);
+        copy_model(&client(), &url, "llama3", "mine").await.unwrap();
+        let sent = request.recv().unwrap();
+        assert!(sent.starts_with("POST /api/copy "));
+        assert!(sent.contains(r#""destination":"mine""#));
+
+        let (url, request) = mock_server(vec![http_response("200 OK", "{}")]);
+        unload_model(&client(), &url, "llama3").await.unwrap();
+        let sent = request.recv().unwrap();
+        assert!(sent.starts_with("POST /api/generate "));
+        assert!(sent.contains(r#""keep_alive":0"#));
+
+        let (url, _) = mock_server(vec![http_response("500 Internal Server Error", r#"{"error":"busy"}"#)]);
+        assert_eq!(
+            delete_model(&client(), &url, "old").await.unwrap_err(),
+            "busy (500 Internal Server Error)"
+        );
+    }
+
+    #[tokio::test]
+    async fn pull_and_create_stream_their_status_lines() {
+        let (url, request) = mock_server(vec![ndjson_response(&[
+            r#"{"status":"pulling manifest"}"#,
+            r#"{"status":"downloading","total":100,"completed":40}"#,
+            r#"{"status":"success"}"#,
+        ])]);
+        let mut statuses = Vec::new();
+        pull_model(&client(), &url, "llama3", |line| statuses.push(line["status"].as_str().unwrap().to_string()))
+            .await
+            .unwrap();
+        assert_eq!(statuses, vec!["pulling manifest", "downloading", "success"]);
+        let sent = request.recv().unwrap();
+        assert!(sent.starts_with("POST /api/pull "));
+        assert!(sent.contains(r#""stream":true"#));
+
+        let (url, request) = mock_server(vec![ndjson_response(&[r#"{"status":"success"}"#])]);
+        let fields = crate::modelfile::parse_modelfile("FROM llama3\nSYSTEM be brief\n").unwrap();
+        let mut lines = 0;
+        create_model(&client(), &url, "brief", fields, "FROM llama3\nSYSTEM be brief\n", |_| lines += 1)
+            .await
+            .unwrap();
+        assert_eq!(lines, 1);
+        let sent = request.recv().unwrap();
+        assert!(sent.starts_with("POST /api/create "));
+        assert!(sent.contains(r#""model":"brief""#));
+        assert!(sent.contains(r#""from":"llama3""#));
+        assert!(sent.contains(r#""modelfile":"FROM llama3\nSYSTEM be brief\n""#));
+
+        let (url, _) = mock_server(vec![http_response("400 Bad Request", r#"{"error":"invalid model name"}"#)]);
+        let err = pull_model(&client(), &url, "bad name", |_| {}).await.unwrap_err();
+        assert_eq!(err, "invalid model name (400 Bad Request)");
+    }
+
+    #[tokio::test]
+    async fn embed_texts_returns_one_vector_per_input() {
+        let (url, request) = mock_server(vec![http_response("200 OK", r#"{"embeddings":[[0.5,1.0],[2.0,-1.0]]}"#)]);
+        let inputs = vec!["a".to_string(), "b".to_string()];
+        let vectors = embed_texts(&client(), &url, "nomic", &inputs).await.unwrap();
+        assert_eq!(vectors, vec![vec![0.5, 1.0], vec![2.0, -1.0]]);
+        let sent = request.recv().unwrap();
+        assert!(sent.starts_with("POST /api/embed "));
+        assert!(sent.contains(r#""input":["a","b"]"#));
+
+        let (url, _) = mock_server(vec![http_response("200 OK", r#"{"embeddings":[[0.5]]}"#)]);
+        let err = embed_texts(&client(), &url, "nomic", &inputs).await.unwrap_err();
+        assert_eq!(err, "expected 2 embeddings from nomic, got 1");
+    }
+
+    #[tokio::test]
+    async fn stream_chat_lines_passes_the_metrics_through() {
+        let (url, request) = mock_server(vec![ndjson_response(&[
+            r#"{"message":{"role":"assistant","content":"Hi"},"done":false}"#,
+            r#"{"message":{"role":"assistant","content":""},"done":true,"eval_count":12,"eval_duration":600000000}"#,
+        ])]);
+        let mut lines = Vec::new();
+        stream_chat_lines(&client(), &url, &chat_request(), |line| lines.push(line))
+            .await
+            .unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["eval_count"], 12);
+        assert!(request.recv().unwrap().starts_with("POST /api/chat "));
+
+        let (url, _) = mock_server(vec![http_response("404 Not Found", r#"{"error":"model not found"}"#)]);
+        let err = stream_chat_lines(&client(), &url, &chat_request(), |_| {}).await.unwrap_err();
+        assert_eq!(err, "Ollama API returned status 404: model not found");
+    }
+}
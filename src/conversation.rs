@@ -0,0 +1,429 @@
+// turning stored chat history and settings into what is sent to /api/chat
+
+use crate::backend::OllamaMessage;
+use crate::store::{ChatMessage, Settings};
+use serde_json::Value;
+
+impl OllamaMessage {
+    // a stored chat message as sent back to Ollama, tool calls and results included
+    pub fn from_chat(msg: &ChatMessage) -> Self {
+        OllamaMessage {
+            role: msg.role.clone(),
+            content: msg.content.clone(),
+            tool_calls: msg
+                .tool_calls
+                .as_ref()
+                .and_then(|t| serde_json::from_str(t).ok()),
+            tool_name: msg.tool_name.clone(),
+        }
+    }
+}
+
+// the messages of a request answering `user_message`: system prompt, the history before it,
+// `context` (e.g. knowledge base excerpts) right before the question, then the question.
+// `user_message` is normally already stored as the last user turn of `history`; anything stored
+// after it (tool rounds of an earlier attempt) follows it again
+pub fn build_chat_messages(
+    system_prompt: &str,
+    history: &[ChatMessage],
+    user_message: &str,
+    context: Option<String>,
+) -> Vec<OllamaMessage> {
+    let mut messages = Vec::new();
+
+    if !system_prompt.is_empty() {
+        messages.push(OllamaMessage::new("system", system_prompt));
+    }
+
+    let split = history
+        .iter()
+        .rposition(|m| m.role == "user")
+        .filter(|&i| history[i].content == user_message)
+        .unwrap_or(history.len());
+    messages.extend(history[..split].iter().map(OllamaMessage::from_chat));
+
+    if let Some(context) = context {
+        messages.push(OllamaMessage::new("system", context));
+    }
+    messages.push(OllamaMessage::new("user", user_message));
+    messages.extend(history.iter().skip(split + 1).map(OllamaMessage::from_chat));
+    messages
+}
+
+// sampling options sent with every request (and remembered alongside the reply)
+pub fn request_options(settings: &Settings) -> Value {
+    sampling_options(settings.temperature, settings.top_p, settings.max_tokens)
+}
+
+// the `options` of an Ollama request; it caps the reply length with `num_predict`
+pub fn sampling_options(temperature: f64, top_p: f64, max_tokens: i32) -> Value {
+    serde_json::json!({
+        "temperature": temperature,
+        "top_p": top_p,
+        "num_predict": max_tokens
+    })
+}
+
+// follow-up asking the model to fix a structured reply that failed validation
+pub fn format_correction_prompt(errors: &[String]) -> String {
+    format!(
+        "Your reply does not match the required JSON format:\n- {}\nReply again with only the corrected JSON.",
+        errors.join("\n- ")
+    )
+}
+
+// the value sent as Ollama's `format`: the plain string "json" or the parsed schema
+pub fn format_request_value(format: &str) -> Option<Value> {
+    if format.trim() == "json" {
+        Some(Value::String("json".to_string()))
+    } else {
+        serde_json::from_str::<Value>(format).ok()
+    }
+}
+
+// check a reply against the chat's format; returns the problems found (empty = conforms)
+pub fn check_structured_reply(reply: &str, format: &str) -> Vec<String> {
+    let value = match serde_json::from_str::<Value>(reply.trim()) {
+        Ok(v) => v,
+        Err(e) => return vec![format!("reply is not valid JSON: {}", e)],
+    };
+    match format_request_value(format) {
+        Some(Value::String(_)) | None => vec![],
+        Some(schema) => {
+            let mut errors = Vec::new();
+            validate_json(&value, &schema, "$", &mut errors);
+            errors
+        }
+    }
+}
+
+// the JSON type of a value, as schemas spell it
+pub fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn json_type_matches(value: &Value, expected: &str) -> bool {
+    let actual = json_type_name(value);
+    actual == expected
+        || (expected == "number" && actual == "integer")
+        // 3.0 is a valid "integer" as far as JSON Schema is concerned
+        || (expected == "integer" && value.as_f64().is_some_and(|f| f.fract() == 0.0))
+}
+
+// validator for the commonly used subset of JSON Schema: type, enum, const, properties,
+// required, additionalProperties, items, min/max (length, items, value), allOf/anyOf/oneOf
+pub fn validate_json(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(list) => list.iter().filter_map(|t| t.as_str()).collect(),
+            _ => vec![],
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| json_type_matches(value, t)) {
+            errors.push(format!(
+                "{}: expected {}, got {}",
+                path,
+                allowed.join(" or "),
+                json_type_name(value)
+            ));
+            return;
+        }
+    }
+
+    if let Some(options) = schema.get("enum").and_then(|v| v.as_array()) {
+        if !options.contains(value) {
+            errors.push(format!(
+                "{}: must be one of {}",
+                path,
+                Value::Array(options.clone())
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            errors.push(format!("{}: must equal {}", path, expected));
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            if let Some(required) = schema.get("required").and_then(|v| v.as_array()) {
+                for key in required.iter().filter_map(|k| k.as_str()) {
+                    if !map.contains_key(key) {
+                        errors.push(format!("{}: missing required property \"{}\"", path, key));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(|v| v.as_object());
+            for (key, child) in map {
+                let child_path = format!("{}.{}", path, key);
+                match properties.and_then(|p| p.get(key)) {
+                    Some(child_schema) => validate_json(child, child_schema, &child_path, errors),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            errors.push(format!("{}: property is not allowed", child_path))
+                        }
+                        Some(extra @ Value::Object(_)) => {
+                            validate_json(child, extra, &child_path, errors)
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(|v| v.as_u64()) {
+                if (items.len() as u64) < min {
+                    errors.push(format!("{}: needs at least {} items", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(|v| v.as_u64()) {
+                if items.len() as u64 > max {
+                    errors.push(format!("{}: allows at most {} items", path, max));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_json(item, item_schema, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::String(text) => {
+            let len = text.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(|v| v.as_u64()) {
+                if len < min {
+                    errors.push(format!("{}: must be at least {} characters", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(|v| v.as_u64()) {
+                if len > max {
+                    errors.push(format!("{}: must be at most {} characters", path, max));
+                }
+            }
+        }
+        Value::Number(n) => {
+            let v = n.as_f64().unwrap_or(0.0);
+            if let Some(min) = schema.get("minimum").and_then(|v| v.as_f64()) {
+                if v < min {
+                    errors.push(format!("{}: must be >= {}", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(|v| v.as_f64()) {
+                if v > max {
+                    errors.push(format!("{}: must be <= {}", path, max));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(all) = schema.get("allOf").and_then(|v| v.as_array()) {
+        for sub in all {
+            validate_json(value, sub, path, errors);
+        }
+    }
+    let matching = |list: &Vec<Value>| {
+        list.iter()
+            .filter(|sub| {
+                let mut sub_errors = Vec::new();
+                validate_json(value, sub, path, &mut sub_errors);
+                sub_errors.is_empty()
+            })
+            .count()
+    };
+    if let Some(any) = schema.get("anyOf").and_then(|v| v.as_array()) {
+        if matching(any) == 0 {
+            errors.push(format!("{}: does not match any allowed shape (anyOf)", path));
+        }
+    }
+    if let Some(one) = schema.get("oneOf").and_then(|v| v.as_array()) {
+        if matching(one) != 1 {
+            errors.push(format!("{}: must match exactly one allowed shape (oneOf)", path));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage;
+
+    fn roles(messages: &[OllamaMessage]) -> Vec<(&str, &str)> {
+        messages
+            .iter()
+            .map(|m| (m.role.as_str(), m.content.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn stored_question_is_not_sent_twice() {
+        let history = vec![
+            ChatMessage::new("user", "first"),
+            ChatMessage::new("assistant", "answer"),
+            ChatMessage::new("user", "second"),
+        ];
+        let messages = build_chat_messages("be brief", &history, "second", None);
+        assert_eq!(
+            roles(&messages),
+            vec![
+                ("system", "be brief"),
+                ("user", "first"),
+                ("assistant", "answer"),
+                ("user", "second"),
+            ]
+        );
+    }
+
+    #[test]
+    fn question_missing_from_history_is_appended() {
+        let history = vec![ChatMessage::new("user", "first"), ChatMessage::new("assistant", "answer")];
+        let messages = build_chat_messages("", &history, "new question", None);
+        assert_eq!(
+            roles(&messages),
+            vec![("user", "first"), ("assistant", "answer"), ("user", "new question")]
+        );
+    }
+
+    #[test]
+    fn context_goes_right_before_the_question() {
+        let history = vec![ChatMessage::new("user", "what is in the docs?")];
+        let messages = build_chat_messages(
+            "sys",
+            &history,
+            "what is in the docs?",
+            Some("Excerpts: ...".to_string()),
+        );
+        assert_eq!(
+            roles(&messages),
+            vec![
+                ("system", "sys"),
+                ("system", "Excerpts: ..."),
+                ("user", "what is in the docs?"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tool_rounds_of_an_earlier_attempt_follow_the_question() {
+        let calls = r#"[{"function":{"name":"calculator","arguments":{"expression":"2+2"}}}]"#;
+        let history = vec![
+            ChatMessage::new("user", "what is 2+2?"),
+            ChatMessage {
+                tool_calls: Some(calls.to_string()),
+                ..ChatMessage::new("assistant", "")
+            },
+            ChatMessage {
+                tool_name: Some("calculator".to_string()),
+                ..ChatMessage::new("tool", "4")
+            },
+        ];
+        let messages = build_chat_messages("", &history, "what is 2+2?", Some("ctx".into()));
+        assert_eq!(
+            roles(&messages),
+            vec![("system", "ctx"), ("user", "what is 2+2?"), ("assistant", ""), ("tool", "4")]
+        );
+        let call = &messages[2].tool_calls.as_ref().unwrap()[0];
+        assert_eq!(call.function.name, "calculator");
+        assert_eq!(call.function.arguments["expression"], "2+2");
+        assert_eq!(messages[3].tool_name.as_deref(), Some("calculator"));
+    }
+
+    #[test]
+    fn malformed_stored_tool_calls_are_dropped() {
+        let msg = ChatMessage {
+            tool_calls: Some("not json".to_string()),
+            ..ChatMessage::new("assistant", "text")
+        };
+        let sent = OllamaMessage::from_chat(&msg);
+        assert!(sent.tool_calls.is_none());
+        assert_eq!(sent.content, "text");
+    }
+
+    #[test]
+    fn options_come_from_settings() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        storage::migrate(&conn).unwrap();
+        let mut settings = storage::load_settings(&conn).unwrap();
+        settings.temperature = 0.25;
+        settings.max_tokens = 64;
+        let options = request_options(&settings);
+        assert_eq!(options["temperature"], 0.25);
+        assert_eq!(options["top_p"], 0.95);
+        assert_eq!(options["num_predict"], 64);
+    }
+
+    #[test]
+    fn correction_prompt_lists_every_error() {
+        let prompt = format_correction_prompt(&["a is required".into(), "b must be a string".into()]);
+        assert!(prompt.contains("- a is required\n- b must be a string"));
+        assert!(prompt.ends_with("Reply again with only the corrected JSON."));
+    }
+
+    #[test]
+    fn plain_json_mode_only_needs_valid_json() {
+        assert_eq!(format_request_value("json"), Some(Value::String("json".into())));
+        assert!(check_structured_reply(" {\"a\": 1} ", "json").is_empty());
+        let errors = check_structured_reply("not json", "json");
+        assert!(errors[0].starts_with("reply is not valid JSON"));
+    }
+
+    #[test]
+    fn schema_errors_name_the_path() {
+        let schema = r#"{
+            "type": "object",
+            "required": ["name", "tags"],
+            "additionalProperties": false,
+            "properties": {
+                "name": { "type": "string", "minLength": 2 },
+                "age": { "type": "integer", "minimum": 0 },
+                "tags": { "type": "array", "maxItems": 1, "items": { "enum": ["a", "b"] } }
+            }
+        }"#;
+        assert!(check_structured_reply(r#"{"name": "Al", "age": 3.0, "tags": ["a"]}"#, schema).is_empty());
+        assert_eq!(
+            check_structured_reply(r#"{"name": "A", "age": -1, "tags": ["c", "a"], "x": 1}"#, schema),
+            vec![
+                "$.age: must be >= 0",
+                "$.name: must be at least 2 characters",
+                "$.tags: allows at most 1 items",
+                "$.tags[0]: must be one of [\"a\",\"b\"]",
+                "$.x: property is not allowed",
+            ]
+        );
+        assert_eq!(
+            check_structured_reply(r#"{"age": "3"}"#, schema),
+            vec![
+                "$: missing required property \"name\"",
+                "$: missing required property \"tags\"",
+                "$.age: expected integer, got string",
+            ]
+        );
+    }
+
+    #[test]
+    fn combinators_check_every_branch() {
+        let schema = serde_json::json!({ "oneOf": [{ "type": "number" }, { "type": "integer" }] });
+        let mut errors = Vec::new();
+        validate_json(&serde_json::json!(1.5), &schema, "$", &mut errors);
+        assert!(errors.is_empty());
+        validate_json(&serde_json::json!(2), &schema, "$", &mut errors);
+        assert_eq!(errors, vec!["$: must match exactly one allowed shape (oneOf)"]);
+
+        let schema = serde_json::json!({ "anyOf": [{ "type": "string" }, { "const": 1 }] });
+        let mut errors = Vec::new();
+        validate_json(&serde_json::json!(true), &schema, "$", &mut errors);
+        assert_eq!(errors, vec!["$: does not match any allowed shape (anyOf)"]);
+    }
+}
@@ -0,0 +1,5283 @@
+// the desktop app: every feature, on the SQLite database next to the executable
+
+use dioxus::prelude::*;
+use reqwest::Client;
+use rusqlite::Connection;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc, LazyLock, Mutex,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot};
+use uuid::Uuid;
+
+use rusty_chat::api::{self, ApiConfig, ApiEvent, ApiServer};
+use rusty_chat::backend::{
+    self, copy_model, delete_model, embed_texts, fetch_backend_version, fetch_installed_models, fetch_model_names,
+    list_loaded_models, pull_model, show_model, stream_chat_lines, unload_model, ChatError, InstalledModel,
+    LoadedModel, ModelDetails, OllamaChatRequest, OllamaFunctionCall, OllamaMessage, OLLAMA_BASE_URL,
+};
+use rusty_chat::conversation::sampling_options;
+use rusty_chat::generation::{generate_reply, GenerationSlots, ReplyContext, ReplyHooks, SlotGuard};
+use rusty_chat::knowledge::{chunk_text, cosine_similarity, KbChunk};
+use rusty_chat::modelfile::{parse_modelfile, seed_modelfile};
+use rusty_chat::prompts::{check_template, fill_template, slash_matches, template_variables, PromptTemplate};
+use rusty_chat::storage::{
+    self, apply_persona, chat_clients, chat_persona, chat_tags, chat_uses_kb, chats_with_model, chats_with_queue,
+    clamp_title, clamp_to_i32, clear_chat_errors, delete_persona, delete_prompt_template, delete_schema,
+    dismiss_chat_error, finish_compare_reply, list_chat_errors, list_comparisons,
+    list_kb_sources, list_personas, list_prompt_templates, list_queued_messages, list_saved_schemas,
+    list_unread_chats, list_used_models, load_chat_format, load_tool_policy, mark_chat_unread,
+    message_embedding_progress, pending_message_embeddings, prune_message_embeddings, queue_message,
+    remove_kb_source, remove_queued_message, save_chat_format, save_persona,
+    save_prompt_template, save_schema, save_tool_policy, set_chat_persona, set_chat_uses_kb, update_queued_message,
+    ChatErrorRecord, ChatMessage, ChatStore, CompareColumn, GenerationMeta, Persona, Settings, StorageError,
+    ToolRun,
+};
+use rusty_chat::tools::{eval_expression, format_number, render_command, tool_definition, truncate_tool_output, ToolPolicy};
+
+use crate::ui::{open_store, or_report, report_error, ErrorBanner, Message, ModelSettings, PendingReply, RenameRow};
+use crate::{FAVICON, MAIN_CSS};
+
+// SQLite file holding chats, messages and settings
+const DB_PATH: &str = "chat.db";
+// JSON file declaring the user's command-line tools
+const USER_TOOLS_PATH: &str = "tools.json";
+
+/* ================= DATABASE ================= */
+
+// database picked on the recovery screen instead of `DB_PATH` ("" = default)
+static DB_FILE: Mutex<String> = Mutex::new(String::new());
+
+pub fn db_path() -> String {
+    let path = DB_FILE.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    if path.is_empty() { DB_PATH.to_string() } else { path }
+}
+
+fn set_db_path(path: &str) {
+    *DB_FILE.lock().unwrap_or_else(|e| e.into_inner()) = path.to_string();
+}
+
+fn try_init_db() -> Result<Connection, StorageError> {
+    storage::open(&db_path())
+}
+
+// backups of the database go to a `backups` folder next to it
+fn backup_dir() -> PathBuf {
+    std::path::Path::new(&db_path())
+        .parent()
+        .unwrap_or(std::path::Path::new(""))
+        .join("backups")
+}
+
+// blocking: run it with `spawn_blocking`
+fn take_backup(keep: i32) -> Result<PathBuf, StorageError> {
+    storage::create_backup(&try_init_db()?, &backup_dir(), keep.max(1) as usize)
+}
+
+// connection for reading in the running app. The startup check guarantees the file opened once;
+// if it stops opening later (deleted, locked for too long, permissions changed) the error goes to
+// the banner and an empty in-memory database stands in so the UI keeps rendering. The stand-in is
+// read-only: writes go through `try_init_db` and report the error instead of storing into a
+// database that vanishes
+fn init_db() -> Connection {
+    match try_init_db() {
+        Ok(conn) => conn,
+        Err(e) => {
+            report_error(e);
+            let conn = Connection::open_in_memory().expect("in-memory SQLite is always available");
+            let _ = storage::migrate(&conn);
+            let _ = conn.pragma_update(None, "query_only", true);
+            conn
+        }
+    }
+}
+
+// move an unusable database (and its journal files) aside as `<name>.broken-<unix time>` so a new
+// one can take its place; the old file is kept for manual rescue
+fn set_aside_database(path: &str) -> std::io::Result<()> {
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    for suffix in ["", "-journal", "-wal", "-shm"] {
+        let file = format!("{path}{suffix}");
+        if std::path::Path::new(&file).exists() {
+            std::fs::rename(&file, format!("{file}.broken-{stamp}"))?;
+        }
+    }
+    Ok(())
+}
+
+/* ================= UNDO DELETE ================= */
+
+// how long "Undo" stays offered after chats are moved to the Trash
+const UNDO_TOAST_SECS: u64 = 10;
+
+// chats just moved to the Trash, restorable from the toast
+#[derive(Clone, PartialEq)]
+struct UndoDelete {
+    label: String,
+    ids: Vec<String>,
+    serial: u32,
+}
+
+static UNDO_DELETE: GlobalSignal<Option<UndoDelete>> = Signal::global(|| None);
+static UNDO_SERIAL: AtomicU32 = AtomicU32::new(0);
+
+// show the undo toast for `ids`; it hides itself unless a newer delete replaced it
+fn offer_undo(label: String, ids: Vec<String>) {
+    let serial = UNDO_SERIAL.fetch_add(1, Ordering::Relaxed) + 1;
+    *UNDO_DELETE.write() = Some(UndoDelete { label, ids, serial });
+    spawn(async move {
+        tokio::time::sleep(Duration::from_secs(UNDO_TOAST_SECS)).await;
+        if UNDO_DELETE.peek().as_ref().map(|u| u.serial) == Some(serial) {
+            *UNDO_DELETE.write() = None;
+        }
+    });
+}
+
+#[component]
+fn UndoToast(chats: Signal<Vec<(String, String)>>) -> Element {
+    let Some(undo) = UNDO_DELETE() else {
+        return rsx! {};
+    };
+    let ids = undo.ids.clone();
+    rsx! {
+        div { class: "undo-toast",
+            span { "🗑 {undo.label}" }
+            button {
+                class: "rename-save",
+                onclick: move |_| {
+                    let conn = match try_init_db() {
+                        Ok(conn) => conn,
+                        Err(e) => return report_error(format!("Could not restore the chats: {}", e)),
+                    };
+                    if let Err(e) = storage::restore_chats(&conn, &ids) {
+                        return report_error(format!("Could not restore the chats: {}", e));
+                    }
+                    *UNDO_DELETE.write() = None;
+                    match open_store().and_then(|s| s.list_chats()) {
+                        Ok(list) => chats.set(list),
+                        Err(e) => report_error(format!("Could not load chats: {}", e)),
+                    }
+                },
+                "Undo"
+            }
+            button {
+                class: "error-banner-dismiss",
+                title: "Dismiss",
+                onclick: move |_| *UNDO_DELETE.write() = None,
+                "✕"
+            }
+        }
+    }
+}
+
+/* ================= TRASH ================= */
+
+#[component]
+fn TrashModal(show_trash: Signal<bool>, chats: Signal<Vec<(String, String)>>, settings: Signal<Settings>) -> Element {
+    // bumped after every change so the list is read again
+    let mut revision = use_signal(|| 0_u32);
+    // "Empty Trash" asks for a second click
+    let mut confirm_empty = use_signal(|| false);
+    let trash = {
+        let _ = revision();
+        storage::list_trash(&init_db()).unwrap_or_else(|e| {
+            report_error(format!("Could not read the Trash: {}", e));
+            vec![]
+        })
+    };
+    let days = settings().trash_days;
+
+    let mut restore = move |ids: Vec<String>| {
+        let conn = match try_init_db() {
+            Ok(conn) => conn,
+            Err(e) => return report_error(format!("Could not restore the chats: {}", e)),
+        };
+        if let Err(e) = storage::restore_chats(&conn, &ids) {
+            return report_error(format!("Could not restore the chats: {}", e));
+        }
+        match open_store().and_then(|s| s.list_chats()) {
+            Ok(list) => chats.set(list),
+            Err(e) => report_error(format!("Could not load chats: {}", e)),
+        }
+        revision += 1;
+    };
+    let all_ids: Vec<String> = trash.iter().map(|t| t.id.clone()).collect();
+
+    rsx! {
+        div { class: "settings-overlay",
+            div { class: "settings-modal trash-modal",
+                h3 { "Trash" }
+                p { class: "dim-text",
+                    if days > 0 {
+                        "Deleted chats are removed for good after {days} days (change this in Settings)."
+                    } else {
+                        "Deleted chats stay here until you empty the Trash (change this in Settings)."
+                    }
+                }
+
+                if trash.is_empty() {
+                    p { class: "dim-text", "The Trash is empty." }
+                }
+                div { class: "trash-list",
+                    for chat in trash.iter().cloned() {
+                        div { key: "{chat.id}", class: "trash-row",
+                            div { class: "trash-info",
+                                span { class: "chat-title", "{chat.title}" }
+                                span { class: "dim-text", "Deleted {chat.deleted_at}" }
+                            }
+                            button {
+                                class: "rename-save",
+                                onclick: {
+                                    let id = chat.id.clone();
+                                    move |_| restore(vec![id.clone()])
+                                },
+                                "Restore"
+                            }
+                            button {
+                                class: "delete-chat-btn big",
+                                title: "Delete this chat and its messages for good",
+                                onclick: {
+                                    let id = chat.id.clone();
+                                    move |_| {
+                                        if let Err(e) = open_store().and_then(|s| s.delete_chat(&id)) {
+                                            report_error(format!("Could not delete the chat: {}", e));
+                                        }
+                                        revision += 1;
+                                    }
+                                },
+                                "Delete forever"
+                            }
+                        }
+                    }
+                }
+
+                div { class: "modal-actions",
+                    button {
+                        disabled: trash.is_empty(),
+                        onclick: move |_| restore(all_ids.clone()),
+                        "Restore all"
+                    }
+                    button {
+                        class: "delete-all",
+                        disabled: trash.is_empty(),
+                        onclick: move |_| {
+                            if !confirm_empty() {
+                                confirm_empty.set(true);
+                                return;
+                            }
+                            confirm_empty.set(false);
+                            if let Err(e) = try_init_db().and_then(|conn| storage::purge_trash(&conn, 0)) {
+                                report_error(format!("Could not empty the Trash: {}", e));
+                            }
+                            revision += 1;
+                        },
+                        if confirm_empty() { "Click again to delete them for good" } else { "Empty Trash" }
+                    }
+                    button { onclick: move |_| show_trash.set(false), "Close" }
+                }
+            }
+        }
+    }
+}
+
+/* ================= BACKUPS ================= */
+
+// how often the automatic backup checks whether one is due
+const BACKUP_CHECK_SECS: u64 = 600;
+
+#[component]
+fn BackupsModal(
+    show_backups: Signal<bool>,
+    chats: Signal<Vec<(String, String)>>,
+    messages: Signal<Vec<ChatMessage>>,
+    current_chat_id: Signal<Option<String>>,
+    settings: Signal<Settings>,
+) -> Element {
+    // bumped after a backup is taken so the list is read again
+    let mut revision = use_signal(|| 0_u32);
+    let mut busy = use_signal(|| false);
+    let mut status = use_signal(|| Option::<String>::None);
+    // backup whose chats are listed for picking, and the chats ticked in it
+    let mut opened = use_signal(|| Option::<PathBuf>::None);
+    let mut picked = use_signal(HashSet::<String>::new);
+    // backup armed for a full restore (asks for a second click)
+    let mut confirm_restore = use_signal(|| Option::<PathBuf>::None);
+
+    let backups = {
+        let _ = revision();
+        storage::list_backups(&backup_dir())
+    };
+    let opened_chats = match opened() {
+        Some(path) => storage::backup_chats(&path).unwrap_or_else(|e| {
+            report_error(format!("Could not read the backup: {}", e));
+            vec![]
+        }),
+        None => vec![],
+    };
+    let keep = settings().backup_keep;
+
+    let backup_now = move |_| {
+        busy.set(true);
+        spawn(async move {
+            match tokio::task::spawn_blocking(move || take_backup(keep)).await {
+                Ok(Ok(path)) => status.set(Some(format!("Backed up to {}", path.display()))),
+                Ok(Err(e)) => report_error(format!("Could not back up the chats: {}", e)),
+                Err(e) => report_error(format!("Could not back up the chats: {}", e)),
+            }
+            busy.set(false);
+            revision += 1;
+        });
+    };
+
+    // the chat list, the open chat and the settings all change with a restore
+    let mut reload = move || {
+        let list = match open_store().and_then(|s| s.list_chats()) {
+            Ok(list) => list,
+            Err(e) => return report_error(format!("Could not load chats: {}", e)),
+        };
+        match current_chat_id() {
+            Some(id) if list.iter().any(|(cid, _)| *cid == id) => {
+                messages.set(or_report(open_store().and_then(|s| s.load_messages(&id)), "load the chat"))
+            }
+            _ => {
+                current_chat_id.set(None);
+                messages.set(vec![]);
+            }
+        }
+        chats.set(list);
+    };
+
+    let mut restore_all = move |path: PathBuf| {
+        if confirm_restore() != Some(path.clone()) {
+            confirm_restore.set(Some(path));
+            return;
+        }
+        confirm_restore.set(None);
+        busy.set(true);
+        spawn(async move {
+            // the current state is backed up first, so the restore itself can be undone
+            // (one extra is kept so the backup being restored isn't rotated away)
+            let result = tokio::task::spawn_blocking(move || {
+                take_backup(keep + 1)?;
+                storage::restore_backup(&mut try_init_db()?, &path)
+            })
+            .await;
+            match result {
+                Ok(Ok(())) => {
+                    status.set(Some("Restored. The chats from before are in a new backup.".to_string()));
+                    match open_store().and_then(|s| s.load_settings()) {
+                        Ok(restored) => settings.set(restored),
+                        Err(e) => report_error(format!("Could not load settings: {}", e)),
+                    }
+                    reload();
+                }
+                Ok(Err(e)) => report_error(format!("Could not restore the backup: {}", e)),
+                Err(e) => report_error(format!("Could not restore the backup: {}", e)),
+            }
+            busy.set(false);
+            revision += 1;
+        });
+    };
+
+    let restore_picked = move |_| {
+        let Some(path) = opened() else { return };
+        let ids: Vec<String> = picked().into_iter().collect();
+        match try_init_db().and_then(|conn| storage::restore_backup_chats(&conn, &path, &ids)) {
+            Ok(n) => status.set(Some(format!("Restored {} chats", n))),
+            Err(e) => return report_error(format!("Could not restore the chats: {}", e)),
+        }
+        picked.write().clear();
+        reload();
+    };
+
+    rsx! {
+        div { class: "settings-overlay",
+            div { class: "settings-modal backups-modal",
+                h3 { "Backups" }
+                p { class: "dim-text",
+                    if settings().backup_hours > 0 {
+                        "A backup is taken every {settings().backup_hours} hours and the newest {keep} are kept in {backup_dir().display()} (change this in Settings)."
+                    } else {
+                        "Automatic backups are off (turn them on in Settings). Backups go to {backup_dir().display()}."
+                    }
+                }
+                if let Some(text) = status() {
+                    p { class: "dim-text", "{text}" }
+                }
+
+                if backups.is_empty() {
+                    p { class: "dim-text", "No backups yet." }
+                }
+                div { class: "trash-list",
+                    for backup in backups.iter().cloned() {
+                        div { key: "{backup.path.display()}", class: "trash-row",
+                            div { class: "trash-info",
+                                span { class: "chat-title", "{backup.taken_at}" }
+                                span { class: "dim-text", "{backup.chats} chats, {backup.messages} messages" }
+                            }
+                            button {
+                                class: "rename-btn",
+                                disabled: busy(),
+                                onclick: {
+                                    let path = backup.path.clone();
+                                    move |_| {
+                                        picked.write().clear();
+                                        opened.set(if opened() == Some(path.clone()) { None } else { Some(path.clone()) });
+                                    }
+                                },
+                                if opened() == Some(backup.path.clone()) { "Hide chats" } else { "Choose chats…" }
+                            }
+                            button {
+                                class: "delete-chat-btn big",
+                                disabled: busy(),
+                                title: "Replace all chats and settings with this backup",
+                                onclick: {
+                                    let path = backup.path.clone();
+                                    move |_| restore_all(path.clone())
+                                },
+                                if confirm_restore() == Some(backup.path.clone()) { "Click again to replace everything" } else { "Restore all" }
+                            }
+                        }
+                        if opened() == Some(backup.path.clone()) {
+                            div { class: "backup-chats",
+                                for chat in opened_chats.iter().cloned() {
+                                    label { key: "{chat.id}", class: "row",
+                                        input {
+                                            r#type: "checkbox",
+                                            checked: picked().contains(&chat.id),
+                                            onchange: {
+                                                let id = chat.id.clone();
+                                                move |e: FormEvent| {
+                                                    if e.checked() {
+                                                        picked.write().insert(id.clone());
+                                                    } else {
+                                                        picked.write().remove(&id);
+                                                    }
+                                                }
+                                            },
+                                        }
+                                        span { "{chat.title}" }
+                                        span { class: "dim-text", "({chat.messages} messages)" }
+                                    }
+                                }
+                                button {
+                                    class: "rename-save",
+                                    disabled: picked().is_empty() || busy(),
+                                    title: "Put the ticked chats back as they are in this backup; other chats are left alone",
+                                    onclick: restore_picked,
+                                    "Restore selected ({picked().len()})"
+                                }
+                            }
+                        }
+                    }
+                }
+
+                div { class: "modal-actions",
+                    button { disabled: busy(), onclick: backup_now, if busy() { "Working…" } else { "Backup now" } }
+                    button { onclick: move |_| show_backups.set(false), "Close" }
+                }
+            }
+        }
+    }
+}
+
+/* ================= DATABASE RECOVERY ================= */
+
+// shown instead of the app when the database can't be opened at startup
+#[component]
+fn DatabaseRecovery(error: String, mut db_error: Signal<Option<String>>) -> Element {
+    let mut other_path = use_signal(|| "".to_string());
+    // offers the newest automatic backup, if there is one
+    let mut backup_path = use_signal(|| {
+        storage::list_backups(&backup_dir())
+            .first()
+            .map(|b| b.path.display().to_string())
+            .unwrap_or_default()
+    });
+    // outcome of the last recovery step that failed before the database was even retried
+    let mut step_error = use_signal(|| Option::<String>::None);
+
+    let mut retry = move || {
+        step_error.set(None);
+        db_error.set(try_init_db().err().map(|e| e.to_string()));
+    };
+
+    rsx! {
+        div { class: "recovery-screen",
+            div { class: "recovery-panel",
+                h2 { "The chat database can't be opened" }
+                p { class: "recovery-error", "{error}" }
+                p { class: "recovery-hint",
+                    "It may be locked by another RustyChat window, damaged, or in a folder you can't write to. Nothing has been changed yet."
+                }
+                if let Some(e) = step_error() {
+                    p { class: "recovery-error", "{e}" }
+                }
+
+                div { class: "recovery-option",
+                    h3 { "Try again" }
+                    p { "If another program was holding the file, close it and retry." }
+                    button { class: "big", onclick: move |_| retry(), "🔄 Try again" }
+                }
+
+                div { class: "recovery-option",
+                    h3 { "Open another file" }
+                    p { "Use a different database file (it is created if it doesn't exist)." }
+                    div { class: "recovery-row",
+                        input {
+                            class: "input",
+                            placeholder: "path/to/chat.db",
+                            value: "{other_path}",
+                            oninput: move |e| other_path.set(e.value()),
+                        }
+                        button {
+                            class: "big",
+                            disabled: other_path().trim().is_empty(),
+                            onclick: move |_| {
+                                set_db_path(other_path().trim());
+                                retry();
+                            },
+                            "📂 Open"
+                        }
+                    }
+                }
+
+                div { class: "recovery-option",
+                    h3 { "Start fresh" }
+                    p { "Move the current file aside (renamed to *.broken-<time>) and start with an empty database." }
+                    button {
+                        class: "big",
+                        onclick: move |_| match set_aside_database(&db_path()) {
+                            Ok(()) => retry(),
+                            Err(e) => step_error.set(Some(format!("Could not move the old database aside: {}", e))),
+                        },
+                        "✨ Start fresh"
+                    }
+                }
+
+                div { class: "recovery-option",
+                    h3 { "Restore a backup" }
+                    p { "Move the current file aside and replace it with a copy of a backup." }
+                    div { class: "recovery-row",
+                        input {
+                            class: "input",
+                            placeholder: "path/to/backup.db",
+                            value: "{backup_path}",
+                            oninput: move |e| backup_path.set(e.value()),
+                        }
+                        button {
+                            class: "big",
+                            disabled: backup_path().trim().is_empty(),
+                            onclick: move |_| {
+                                let backup = backup_path().trim().to_string();
+                                if !std::path::Path::new(&backup).is_file() {
+                                    step_error.set(Some(format!("No backup found at {}", backup)));
+                                    return;
+                                }
+                                let path = db_path();
+                                let restored = set_aside_database(&path)
+                                    .and_then(|_| std::fs::copy(&backup, &path).map(|_| ()));
+                                match restored {
+                                    Ok(()) => retry(),
+                                    Err(e) => step_error.set(Some(format!("Could not restore the backup: {}", e))),
+                                }
+                            },
+                            "♻️ Restore"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/* ================= OLLAMA MODEL MANAGEMENT ================= */
+
+// progress of one streamed /api/pull
+#[derive(Clone, Debug, PartialEq)]
+struct PullProgress {
+    model: String,
+    status: String,
+    completed: u64,
+    total: u64,
+    finished: bool,
+    error: Option<String>,
+}
+
+// human-readable byte size (e.g. "4.1 GB")
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/* ================= BACKEND HEALTH ================= */
+
+// how often the endpoint is checked while it's up, and while it's down (to reconnect quickly)
+const HEALTH_INTERVAL_SECS: u64 = 10;
+const HEALTH_RETRY_SECS: u64 = 3;
+
+// reachability of the configured Ollama endpoint
+#[derive(Clone, Debug, PartialEq)]
+enum BackendStatus {
+    Checking,
+    Online { version: String },
+    Offline { error: String },
+}
+
+impl BackendStatus {
+    fn is_offline(&self) -> bool {
+        matches!(self, BackendStatus::Offline { .. })
+    }
+
+    fn label(&self) -> String {
+        match self {
+            BackendStatus::Checking => "Connecting to Ollama...".to_string(),
+            BackendStatus::Online { version } => format!("Ollama {}", version),
+            BackendStatus::Offline { .. } => "Ollama unreachable".to_string(),
+        }
+    }
+
+    fn dot_class(&self) -> &'static str {
+        match self {
+            BackendStatus::Checking => "status-dot checking",
+            BackendStatus::Online { .. } => "status-dot online",
+            BackendStatus::Offline { .. } => "status-dot offline",
+        }
+    }
+}
+
+/* ================= SETTINGS MODAL (moved above App to ensure it's in scope) ================= */
+
+#[component]
+fn SettingsModal(
+    settings: Signal<Settings>,
+    show_settings: Signal<bool>,
+    chats: Signal<Vec<(String, String)>>,
+    messages: Signal<Vec<ChatMessage>>,
+    current_chat_id: Signal<Option<String>>,
+    models_revision: Signal<u32>,
+    backend_status: Signal<BackendStatus>,
+    api_status: Signal<String>,
+) -> Element {
+    // local editable copies using signals
+    let local_model = use_signal(|| settings().model.clone());
+    let local_system = use_signal(|| settings().system_prompt.clone());
+    let local_temp = use_signal(|| settings().temperature);
+    let local_top_p = use_signal(|| settings().top_p);
+    let local_max_tokens = use_signal(|| settings().max_tokens);
+    let mut local_zoom = use_signal(|| settings().zoom);
+    let local_width = use_signal(|| settings().window_width);
+    let local_height = use_signal(|| settings().window_height);
+    let mut local_tools_enabled = use_signal(|| settings().tools_enabled);
+    let mut local_tool_folder = use_signal(|| settings().tool_folder.clone());
+    let mut local_embed_model = use_signal(|| settings().embed_model.clone());
+    let mut local_kb_top_k = use_signal(|| settings().kb_top_k);
+    let mut local_embed_messages = use_signal(|| settings().embed_messages);
+    let mut local_max_generations = use_signal(|| settings().max_generations);
+    let mut local_notify_background = use_signal(|| settings().notify_background);
+    let mut local_api_enabled = use_signal(|| settings().api_enabled);
+    let mut local_api_port = use_signal(|| settings().api_port);
+    let mut local_api_token = use_signal(|| settings().api_token.clone());
+    let mut local_trash_days = use_signal(|| settings().trash_days);
+    let mut local_backup_hours = use_signal(|| settings().backup_hours);
+    let mut local_backup_keep = use_signal(|| settings().backup_keep);
+    // "Delete All History" asks for a second click
+    let mut confirm_delete_all = use_signal(|| false);
+    // tools declared in tools.json and the approval policy chosen for each
+    let user_tools = use_signal(load_user_tools);
+    let mut local_policies = use_signal(|| {
+        let conn = init_db();
+        user_tools()
+            .unwrap_or_default()
+            .iter()
+            .map(|t| (t.name.clone(), load_tool_policy(&conn, &t.name)))
+            .collect::<Vec<(String, ToolPolicy)>>()
+    });
+
+    // list of available models from Ollama
+    let available_models = use_signal(Vec::<String>::new);
+    // why the list couldn't be loaded, if it couldn't
+    let mut models_error = use_signal(|| Option::<String>::None);
+
+    // fetch available models when modal mounts
+    {
+        let mut models_sig = available_models;
+        use_effect(move || {
+            // re-fetch whenever models are pulled, created or deleted elsewhere (or Ollama comes back)
+            let _ = models_revision();
+            spawn(async move {
+                let client = Client::new();
+                match fetch_model_names(&client, OLLAMA_BASE_URL).await {
+                    Ok(names) => {
+                        models_sig.set(names);
+                        models_error.set(None);
+                    }
+                    Err(e) => models_error.set(Some(e)),
+                }
+            });
+
+            // no cleanup required
+        });
+    }
+
+    // When the settings modal opens, ensure local edit fields reflect the persisted settings.
+    {
+        let show_settings_sig = show_settings;
+        let settings_sig = settings;
+        let mut local_model_sig = local_model;
+        let mut local_system_sig = local_system;
+        let mut local_temp_sig = local_temp;
+        let mut local_top_p_sig = local_top_p;
+        let mut local_max_tokens_sig = local_max_tokens;
+        let mut local_zoom_sig = local_zoom;
+        let mut local_width_sig = local_width;
+        let mut local_height_sig = local_height;
+        let mut local_tools_enabled_sig = local_tools_enabled;
+        let mut local_tool_folder_sig = local_tool_folder;
+        let mut local_embed_model_sig = local_embed_model;
+        let mut local_kb_top_k_sig = local_kb_top_k;
+        let mut local_embed_messages_sig = local_embed_messages;
+        let mut local_max_generations_sig = local_max_generations;
+        let mut local_notify_background_sig = local_notify_background;
+        let mut local_api_enabled_sig = local_api_enabled;
+        let mut local_api_port_sig = local_api_port;
+        let mut local_api_token_sig = local_api_token;
+        use_effect(move || {
+            if show_settings_sig() {
+                let s = settings_sig();
+                local_model_sig.set(s.model.clone());
+                local_system_sig.set(s.system_prompt.clone());
+                local_temp_sig.set(s.temperature);
+                local_top_p_sig.set(s.top_p);
+                local_max_tokens_sig.set(s.max_tokens);
+                local_zoom_sig.set(s.zoom);
+                local_width_sig.set(s.window_width);
+                local_height_sig.set(s.window_height);
+                local_tools_enabled_sig.set(s.tools_enabled);
+                local_tool_folder_sig.set(s.tool_folder.clone());
+                local_embed_model_sig.set(s.embed_model.clone());
+                local_kb_top_k_sig.set(s.kb_top_k);
+                local_embed_messages_sig.set(s.embed_messages);
+                local_max_generations_sig.set(s.max_generations);
+                local_notify_background_sig.set(s.notify_background);
+                local_api_enabled_sig.set(s.api_enabled);
+                local_api_port_sig.set(s.api_port);
+                local_api_token_sig.set(s.api_token.clone());
+            }
+        });
+    }
+
+    // why the model list is empty, if it is
+    let models_note = models_error().map(|err| {
+        if backend_status().is_offline() {
+            format!("Ollama isn't reachable at {OLLAMA_BASE_URL}, so the model list is empty. It reloads by itself once Ollama is back.")
+        } else {
+            format!("Could not load the model list: {err}")
+        }
+    });
+
+    let apply = {
+        to_owned![
+            local_model,
+            local_system,
+            local_temp,
+            local_top_p,
+            local_max_tokens,
+            local_zoom,
+            local_width,
+            local_height,
+            local_tools_enabled,
+            local_tool_folder,
+            local_embed_model,
+            local_kb_top_k,
+            local_embed_messages,
+            local_max_generations,
+            local_notify_background,
+            local_api_enabled,
+            local_api_port,
+            local_api_token,
+            local_trash_days,
+            local_backup_hours,
+            local_backup_keep,
+            local_policies,
+            settings,
+            show_settings
+        ];
+        move |_| {
+            // ensure integer fields are clamped to i32
+            let mut model_str = local_model().clone();
+            // sanitize model string and trim
+            model_str = model_str.trim().to_string();
+
+            let new_settings = Settings {
+                model: model_str,
+                system_prompt: local_system().clone(),
+                temperature: local_temp(),
+                top_p: local_top_p(),
+                max_tokens: clamp_to_i32(local_max_tokens().into()),
+                zoom: clamp_to_i32(local_zoom().into()),
+                // always start maximized; user cannot change this setting in the UI
+                maximized: true,
+                window_width: clamp_to_i32(local_width().into()),
+                window_height: clamp_to_i32(local_height().into()),
+                tools_enabled: local_tools_enabled(),
+                tool_folder: local_tool_folder().trim().to_string(),
+                embed_model: local_embed_model().trim().to_string(),
+                kb_top_k: local_kb_top_k().clamp(1, 20),
+                embed_messages: local_embed_messages(),
+                max_generations: local_max_generations().clamp(0, 16),
+                notify_background: local_notify_background(),
+                api_enabled: local_api_enabled(),
+                api_port: local_api_port().clamp(1, 65535),
+                // the server never runs without a token
+                api_token: match local_api_token().trim() {
+                    "" if local_api_enabled() => api::generate_token(),
+                    token => token.to_string(),
+                },
+                trash_days: local_trash_days().clamp(0, 3650),
+                backup_hours: local_backup_hours().clamp(0, 720),
+                backup_keep: local_backup_keep().clamp(1, 100),
+            };
+            // still applied for this session when it can't be stored
+            let saved = open_store().and_then(|s| s.save_settings(&new_settings)).and_then(|()| {
+                let conn = try_init_db()?;
+                local_policies().iter().try_for_each(|(name, policy)| save_tool_policy(&conn, name, *policy))
+            });
+            if let Err(e) = saved {
+                report_error(format!("Settings were not saved: {}", e));
+            }
+            settings.set(new_settings);
+            show_settings.set(false);
+        }
+    };
+
+    let delete_all = {
+        to_owned![chats, messages, current_chat_id, show_settings];
+        move |_| {
+            if !confirm_delete_all() {
+                confirm_delete_all.set(true);
+                return;
+            }
+            // everything goes to the Trash, so it can still be undone
+            let ids: Vec<String> = chats().into_iter().map(|(id, _)| id).collect();
+            if let Err(e) = try_init_db().and_then(|conn| storage::trash_chats(&conn, &ids)) {
+                report_error(format!("Could not delete chats: {}", e));
+                return;
+            }
+            offer_undo(format!("{} chats moved to Trash", ids.len()), ids);
+
+            chats.set(vec![]);
+            messages.set(vec![]);
+            current_chat_id.set(None);
+            show_settings.set(false);
+        }
+    };
+
+    let cancel = {
+        to_owned![show_settings];
+        move |_| {
+            show_settings.set(false);
+        }
+    };
+
+    rsx! {
+        div { class: "settings-overlay",
+            div { class: "settings-modal",
+                h3 { "Settings" }
+
+                ModelSettings {
+                    model: local_model,
+                    models: available_models(),
+                    models_note,
+                    system_prompt: local_system,
+                    temperature: local_temp,
+                    top_p: local_top_p,
+                    max_tokens: local_max_tokens
+                }
+
+                label { "Zoom (%) — applied globally (50 - 200)" }
+                div { class: "zoom-row",
+                    button { onclick: move |_| { local_zoom.set((local_zoom() - 10).max(50)); }, "−" }
+                    span { "{local_zoom}%" }
+                    button { onclick: move |_| { local_zoom.set((local_zoom() + 10).min(200)); }, "+" }
+                }
+
+                label { "Empty deleted chats from the Trash after (days, 0 = never)" }
+                input {
+                    class: "input",
+                    r#type: "number",
+                    step: "1",
+                    min: "0",
+                    max: "3650",
+                    value: "{local_trash_days}",
+                    oninput: move |e| local_trash_days.set(e.value().parse::<i32>().unwrap_or(30).clamp(0, 3650)),
+                }
+
+                label { "Back up the chats every (hours, 0 = never)" }
+                input {
+                    class: "input",
+                    r#type: "number",
+                    step: "1",
+                    min: "0",
+                    max: "720",
+                    value: "{local_backup_hours}",
+                    oninput: move |e| local_backup_hours.set(e.value().parse::<i32>().unwrap_or(24).clamp(0, 720)),
+                }
+
+                label { "Backups to keep (1 - 100)" }
+                input {
+                    class: "input",
+                    r#type: "number",
+                    step: "1",
+                    min: "1",
+                    max: "100",
+                    value: "{local_backup_keep}",
+                    oninput: move |e| local_backup_keep.set(e.value().parse::<i32>().unwrap_or(10).clamp(1, 100)),
+                }
+
+                label { "Chats generating at the same time (0 = no limit)" }
+                input {
+                    class: "input",
+                    r#type: "number",
+                    step: "1",
+                    min: "0",
+                    max: "16",
+                    value: "{local_max_generations}",
+                    oninput: move |e| local_max_generations.set(e.value().parse::<i32>().unwrap_or(0).clamp(0, 16)),
+                }
+
+                label { "Notifications" }
+                div { class: "row",
+                    input {
+                        r#type: "checkbox",
+                        checked: local_notify_background(),
+                        onchange: move |e| local_notify_background.set(e.checked()),
+                    }
+                    span { class: "dim-text", "Show a desktop notification when a reply finishes in a chat that isn't open" }
+                }
+
+                label { "Local API" }
+                div { class: "row",
+                    input {
+                        r#type: "checkbox",
+                        checked: local_api_enabled(),
+                        onchange: move |e| local_api_enabled.set(e.checked()),
+                    }
+                    span { class: "dim-text", "Let editor plugins and scripts on this computer use your chats over HTTP (127.0.0.1 only), including an OpenAI-compatible /v1/chat/completions endpoint that records their conversations" }
+                }
+                if local_api_enabled() {
+                    label { "API port" }
+                    input {
+                        class: "input",
+                        r#type: "number",
+                        step: "1",
+                        min: "1",
+                        max: "65535",
+                        value: "{local_api_port}",
+                        oninput: move |e| local_api_port.set(e.value().parse::<i32>().unwrap_or(storage::DEFAULT_API_PORT).clamp(1, 65535)),
+                    }
+                    label { "API token (send as \"Authorization: Bearer <token>\")" }
+                    div { class: "row",
+                        input {
+                            class: "input",
+                            readonly: true,
+                            value: "{local_api_token}",
+                            placeholder: "Created when you apply",
+                        }
+                        button { onclick: move |_| local_api_token.set(api::generate_token()), "New token" }
+                    }
+                    if !api_status().is_empty() {
+                        p { class: "dim-text", "{api_status}" }
+                    }
+                }
+
+                label { "Tools" }
+                div { class: "row",
+                    input {
+                        r#type: "checkbox",
+                        checked: local_tools_enabled(),
+                        onchange: move |e| local_tools_enabled.set(e.checked()),
+                    }
+                    span { class: "dim-text", "Let the model call built-in tools (current time, calculator, files, chat history)" }
+                }
+
+                label { "Folder the read_file tool may read from (empty = no file access)" }
+                input {
+                    class: "input",
+                    value: "{local_tool_folder}",
+                    placeholder: "/home/me/notes",
+                    oninput: move |e| local_tool_folder.set(e.value()),
+                }
+
+                label { "Embedding model (knowledge base and semantic search)" }
+                select {
+                    class: "input",
+                    value: "{local_embed_model}",
+                    onchange: move |e| local_embed_model.set(e.value()),
+                    option { selected: local_embed_model().is_empty(), value: "", "- Select an embedding model -" }
+                    {available_models().iter().map(|m| rsx!( option { selected: m == &local_embed_model(), value: "{m}", "{m}" } ))}
+                    if !local_embed_model().is_empty() && !available_models().contains(&local_embed_model()) {
+                        option { selected: true, value: "{local_embed_model}", "{local_embed_model}" }
+                    }
+                }
+
+                label { "Knowledge base excerpts per reply (1 - 20)" }
+                input {
+                    class: "input",
+                    r#type: "number",
+                    step: "1",
+                    min: "1",
+                    max: "20",
+                    value: "{local_kb_top_k}",
+                    oninput: move |e| local_kb_top_k.set(e.value().parse::<i32>().unwrap_or(4).clamp(1, 20)),
+                }
+
+                label { "Semantic search" }
+                div { class: "row",
+                    input {
+                        r#type: "checkbox",
+                        checked: local_embed_messages(),
+                        onchange: move |e| local_embed_messages.set(e.checked()),
+                    }
+                    span { class: "dim-text", "Embed chat messages in the background so past conversations can be searched by meaning" }
+                }
+
+                label { "Command tools (declared in {USER_TOOLS_PATH})" }
+                {match user_tools() {
+                    Err(e) => rsx! { p { class: "dim-text warning-text", "Could not load tools: {e}" } },
+                    Ok(tools) if tools.is_empty() => rsx! { p { class: "dim-text", "No command tools defined." } },
+                    Ok(_) => rsx! {
+                        {local_policies().iter().map(|(name, policy)| {
+                            let name_for_change = name.clone();
+                            let current = policy.as_str();
+                            rsx! {
+                                div { class: "row tool-policy-row",
+                                    span { class: "tool-policy-name", "{name}" }
+                                    select {
+                                        class: "input",
+                                        value: "{current}",
+                                        onchange: move |e| {
+                                            let chosen = ToolPolicy::parse(&e.value());
+                                            local_policies.with_mut(|list| {
+                                                if let Some(entry) = list.iter_mut().find(|(n, _)| n == &name_for_change) {
+                                                    entry.1 = chosen;
+                                                }
+                                            });
+                                        },
+                                        option { selected: current == "allow", value: "allow", "Always allow" }
+                                        option { selected: current == "ask", value: "ask", "Ask every time" }
+                                        option { selected: current == "deny", value: "deny", "Deny" }
+                                    }
+                                }
+                            }
+                        })}
+                    },
+                }}
+
+                /* Window behavior removed from UI — always starts maximized */
+
+                div { class: "modal-actions",
+                    button { onclick: apply, "Apply" }
+                    button {
+                        onclick: delete_all,
+                        class: "delete-all",
+                        disabled: chats().is_empty(),
+                        if confirm_delete_all() { "Click again to move all {chats().len()} chats to Trash" } else { "Delete All History" }
+                    }
+                    button { onclick: cancel, "Cancel" }
+                }
+            }
+        }
+    }
+}
+
+/* ================= MODELS MODAL ================= */
+
+#[component]
+fn ModelsModal(show_models: Signal<bool>, models_revision: Signal<u32>) -> Element {
+    let http_client = use_signal(Client::new);
+
+    let mut installed = use_signal(Vec::<InstalledModel>::new);
+    let mut loaded = use_signal(Vec::<LoadedModel>::new);
+    let mut pulls = use_signal(Vec::<PullProgress>::new);
+    let mut pull_name = use_signal(|| "".to_string());
+    // model whose /api/show details are expanded
+    let mut selected = use_signal(|| Option::<String>::None);
+    let mut details = use_signal(|| Option::<ModelDetails>::None);
+    // two-step delete: first click arms, second click deletes
+    let mut confirm_delete = use_signal(|| Option::<String>::None);
+    // (model being copied, name typed for the copy)
+    let mut copying = use_signal(|| Option::<(String, String)>::None);
+    let mut panel_error = use_signal(|| Option::<String>::None);
+
+    // Modelfile editor: open while `editor_source` names the model it started from
+    let mut editor_source = use_signal(|| Option::<String>::None);
+    let mut editor_name = use_signal(|| "".to_string());
+    let mut editor_text = use_signal(|| "".to_string());
+    let mut create_log = use_signal(Vec::<String>::new);
+    let mut creating = use_signal(|| false);
+
+    // refresh installed and loaded models on open and after every pull/delete
+    use_effect(move || {
+        let _ = models_revision();
+        spawn(async move {
+            let client = http_client();
+            match fetch_installed_models(&client, OLLAMA_BASE_URL).await {
+                Ok(models) => installed.set(models),
+                Err(e) => panel_error.set(Some(format!("Could not list models: {}", e))),
+            }
+            if let Ok(models) = list_loaded_models(&client, OLLAMA_BASE_URL).await {
+                loaded.set(models);
+            }
+        });
+    });
+
+    let start_pull = move |_| {
+        let name = pull_name().trim().to_string();
+        if name.is_empty() || pulls().iter().any(|p| p.model == name && !p.finished) {
+            return;
+        }
+        pulls.retain(|p| p.model != name);
+        pulls.push(PullProgress {
+            model: name.clone(),
+            status: "starting".to_string(),
+            completed: 0,
+            total: 0,
+            finished: false,
+            error: None,
+        });
+        pull_name.set("".to_string());
+
+        spawn(async move {
+            let result = pull_model(&http_client(), OLLAMA_BASE_URL, &name, |line| {
+                if let Some(p) = pulls.write().iter_mut().find(|p| p.model == name) {
+                    if let Some(err) = line.get("error").and_then(|v| v.as_str()) {
+                        p.error = Some(err.to_string());
+                    }
+                    if let Some(status) = line.get("status").and_then(|v| v.as_str()) {
+                        p.status = status.to_string();
+                    }
+                    // only layer downloads report sizes; other steps reset the bar
+                    p.total = line.get("total").and_then(|v| v.as_u64()).unwrap_or(0);
+                    p.completed = line.get("completed").and_then(|v| v.as_u64()).unwrap_or(0);
+                }
+            })
+            .await;
+
+            if let Some(p) = pulls.write().iter_mut().find(|p| p.model == name) {
+                p.finished = true;
+                if let Err(e) = result {
+                    p.error = Some(e);
+                }
+            }
+            models_revision += 1;
+        });
+    };
+
+    let create_model = move |_| {
+        let name = editor_name().trim().to_string();
+        let modelfile = editor_text();
+        if name.is_empty() || creating() {
+            return;
+        }
+        let fields = match parse_modelfile(&modelfile) {
+            Ok(fields) => fields,
+            Err(e) => {
+                create_log.set(vec![format!("error: {}", e)]);
+                return;
+            }
+        };
+
+        creating.set(true);
+        create_log.set(vec![format!("creating {}", name)]);
+        spawn(async move {
+            let mut failed = false;
+            let result = backend::create_model(&http_client(), OLLAMA_BASE_URL, &name, fields, &modelfile, |line| {
+                if let Some(err) = line.get("error").and_then(|v| v.as_str()) {
+                    failed = true;
+                    create_log.push(format!("error: {}", err));
+                } else if let Some(status) = line.get("status").and_then(|v| v.as_str()) {
+                    create_log.push(status.to_string());
+                }
+            })
+            .await;
+
+            if let Err(e) = result {
+                failed = true;
+                create_log.push(format!("error: {}", e));
+            }
+            creating.set(false);
+            if !failed {
+                // close the editor and let every model list pick up the new model
+                editor_source.set(None);
+                models_revision += 1;
+            }
+        });
+    };
+
+    rsx! {
+        div { class: "settings-overlay",
+            div { class: "settings-modal models-modal",
+                h3 { "Models" }
+
+                if let Some(err) = panel_error() {
+                    p { class: "dim-text warning-text", "{err}" }
+                }
+
+                label { "Pull a model (e.g. llama3.2 or qwen2.5:7b)" }
+                div { class: "row",
+                    input {
+                        class: "input",
+                        value: "{pull_name}",
+                        placeholder: "model name",
+                        oninput: move |e| pull_name.set(e.value()),
+                    }
+                    button { class: "model-action", disabled: pull_name().trim().is_empty(), onclick: start_pull, "Pull" }
+                }
+
+                {pulls().iter().map(|p| {
+                    let pct = if p.total > 0 { p.completed as f64 / p.total as f64 * 100.0 } else if p.finished { 100.0 } else { 0.0 };
+                    let state = match (&p.error, p.finished) {
+                        (Some(e), _) => format!("failed: {}", e),
+                        (None, true) => "done".to_string(),
+                        (None, false) if p.total > 0 => format!("{} ({} / {})", p.status, format_bytes(p.completed), format_bytes(p.total)),
+                        (None, false) => p.status.clone(),
+                    };
+                    rsx! {
+                        div { class: "pull-row",
+                            div { class: "pull-label", span { "{p.model}" } span { class: "dim-text", "{state}" } }
+                            div { class: "progress-bar",
+                                div { class: "progress-fill", style: "width: {pct:.0}%;" }
+                            }
+                        }
+                    }
+                })}
+
+                if pulls().iter().any(|p| p.finished) {
+                    button {
+                        class: "model-action",
+                        onclick: move |_| pulls.retain(|p| !p.finished),
+                        "Clear finished"
+                    }
+                }
+
+                if let Some(source) = editor_source() {
+                    div { class: "modelfile-editor",
+                        h4 { "New model from {source}" }
+                        label { "Name of the new model" }
+                        input {
+                            class: "input",
+                            value: "{editor_name}",
+                            oninput: move |e| editor_name.set(e.value()),
+                        }
+                        label { "Modelfile (FROM, SYSTEM, TEMPLATE, PARAMETER, MESSAGE, LICENSE)" }
+                        textarea {
+                            class: "textarea modelfile-text",
+                            value: "{editor_text}",
+                            oninput: move |e| editor_text.set(e.value()),
+                        }
+                        div { class: "row",
+                            button {
+                                class: "model-action",
+                                disabled: creating() || editor_name().trim().is_empty(),
+                                onclick: create_model,
+                                if creating() { "Creating..." } else { "Create" }
+                            }
+                            button {
+                                class: "model-action",
+                                disabled: creating(),
+                                onclick: move |_| editor_source.set(None),
+                                "Cancel"
+                            }
+                        }
+                        if !create_log().is_empty() {
+                            pre { class: "model-pre", {create_log().join("\n")} }
+                        }
+                    }
+                }
+
+                h4 { "Installed" }
+                {installed().iter().map(|m| {
+                    let name = m.name.clone();
+                    let name_for_details = m.name.clone();
+                    let name_for_delete = m.name.clone();
+                    let name_for_customize = m.name.clone();
+                    let name_for_copy = m.name.clone();
+                    let copy_name = copying().filter(|(source, _)| *source == m.name).map(|(_, name)| name);
+                    let is_selected = selected().as_ref() == Some(&m.name);
+                    let armed = confirm_delete().as_ref() == Some(&m.name);
+                    let size = format_bytes(m.size);
+                    let modified = m.modified_at.chars().take(10).collect::<String>();
+                    rsx! {
+                        div { class: "model-row",
+                            div { class: "model-row-head",
+                                span { class: "model-name", "{name}" }
+                                span { class: "dim-text", "{size} · {modified}" }
+                                div { class: "chat-actions",
+                                    button {
+                                        class: "rename-btn",
+                                        onclick: move |_| {
+                                            if selected().as_ref() == Some(&name_for_details) {
+                                                selected.set(None);
+                                                details.set(None);
+                                                return;
+                                            }
+                                            selected.set(Some(name_for_details.clone()));
+                                            details.set(None);
+                                            let name = name_for_details.clone();
+                                            spawn(async move {
+                                                match show_model(&http_client(), OLLAMA_BASE_URL, &name).await {
+                                                    Ok(d) => {
+                                                        if selected().as_ref() == Some(&name) {
+                                                            details.set(Some(d));
+                                                        }
+                                                    }
+                                                    Err(e) => panel_error.set(Some(format!("Could not load details for {}: {}", name, e))),
+                                                }
+                                            });
+                                        },
+                                        if is_selected { "Hide" } else { "Details" }
+                                    }
+                                    button {
+                                        class: "rename-btn",
+                                        onclick: move |_| {
+                                            let source = name_for_customize.clone();
+                                            create_log.set(vec![]);
+                                            editor_name.set(format!("{}-custom", source.split(':').next().unwrap_or(&source)));
+                                            editor_text.set(format!("FROM {}\n", source));
+                                            editor_source.set(Some(source.clone()));
+                                            spawn(async move {
+                                                match show_model(&http_client(), OLLAMA_BASE_URL, &source).await {
+                                                    Ok(d) => {
+                                                        if editor_source().as_ref() == Some(&source) {
+                                                            editor_text.set(seed_modelfile(&source, &d.modelfile));
+                                                        }
+                                                    }
+                                                    Err(e) => create_log.set(vec![format!("error: could not load Modelfile: {}", e)]),
+                                                }
+                                            });
+                                        },
+                                        "Customize"
+                                    }
+                                    button {
+                                        class: "rename-btn",
+                                        title: "Duplicate this model under another name",
+                                        onclick: move |_| {
+                                            let source = name_for_copy.clone();
+                                            let (base, tag) = source.split_once(':').unwrap_or((&source, "latest"));
+                                            let suggested = format!("{}-copy:{}", base, tag);
+                                            copying.set(Some((source, suggested)));
+                                        },
+                                        "Copy"
+                                    }
+                                    button {
+                                        class: "delete-chat-btn big",
+                                        onclick: move |_| {
+                                            if confirm_delete().as_ref() != Some(&name_for_delete) {
+                                                confirm_delete.set(Some(name_for_delete.clone()));
+                                                return;
+                                            }
+                                            confirm_delete.set(None);
+                                            let name = name_for_delete.clone();
+                                            spawn(async move {
+                                                match delete_model(&http_client(), OLLAMA_BASE_URL, &name).await {
+                                                    Ok(()) => {
+                                                        if selected().as_ref() == Some(&name) {
+                                                            selected.set(None);
+                                                            details.set(None);
+                                                        }
+                                                        models_revision += 1;
+                                                    }
+                                                    Err(e) => panel_error.set(Some(format!("Could not delete {}: {}", name, e))),
+                                                }
+                                            });
+                                        },
+                                        if armed { "Confirm delete" } else { "Delete" }
+                                    }
+                                }
+                            }
+
+                            if let Some(copy_name) = copy_name {
+                                div { class: "row",
+                                    input {
+                                        class: "input",
+                                        value: "{copy_name}",
+                                        placeholder: "name for the copy",
+                                        oninput: move |e| {
+                                            if let Some((_, name)) = copying.write().as_mut() {
+                                                *name = e.value();
+                                            }
+                                        },
+                                    }
+                                    button {
+                                        class: "rename-save",
+                                        disabled: copy_name.trim().is_empty(),
+                                        onclick: move |_| {
+                                            let Some((source, destination)) = copying() else { return };
+                                            spawn(async move {
+                                                match copy_model(&http_client(), OLLAMA_BASE_URL, &source, destination.trim()).await {
+                                                    Ok(()) => {
+                                                        copying.set(None);
+                                                        models_revision += 1;
+                                                    }
+                                                    Err(e) => panel_error.set(Some(format!("Could not copy {}: {}", source, e))),
+                                                }
+                                            });
+                                        },
+                                        "Copy"
+                                    }
+                                    button { class: "rename-cancel", onclick: move |_| copying.set(None), "Cancel" }
+                                }
+                            }
+
+                            if is_selected {
+                                match details() {
+                                    Some(d) => rsx! {
+                                        div { class: "model-details",
+                                            p { "Family: {d.family} · Parameters: {d.parameter_size} · Quantization: {d.quantization}" }
+                                            p {
+                                                "Context length: "
+                                                {d.context_length.map(|c| c.to_string()).unwrap_or_else(|| "unknown".to_string())}
+                                                " · Size: {size}"
+                                            }
+                                            if !d.parameters.is_empty() {
+                                                label { "Parameters" }
+                                                pre { class: "model-pre", "{d.parameters}" }
+                                            }
+                                            if !d.template.is_empty() {
+                                                label { "Template" }
+                                                pre { class: "model-pre", "{d.template}" }
+                                            }
+                                            if !d.license.is_empty() {
+                                                label { "License" }
+                                                pre { class: "model-pre", "{d.license}" }
+                                            }
+                                        }
+                                    },
+                                    None => rsx! { p { class: "dim-text", "Loading details..." } },
+                                }
+                            }
+                        }
+                    }
+                })}
+
+                if installed().is_empty() {
+                    p { class: "dim-text", "No models installed." }
+                }
+
+                h4 { "Loaded in memory" }
+                {loaded().iter().map(|m| {
+                    let name = m.name.clone();
+                    let name_for_unload = m.name.clone();
+                    let vram = format_bytes(m.size_vram);
+                    let size = format_bytes(m.size);
+                    let expires = m.expires_at.replace('T', " ").chars().take(19).collect::<String>();
+                    rsx! {
+                        div { class: "model-row",
+                            div { class: "model-row-head",
+                                span { class: "model-name", "{name}" }
+                                span { class: "dim-text", "{size} ({vram} VRAM) · until {expires}" }
+                                button {
+                                    class: "rename-btn",
+                                    onclick: move |_| {
+                                        let name = name_for_unload.clone();
+                                        spawn(async move {
+                                            let client = http_client();
+                                            if let Err(e) = unload_model(&client, OLLAMA_BASE_URL, &name).await {
+                                                panel_error.set(Some(format!("Could not unload {}: {}", name, e)));
+                                            }
+                                            if let Ok(models) = list_loaded_models(&client, OLLAMA_BASE_URL).await {
+                                                loaded.set(models);
+                                            }
+                                        });
+                                    },
+                                    "Unload"
+                                }
+                            }
+                        }
+                    }
+                })}
+
+                if loaded().is_empty() {
+                    p { class: "dim-text", "No models loaded." }
+                }
+
+                div { class: "modal-actions",
+                    button { onclick: move |_| models_revision += 1, "Refresh" }
+                    button { onclick: move |_| show_models.set(false), "Close" }
+                }
+            }
+        }
+    }
+}
+
+/* ================= APP ================= */
+
+#[component]
+pub fn App() -> Element {
+    // why the database couldn't be opened at startup (None = ready)
+    let db_error = use_signal(|| try_init_db().err().map(|e| e.to_string()));
+
+    rsx! {
+        document::Link { rel: "icon", href: FAVICON }
+        document::Link { rel: "stylesheet", href: MAIN_CSS }
+
+        if let Some(error) = db_error() {
+            DatabaseRecovery { error, db_error }
+        } else {
+            ChatApp {}
+        }
+    }
+}
+
+#[component]
+fn ChatApp() -> Element {
+    let conn = init_db();
+
+    let chats = use_signal(Vec::<(String, String)>::new);
+    let current_chat_id = use_signal(|| Option::<String>::None);
+    let messages = use_signal(Vec::<ChatMessage>::new);
+
+    // settings and modal visibility
+    let settings = use_signal(|| {
+        open_store().and_then(|s| s.load_settings()).unwrap_or_else(|e| {
+            report_error(format!("Could not load settings, using defaults: {}", e));
+            Settings::default()
+        })
+    });
+    let show_settings = use_signal(|| false);
+    let show_models = use_signal(|| false);
+    let show_kb = use_signal(|| false);
+    let show_prompts = use_signal(|| false);
+    let show_personas = use_signal(|| false);
+    let show_backups = use_signal(|| false);
+    // bumped whenever personas are added, edited or deleted
+    let personas_revision = use_signal(|| 0_u32);
+    // chats with a request in flight, shared by the chat window and the sidebar spinners
+    let active_runs = use_signal(HashMap::<String, ChatRun>::new);
+    // chats with replies that arrived while they weren't open
+    let mut unread_chats = use_signal(|| list_unread_chats(&conn));
+
+    // opening a chat (from anywhere) marks it read
+    use_effect(move || {
+        if let Some(cid) = current_chat_id() {
+            if unread_chats.peek().contains(&cid) {
+                unread_chats.write().remove(&cid);
+                if let Err(e) = try_init_db().and_then(|conn| mark_chat_unread(&conn, &cid, false)) {
+                    report_error(format!("Could not mark the chat as read: {}", e));
+                }
+            }
+        }
+    });
+    // bumped whenever the set of installed models changes so model lists re-fetch
+    let mut models_revision = use_signal(|| 0_u32);
+    // result of the latest /api/version check
+    let mut backend_status = use_signal(|| BackendStatus::Checking);
+
+    // check the endpoint in the background; quicker re-checks while it's down
+    use_future(move || async move {
+        let client = Client::new();
+        loop {
+            let status = match fetch_backend_version(&client, OLLAMA_BASE_URL).await {
+                Ok(version) => BackendStatus::Online { version },
+                Err(error) => BackendStatus::Offline { error },
+            };
+            let reconnected = !matches!(*backend_status.peek(), BackendStatus::Online { .. })
+                && matches!(status, BackendStatus::Online { .. });
+            let wait = if status.is_offline() { HEALTH_RETRY_SECS } else { HEALTH_INTERVAL_SECS };
+            if *backend_status.peek() != status {
+                backend_status.set(status);
+            }
+            // model lists fetched while the server was down are empty; reload them
+            if reconnected {
+                models_revision += 1;
+            }
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+        }
+    });
+    // back up the database every `backup_hours`; the age of the newest backup decides, so the
+    // schedule carries over restarts. The same loop empties chats that have been in the Trash
+    // longer than the configured number of days, so a long-running app keeps doing it too.
+    use_future(move || async move {
+        loop {
+            let s = settings.peek().clone();
+            if s.trash_days > 0 {
+                if let Err(e) = try_init_db().and_then(|conn| storage::purge_trash(&conn, s.trash_days)) {
+                    report_error(format!("Could not clean up the Trash: {}", e));
+                }
+            }
+            let every = Duration::from_secs(s.backup_hours.max(0) as u64 * 3600);
+            let due = s.backup_hours > 0
+                && storage::newest_backup_age(&backup_dir()).is_none_or(|age| age >= every);
+            if due {
+                match tokio::task::spawn_blocking(move || take_backup(s.backup_keep)).await {
+                    Ok(Err(e)) => report_error(format!("Automatic backup failed: {}", e)),
+                    Err(e) => report_error(format!("Automatic backup failed: {}", e)),
+                    Ok(Ok(_)) => {}
+                }
+            }
+            tokio::time::sleep(Duration::from_secs(BACKUP_CHECK_SECS)).await;
+        }
+    });
+    // semantic search requested from the sidebar or a message ("None" = no results panel)
+    let semantic_query = use_signal(|| Option::<SemanticQuery>::None);
+    // progress line of the background message embedder ("" = idle/off)
+    let mut embed_status = use_signal(|| "".to_string());
+
+    // embed chat messages in the background while semantic search is enabled;
+    // pending work is read from the database, so it resumes after a restart
+    use_future(move || async move {
+        let client = Client::new();
+        loop {
+            let s = settings.peek().clone();
+            let mut embedded = 0;
+            let status = if s.embed_messages && !s.embed_model.is_empty() {
+                let embedded_batch = match try_init_db().and_then(|conn| prune_message_embeddings(&conn)) {
+                    Ok(()) => embed_pending_messages(&client, &s.embed_model).await,
+                    Err(e) => Err(e.to_string()),
+                };
+                match embedded_batch {
+                    Ok(n) => {
+                        embedded = n;
+                        let (done, total) = message_embedding_progress(&init_db(), &s.embed_model);
+                        format!("Embedded {done} / {total} messages")
+                    }
+                    Err(e) => format!("Embedding paused: {e}"),
+                }
+            } else {
+                "".to_string()
+            };
+            if *embed_status.peek() != status {
+                embed_status.set(status);
+            }
+            if embedded == 0 {
+                tokio::time::sleep(Duration::from_secs(MSG_EMBED_IDLE_SECS)).await;
+            }
+        }
+    });
+
+    // the local API server: (re)started whenever its settings change, stopped by dropping it
+    let api_events = use_hook(|| broadcast::channel::<ApiEvent>(64).0);
+    let mut api_server = use_signal(|| Option::<ApiServer>::None);
+    let mut api_status = use_signal(String::new);
+    let api_config = use_memo(move || {
+        let s = settings();
+        (s.api_enabled, s.api_port, s.api_token)
+    });
+    {
+        let api_events = api_events.clone();
+        use_effect(move || {
+            let (enabled, port, token) = api_config();
+            let events = api_events.clone();
+            spawn(async move {
+                api_server.set(None);
+                if !enabled {
+                    api_status.set(String::new());
+                    return;
+                }
+                let config = ApiConfig {
+                    db_path: db_path(),
+                    port: port.clamp(1, 65535) as u16,
+                    token,
+                    base_url: OLLAMA_BASE_URL.to_string(),
+                    slots: GENERATION_SLOTS.clone(),
+                };
+                match api::start(config, events).await {
+                    Ok(server) => {
+                        api_status.set(format!("Listening on http://{}", server.addr()));
+                        api_server.set(Some(server));
+                    }
+                    Err(e) => {
+                        let message = format!("Could not start the local API on port {}: {}", port, e);
+                        api_status.set(message.clone());
+                        report_error(message);
+                    }
+                }
+            });
+        });
+    }
+
+    // show changes made through the API as they happen
+    let (mut chats_sig, mut current_chat_sig, mut messages_sig) = (chats, current_chat_id, messages);
+    use_future(move || {
+        let mut changes = api_events.subscribe();
+        async move {
+            loop {
+                let reload_messages = match changes.recv().await {
+                    Ok(ApiEvent::MessagesChanged(chat_id)) => Some(chat_id),
+                    Ok(ApiEvent::ChatsChanged) => {
+                        if let Ok(list) = open_store().and_then(|s| s.list_chats()) {
+                            // a chat deleted through the API closes if it was open
+                            let open = current_chat_sig.peek().clone();
+                            if open.is_some_and(|open| !list.iter().any(|(id, _)| *id == open)) {
+                                current_chat_sig.set(None);
+                                messages_sig.set(vec![]);
+                            }
+                            chats_sig.set(list);
+                        }
+                        None
+                    }
+                    // missed some: the open chat may be stale
+                    Err(broadcast::error::RecvError::Lagged(_)) => current_chat_sig.peek().clone(),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if let Some(chat_id) = reload_messages {
+                    if current_chat_sig.peek().as_deref() == Some(chat_id.as_str()) {
+                        messages_sig.set(or_report(open_store().and_then(|s| s.load_messages(&chat_id)), "load the chat"));
+                    }
+                }
+            }
+        }
+    });
+
+    // load chats once
+    {
+        let mut chats = chats;
+        use_effect(move || {
+            match open_store().and_then(|s| s.list_chats()) {
+                Ok(list) => chats.set(list),
+                Err(e) => report_error(format!("Could not load chats: {}", e)),
+            }
+        });
+    }
+
+    // always start maximized; user cannot change this in UI
+    let container_style = "width: 100vw; height: 100vh;".to_string();
+
+    // apply zoom using CSS 'zoom' so layout doesn't create blank transform area
+    let zoom_style = format!("zoom: {}%;", settings().zoom);
+
+    rsx! {
+        div { class: "outer-wrapper", style: "{container_style}",
+            ErrorBanner {}
+            UndoToast { chats }
+            div { class: "app-container", style: "{zoom_style}",
+                Sidebar {
+                    chats,
+                    current_chat_id,
+                    messages,
+                    show_settings,
+                    show_models,
+                    show_kb,
+                    show_prompts,
+                    show_personas,
+                    show_backups,
+                    personas_revision,
+                    settings,
+                    semantic_query,
+                    embed_status,
+                    active_runs,
+                    unread_chats
+                }
+                ChatWindow {
+                    current_chat_id,
+                    messages,
+                    settings,
+                    chats, // pass chats so header can show title
+                    semantic_query,
+                    personas_revision,
+                    active_runs,
+                    unread_chats,
+                    backend_status
+                }
+            }
+
+            if show_settings() {
+                SettingsModal {
+                    settings,
+                    show_settings,
+                    chats,
+                    messages,
+                    current_chat_id,
+                    models_revision,
+                    backend_status,
+                    api_status
+                }
+            }
+
+            if show_kb() {
+                KnowledgeBaseModal { show_kb, settings }
+            }
+
+            if show_prompts() {
+                PromptLibraryModal { show_prompts }
+            }
+
+            if show_personas() {
+                PersonasModal { show_personas, personas_revision }
+            }
+
+            if show_backups() {
+                BackupsModal { show_backups, chats, messages, current_chat_id, settings }
+            }
+
+            if show_models() {
+                ModelsModal {
+                    show_models,
+                    models_revision
+                }
+            }
+        }
+    }
+}
+
+/* ================= SIDEBAR ================= */
+
+#[component]
+fn Sidebar(
+    chats: Signal<Vec<(String, String)>>,
+    current_chat_id: Signal<Option<String>>,
+    messages: Signal<Vec<ChatMessage>>,
+    show_settings: Signal<bool>,
+    show_models: Signal<bool>,
+    show_kb: Signal<bool>,
+    show_prompts: Signal<bool>,
+    show_personas: Signal<bool>,
+    show_backups: Signal<bool>,
+    personas_revision: Signal<u32>,
+    settings: Signal<Settings>,
+    semantic_query: Signal<Option<SemanticQuery>>,
+    embed_status: Signal<String>,
+    active_runs: Signal<HashMap<String, ChatRun>>,
+    unread_chats: Signal<HashSet<String>>,
+) -> Element {
+    // state for inline renaming
+    let mut editing_chat = use_signal(|| Option::<String>::None);
+    let mut edit_text = use_signal(|| "".to_string());
+    // optional history filter: only list chats with replies from this model ("" = all)
+    let mut model_filter = use_signal(|| "".to_string());
+    // only list chats recorded by the proxy from this client ("" = all)
+    let mut client_filter = use_signal(|| "".to_string());
+    // persona given to chats created with "New Chat" (None = plain settings)
+    let mut new_chat_persona = use_signal(|| Option::<i64>::None);
+    let personas = {
+        let _ = personas_revision();
+        or_report(list_personas(&init_db()), "load personas")
+    };
+    // text of the "find similar" box
+    let mut similar_text = use_signal(|| "".to_string());
+    // folder/tag filter: "" = everything, "unfiled", "folder:<id>" or "tag:<name>"
+    let mut organize_filter = use_signal(|| "".to_string());
+    // bumped after folders or tags change so they are read again
+    let mut organize_revision = use_signal(|| 0u32);
+    // bulk mode: chats ticked for tagging or moving, and the tag typed for them
+    let mut selecting = use_signal(|| false);
+    let mut selected_chats = use_signal(HashSet::<String>::new);
+    let mut bulk_tag = use_signal(|| "".to_string());
+    // name typed for a new folder (None = input hidden) and the folder being renamed
+    let mut new_folder_name = use_signal(|| Option::<String>::None);
+    let mut renaming_folder = use_signal(|| Option::<(i64, String)>::None);
+    let mut dragging = use_signal(|| Option::<DragItem>::None);
+
+    // show the archive instead of the main list
+    let mut show_archived = use_signal(|| false);
+    let mut show_trash = use_signal(|| false);
+    // bulk "Delete" asks for a second click
+    let mut confirm_bulk_delete = use_signal(|| false);
+
+    // read on every render: replies arriving in any chat change the order
+    let (folders, chat_folder_ids, tags_by_chat, all_tags, activity) = {
+        let _ = organize_revision();
+        let _ = chats();
+        let conn = init_db();
+        (
+            or_report(storage::list_folders(&conn), "load the folders"),
+            storage::chat_folders(&conn),
+            or_report(chat_tags(&conn), "load the tags"),
+            storage::list_tags(&conn),
+            or_report(storage::chat_activity(&conn), "load the chat dates"),
+        )
+    };
+    let archived_count = activity.values().filter(|a| a.archived).count();
+    let trash_count = {
+        let _ = chats();
+        let _ = show_trash();
+        storage::list_trash(&init_db()).map(|t| t.len()).unwrap_or(0)
+    };
+
+    let (used_models, chat_sources, visible_chats) = {
+        let conn = init_db();
+        let used_models = or_report(list_used_models(&conn), "load the models used in chats");
+        // re-read whenever the chat list changes (proxied chats arrive through it)
+        let _ = chats();
+        let chat_sources = chat_clients(&conn);
+        let allowed = if model_filter().is_empty() {
+            None
+        } else {
+            Some(chats_with_model(&conn, &model_filter()))
+        };
+        let mut visible_chats: Vec<(String, String)> = chats()
+            .into_iter()
+            .filter(|(id, _)| activity.get(id).map(|a| a.archived).unwrap_or(false) == show_archived())
+            .filter(|(id, _)| allowed.as_ref().map(|a| a.contains(id)).unwrap_or(true))
+            .filter(|(id, _)| client_filter().is_empty() || chat_sources.get(id) == Some(&client_filter()))
+            .filter(|(id, _)| match organize_filter().strip_prefix("tag:") {
+                Some(tag) => tags_by_chat.get(id).map(|tags| tags.iter().any(|t| t == tag)).unwrap_or(false),
+                None => true,
+            })
+            .filter(|(id, _)| match organize_filter().as_str() {
+                "unfiled" => !chat_folder_ids.contains_key(id),
+                f => f
+                    .strip_prefix("folder:")
+                    .and_then(|f| f.parse::<i64>().ok())
+                    .map(|f| chat_folder_ids.get(id) == Some(&f))
+                    .unwrap_or(true),
+            })
+            .collect();
+        // pinned first, then most recently active (the stable sort keeps newer chats first on ties)
+        visible_chats.sort_by_key(|(id, _)| {
+            let a = activity.get(id);
+            (
+                std::cmp::Reverse(a.map(|a| a.pinned).unwrap_or(false)),
+                std::cmp::Reverse(a.map(|a| a.updated_at.clone()).unwrap_or_default()),
+            )
+        });
+        (used_models, chat_sources, visible_chats)
+    };
+    let filtering = !organize_filter().is_empty();
+    let unfiled_chats: Vec<&(String, String)> = visible_chats
+        .iter()
+        .filter(|(id, _)| !chat_folder_ids.contains_key(id))
+        .collect();
+    // consecutive runs of the same section; the list is already in section order
+    let mut date_groups: Vec<(&'static str, Vec<&(String, String)>)> = Vec::new();
+    for chat in unfiled_chats.iter().copied() {
+        let group = activity_group(activity.get(&chat.0));
+        match date_groups.last_mut() {
+            Some((label, chats)) if *label == group => chats.push(chat),
+            _ => date_groups.push((group, vec![chat])),
+        }
+    }
+    let selected_count = selected_chats().len();
+    let mut source_names: Vec<String> = chat_sources.values().cloned().collect();
+    source_names.sort();
+    source_names.dedup();
+
+    // drop whatever is being dragged on folder `target` (None = "No folder"): a chat is filed
+    // there, a folder is moved in front of it
+    let mut drop_on = move |target: Option<i64>| {
+        let result = match dragging() {
+            Some(DragItem::Chat(id)) => try_init_db().and_then(|conn| storage::set_chats_folder(&conn, &[id], target)),
+            Some(DragItem::Folder(id)) if target != Some(id) => try_init_db().and_then(|conn| storage::move_folder(&conn, id, target)),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            report_error(format!("Could not move it: {}", e));
+        }
+        dragging.set(None);
+        organize_revision += 1;
+    };
+
+    // one entry of the chat list
+    let chat_row = |(id, title): &(String, String)| -> Element {
+        // clone once from the iterator values
+        let id_owned = id.clone();
+        let title_clone = title.clone();
+
+        // create separate clones for each closure so none of them move a shared variable
+        let id_for_open = id_owned.clone();
+        let id_for_save = id_owned.clone();
+        let id_for_rename_btn = id_owned.clone();
+        let id_for_delete = id_owned.clone();
+        let title_for_delete = title.clone();
+        // Some(running) while the chat has a request in flight (false = waiting for a slot)
+        let run_state = active_runs().get(&id_owned).map(|r| r.running());
+        let unread = unread_chats().contains(&id_owned);
+        let source = chat_sources.get(&id_owned).cloned();
+        let tags = tags_by_chat.get(&id_owned).cloned().unwrap_or_default();
+        let chat_activity = activity.get(&id_owned).cloned();
+        let (pinned, archived) = chat_activity.as_ref().map(|a| (a.pinned, a.archived)).unwrap_or_default();
+        let dates = chat_activity
+            .map(|a| format!("Created {} · last active {}", a.created_at, a.updated_at))
+            .unwrap_or_default();
+        let id_for_pin = id_owned.clone();
+        let id_for_archive = id_owned.clone();
+        let is_selected = selected_chats().contains(&id_owned);
+        let id_for_drag = id_owned.clone();
+        let id_for_select = id_owned.clone();
+
+        // handles
+        let mut chats_handle = chats;
+        let mut messages_handle = messages;
+        let mut current_chat_handle = current_chat_id;
+        let editing_chat_handle = editing_chat;
+
+        rsx! {
+            div {
+                key: "{id_owned}",
+                class: if is_selected { "chat-item-row selected" } else { "chat-item-row" },
+                // drop it on a folder header to file it there
+                draggable: "true",
+                ondragstart: move |_| dragging.set(Some(DragItem::Chat(id_for_drag.clone()))),
+                ondragend: move |_| dragging.set(None),
+
+                if selecting() {
+                    input {
+                        class: "chat-select",
+                        r#type: "checkbox",
+                        checked: is_selected,
+                        onchange: move |_| {
+                            let mut selected = selected_chats.write();
+                            if !selected.remove(&id_for_select) {
+                                selected.insert(id_for_select.clone());
+                            }
+                        },
+                    }
+                }
+                div {
+                    class: "chat-item",
+                    title: "{dates}",
+                    onclick: move |_| {
+                        // use the dedicated clone inside this closure
+                        messages_handle.set(or_report(open_store().and_then(|s| s.load_messages(&id_for_open)), "load the chat"));
+                        current_chat_handle.set(Some(id_for_open.clone()));
+                    },
+
+                    /* Conditional: either show renaming input or the title, and place actions inline */
+                    {
+                        if editing_chat_handle().as_ref().map(|c| c == &id_for_save).unwrap_or(false) {
+                            rsx! { RenameRow { chat_id: id_for_save.clone(), chats, editing_chat, edit_text } }
+                        } else {
+                            rsx! {
+                                Fragment {
+                                    if let Some(running) = run_state {
+                                        span {
+                                            class: if running { "chat-spinner" } else { "chat-spinner waiting" },
+                                            title: if running { "Generating…" } else { "Waiting for a free generation slot" },
+                                        }
+                                    }
+                                    div { class: if unread { "chat-title unread" } else { "chat-title" }, "{title_clone}" }
+                                    if let Some(client) = source {
+                                        span { class: "client-badge", title: "Recorded from {client}", "{client}" }
+                                    }
+                                    for tag in tags {
+                                        span {
+                                            class: "tag-chip",
+                                            title: "Show chats tagged {tag}",
+                                            onclick: move |e| {
+                                                e.stop_propagation();
+                                                organize_filter.set(format!("tag:{}", tag));
+                                            },
+                                            "#{tag}"
+                                        }
+                                    }
+                                    if unread {
+                                        span { class: "unread-dot", title: "New reply" }
+                                    }
+                                    div { class: "chat-actions",
+                                        button {
+                                            class: if pinned { "rename-btn pin-btn pinned" } else { "rename-btn pin-btn" },
+                                            title: if pinned { "Unpin" } else { "Pin to the top" },
+                                            onclick: move |e| {
+                                                e.stop_propagation();
+                                                if let Err(e) = try_init_db().and_then(|conn| storage::set_chat_pinned(&conn, &id_for_pin, !pinned)) {
+                                                    return report_error(e);
+                                                }
+                                                organize_revision += 1;
+                                            },
+                                            "📌"
+                                        }
+                                        button {
+                                            class: "rename-btn",
+                                            title: if archived { "Move back to the chat list" } else { "Archive (hide without deleting)" },
+                                            onclick: move |e| {
+                                                e.stop_propagation();
+                                                if let Err(e) = try_init_db().and_then(|conn| storage::set_chat_archived(&conn, &id_for_archive, !archived)) {
+                                                    return report_error(e);
+                                                }
+                                                organize_revision += 1;
+                                            },
+                                            if archived { "Unarchive" } else { "Archive" }
+                                        }
+                                        button {
+                                            class: "rename-btn",
+                                            onclick: move |e| {
+                                                // stop propagation so clicking rename doesn't open the chat
+                                                e.stop_propagation();
+                                                editing_chat.set(Some(id_for_rename_btn.clone()));
+                                                // clamp initial edit text as well
+                                                edit_text.set(clamp_title(&title_clone));
+                                            },
+                                            "Rename"
+                                        }
+                                        button {
+                                            class: "delete-chat-btn big",
+                                            onclick: move |e| {
+                                                e.stop_propagation();
+                                                // stop a request still running for the deleted chat
+                                                if let Some(run) = active_runs.write().remove(&id_for_delete) {
+                                                    run.cancel.store(true, Ordering::Relaxed);
+                                                }
+                                                if let Err(e) = try_init_db().and_then(|conn| storage::trash_chats(&conn, std::slice::from_ref(&id_for_delete))) {
+                                                    return report_error(format!("Could not delete the chat: {}", e));
+                                                }
+                                                offer_undo(format!("\"{}\" moved to Trash", title_for_delete), vec![id_for_delete.clone()]);
+
+                                                chats_handle.set(
+                                                    chats_handle()
+                                                        .into_iter()
+                                                        .filter(|(cid, _)| cid != &id_for_delete)
+                                                        .collect()
+                                                );
+
+                                                if current_chat_handle() == Some(id_for_delete.clone()) {
+                                                    current_chat_handle.set(None);
+                                                    messages_handle.set(vec![]);
+                                                }
+                                            },
+                                            "Delete"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    rsx! {
+        div { class: "sidebar",
+            h1 { class: "logo", "RustyChat" }
+
+            button {
+                class: "new-chat-btn big",
+                onclick: move |_| {
+                    let new_id = Uuid::new_v4().to_string();
+                    // on the surface all chats share the same visible name "New Chat"
+                    let title = "New Chat".to_string();
+                    if let Err(e) = open_store().and_then(|s| s.create_chat(&new_id, &title)) {
+                        return report_error(format!("Could not create a chat: {}", e));
+                    }
+
+                    // a persona deleted since it was picked is simply not applied
+                    if let Some(persona_id) = new_chat_persona().filter(|id| personas.iter().any(|p| p.id == *id)) {
+                        if let Err(e) = try_init_db().and_then(|conn| set_chat_persona(&conn, &new_id, Some(persona_id))) {
+                            report_error(format!("The chat was created without its persona: {}", e));
+                        }
+                    }
+
+                    chats.push((new_id.clone(), title));
+                    current_chat_id.set(Some(new_id));
+                    messages.set(vec![]);
+                },
+                "➕ New Chat"
+            }
+
+            if !personas.is_empty() {
+                select {
+                    class: "input model-filter",
+                    title: "Persona for new chats",
+                    onchange: move |e| new_chat_persona.set(e.value().parse::<i64>().ok()),
+                    {persona_options(&personas, new_chat_persona())}
+                }
+            }
+
+            if !used_models.is_empty() {
+                select {
+                    class: "input model-filter",
+                    value: "{model_filter}",
+                    onchange: move |e| model_filter.set(e.value()),
+                    option { selected: model_filter().is_empty(), value: "", "All models" }
+                    {used_models.iter().map(|m| rsx!( option { selected: m == &model_filter(), value: "{m}", "{m}" } ))}
+                }
+            }
+
+            if !source_names.is_empty() {
+                select {
+                    class: "input model-filter",
+                    title: "Chats recorded from other tools through the OpenAI-compatible endpoint",
+                    value: "{client_filter}",
+                    onchange: move |e| client_filter.set(e.value()),
+                    option { selected: client_filter().is_empty(), value: "", "All clients" }
+                    {source_names.iter().map(|c| rsx!( option { selected: c == &client_filter(), value: "{c}", "{c}" } ))}
+                }
+            }
+
+            if !folders.is_empty() || !all_tags.is_empty() {
+                select {
+                    class: "input model-filter",
+                    title: "Show one folder or tag",
+                    value: "{organize_filter}",
+                    onchange: move |e| organize_filter.set(e.value()),
+                    option { selected: organize_filter().is_empty(), value: "", "All folders and tags" }
+                    if !folders.is_empty() {
+                        option { selected: organize_filter() == "unfiled", value: "unfiled", "No folder" }
+                    }
+                    for folder in folders.iter() {
+                        option {
+                            selected: organize_filter() == format!("folder:{}", folder.id),
+                            value: "folder:{folder.id}",
+                            "📁 {folder.name}"
+                        }
+                    }
+                    for tag in all_tags.iter() {
+                        option {
+                            selected: organize_filter() == format!("tag:{}", tag),
+                            value: "tag:{tag}",
+                            "#{tag}"
+                        }
+                    }
+                }
+            }
+
+            div { class: "row organize-row",
+                if let Some(name) = new_folder_name() {
+                    input {
+                        class: "input",
+                        placeholder: "Folder name",
+                        value: "{name}",
+                        oninput: move |e| new_folder_name.set(Some(e.value())),
+                    }
+                    button {
+                        class: "rename-save",
+                        disabled: name.trim().is_empty(),
+                        onclick: move |_| {
+                            let Some(name) = new_folder_name() else { return };
+                            if let Err(e) = try_init_db().and_then(|conn| storage::create_folder(&conn, &name)) {
+                                return report_error(format!("Could not create the folder: {}", e));
+                            }
+                            new_folder_name.set(None);
+                            organize_revision += 1;
+                        },
+                        "Create"
+                    }
+                    button { class: "rename-cancel", onclick: move |_| new_folder_name.set(None), "Cancel" }
+                } else {
+                    button { class: "rename-btn", onclick: move |_| new_folder_name.set(Some(String::new())), "📁 New folder" }
+                    button {
+                        class: "rename-btn",
+                        title: "Tick chats to tag them or move them to a folder together",
+                        onclick: move |_| {
+                            selecting.set(!selecting());
+                            selected_chats.write().clear();
+                        },
+                        if selecting() { "Cancel selection" } else { "☑ Select" }
+                    }
+                    if archived_count > 0 || show_archived() {
+                        button {
+                            class: "rename-btn",
+                            onclick: move |_| show_archived.set(!show_archived()),
+                            if show_archived() { "← Chats" } else { "🗄 Archive ({archived_count})" }
+                        }
+                    }
+                    if trash_count > 0 {
+                        button {
+                            class: "rename-btn",
+                            title: "Deleted chats, restorable until the Trash is emptied",
+                            onclick: move |_| show_trash.set(true),
+                            "🗑 Trash ({trash_count})"
+                        }
+                    }
+                }
+            }
+
+            if settings().embed_messages {
+                input {
+                    class: "input model-filter",
+                    value: "{similar_text}",
+                    placeholder: "🔎 Find similar messages…",
+                    oninput: move |e| similar_text.set(e.value()),
+                    onkeydown: move |e| {
+                        if e.key() == Key::Enter && !similar_text().trim().is_empty() {
+                            semantic_query.set(Some(SemanticQuery {
+                                text: similar_text().trim().to_string(),
+                                exclude_chat: None,
+                            }));
+                        }
+                    },
+                }
+                if !embed_status().is_empty() {
+                    p { class: "dim-text embed-status", "{embed_status}" }
+                }
+            }
+
+            SemanticResults { semantic_query, current_chat_id, messages, settings }
+
+            if show_trash() {
+                TrashModal { show_trash, chats, settings }
+            }
+
+            if selecting() {
+                div { class: "bulk-bar",
+                    p { class: "dim-text", "{selected_count} selected" }
+                    input {
+                        class: "input",
+                        list: "tag-names",
+                        placeholder: "Tag",
+                        value: "{bulk_tag}",
+                        oninput: move |e| bulk_tag.set(e.value()),
+                    }
+                    datalist { id: "tag-names",
+                        for tag in all_tags.iter() {
+                            option { value: "{tag}" }
+                        }
+                    }
+                    div { class: "row",
+                        button {
+                            class: "rename-save",
+                            disabled: selected_count == 0 || bulk_tag().trim().is_empty(),
+                            onclick: move |_| {
+                                let ids: Vec<String> = selected_chats().into_iter().collect();
+                                if let Err(e) = try_init_db().and_then(|conn| storage::add_tag(&conn, &ids, &bulk_tag())) {
+                                    return report_error(format!("Could not tag the chats: {}", e));
+                                }
+                                organize_revision += 1;
+                            },
+                            "Add tag"
+                        }
+                        button {
+                            class: "rename-cancel",
+                            disabled: selected_count == 0 || bulk_tag().trim().is_empty(),
+                            onclick: move |_| {
+                                let ids: Vec<String> = selected_chats().into_iter().collect();
+                                if let Err(e) = try_init_db().and_then(|conn| storage::remove_tag(&conn, &ids, &bulk_tag())) {
+                                    return report_error(format!("Could not untag the chats: {}", e));
+                                }
+                                organize_revision += 1;
+                            },
+                            "Remove tag"
+                        }
+                    }
+                    select {
+                        class: "input model-filter",
+                        disabled: selected_count == 0,
+                        // always shows the prompt; picking an entry moves the selection
+                        value: "",
+                        onchange: move |e| {
+                            let folder = match e.value().as_str() {
+                                "" => return,
+                                "none" => None,
+                                id => id.parse::<i64>().ok(),
+                            };
+                            let ids: Vec<String> = selected_chats().into_iter().collect();
+                            if let Err(e) = try_init_db().and_then(|conn| storage::set_chats_folder(&conn, &ids, folder)) {
+                                return report_error(format!("Could not move the chats: {}", e));
+                            }
+                            organize_revision += 1;
+                        },
+                        option { value: "", "Move to folder…" }
+                        option { value: "none", "No folder" }
+                        for folder in folders.iter() {
+                            option { value: "{folder.id}", "📁 {folder.name}" }
+                        }
+                    }
+                    button {
+                        class: "delete-chat-btn big",
+                        disabled: selected_count == 0,
+                        onclick: move |_| {
+                            if !confirm_bulk_delete() {
+                                confirm_bulk_delete.set(true);
+                                return;
+                            }
+                            confirm_bulk_delete.set(false);
+                            let ids: Vec<String> = selected_chats().into_iter().collect();
+                            for id in &ids {
+                                if let Some(run) = active_runs.write().remove(id) {
+                                    run.cancel.store(true, Ordering::Relaxed);
+                                }
+                            }
+                            if let Err(e) = try_init_db().and_then(|conn| storage::trash_chats(&conn, &ids)) {
+                                return report_error(format!("Could not delete the chats: {}", e));
+                            }
+                            chats.set(chats().into_iter().filter(|(id, _)| !ids.contains(id)).collect());
+                            if current_chat_id().is_some_and(|id| ids.contains(&id)) {
+                                current_chat_id.set(None);
+                                messages.set(vec![]);
+                            }
+                            offer_undo(format!("{} chats moved to Trash", ids.len()), ids);
+                            selected_chats.write().clear();
+                        },
+                        if confirm_bulk_delete() { "Click again to delete {selected_count}" } else { "Delete" }
+                    }
+                    button {
+                        class: "rename-cancel",
+                        onclick: move |_| {
+                            selecting.set(false);
+                            selected_chats.write().clear();
+                            confirm_bulk_delete.set(false);
+                        },
+                        "Done"
+                    }
+                }
+            }
+
+            div { class: "chat-list",
+                {folders.iter().map(|folder| {
+                    let folder_id = folder.id;
+                    let collapsed = folder.collapsed;
+                    let name = folder.name.clone();
+                    let folder_chats: Vec<&(String, String)> = visible_chats
+                        .iter()
+                        .filter(|(id, _)| chat_folder_ids.get(id) == Some(&folder_id))
+                        .collect();
+                    // while filtering, folders without a matching chat are left out
+                    if filtering && folder_chats.is_empty() {
+                        return rsx! {};
+                    }
+                    let count = folder_chats.len();
+                    let renaming = renaming_folder().filter(|(id, _)| *id == folder_id);
+
+                    rsx! {
+                        div { key: "folder-{folder_id}", class: "folder",
+                            div {
+                                class: "folder-header",
+                                draggable: "true",
+                                ondragstart: move |_| dragging.set(Some(DragItem::Folder(folder_id))),
+                                ondragend: move |_| dragging.set(None),
+                                ondragover: move |e| e.prevent_default(),
+                                ondrop: move |e| {
+                                    e.prevent_default();
+                                    drop_on(Some(folder_id));
+                                },
+                                onclick: move |_| {
+                                    if let Err(e) = try_init_db().and_then(|conn| storage::set_folder_collapsed(&conn, folder_id, !collapsed)) {
+                                        return report_error(e);
+                                    }
+                                    organize_revision += 1;
+                                },
+
+                                if let Some((_, text)) = renaming {
+                                    div { class: "rename-row",
+                                        input {
+                                            class: "rename-input",
+                                            value: "{text}",
+                                            onclick: move |e| e.stop_propagation(),
+                                            oninput: move |e| renaming_folder.set(Some((folder_id, e.value()))),
+                                        }
+                                        button {
+                                            class: "rename-save",
+                                            onclick: move |e| {
+                                                e.stop_propagation();
+                                                let Some((_, text)) = renaming_folder() else { return };
+                                                if !text.trim().is_empty() {
+                                                    if let Err(e) = try_init_db().and_then(|conn| storage::rename_folder(&conn, folder_id, &text)) {
+                                                        return report_error(format!("Could not rename the folder: {}", e));
+                                                    }
+                                                }
+                                                renaming_folder.set(None);
+                                                organize_revision += 1;
+                                            },
+                                            "Save"
+                                        }
+                                        button {
+                                            class: "rename-cancel",
+                                            onclick: move |e| {
+                                                e.stop_propagation();
+                                                renaming_folder.set(None);
+                                            },
+                                            "Cancel"
+                                        }
+                                    }
+                                } else {
+                                    span { class: "folder-arrow", if collapsed { "▸" } else { "▾" } }
+                                    span { class: "folder-name", "📁 {name}" }
+                                    span { class: "folder-count", "{count}" }
+                                    div { class: "chat-actions",
+                                        button {
+                                            class: "rename-btn",
+                                            onclick: move |e| {
+                                                e.stop_propagation();
+                                                renaming_folder.set(Some((folder_id, name.clone())));
+                                            },
+                                            "Rename"
+                                        }
+                                        button {
+                                            class: "delete-chat-btn big",
+                                            title: "Delete the folder (its chats are kept)",
+                                            onclick: move |e| {
+                                                e.stop_propagation();
+                                                if let Err(e) = try_init_db().and_then(|conn| storage::delete_folder(&conn, folder_id)) {
+                                                    return report_error(format!("Could not delete the folder: {}", e));
+                                                }
+                                                if organize_filter() == format!("folder:{}", folder_id) {
+                                                    organize_filter.set("".to_string());
+                                                }
+                                                organize_revision += 1;
+                                            },
+                                            "Delete"
+                                        }
+                                    }
+                                }
+                            }
+                            if !collapsed {
+                                {folder_chats.into_iter().map(&chat_row)}
+                            }
+                        }
+                    }
+                })}
+
+                if !folders.is_empty() && (!filtering || !unfiled_chats.is_empty()) {
+                    div {
+                        class: "folder-header unfiled",
+                        title: "Drop a chat here to take it out of its folder",
+                        ondragover: move |e| e.prevent_default(),
+                        ondrop: move |e| {
+                            e.prevent_default();
+                            drop_on(None);
+                        },
+                        span { class: "folder-name", "No folder" }
+                    }
+                }
+                {date_groups.into_iter().map(|(label, rows)| rsx! {
+                    div { key: "{label}", class: "date-group",
+                        p { class: "date-group-label", "{label}" }
+                        {rows.into_iter().map(&chat_row)}
+                    }
+                })}
+            }
+
+            // Footer inside the sidebar (bottom-left area)
+            div { class: "sidebar-footer",
+                button {
+                    class: "settings-btn big",
+                    onclick: move |_| {
+                        show_settings.set(!show_settings());
+                    },
+                    // icon + tooltip text shown on hover via CSS
+                    span { class: "settings-icon", "⚙️" }
+                    span { class: "settings-tooltip", "Settings" }
+                }
+                button {
+                    class: "settings-btn big",
+                    onclick: move |_| {
+                        show_models.set(!show_models());
+                    },
+                    span { class: "settings-icon", "📦" }
+                    span { class: "settings-tooltip", "Models" }
+                }
+                button {
+                    class: "settings-btn big",
+                    onclick: move |_| {
+                        show_kb.set(!show_kb());
+                    },
+                    span { class: "settings-icon", "📚" }
+                    span { class: "settings-tooltip", "Knowledge" }
+                }
+                button {
+                    class: "settings-btn big",
+                    onclick: move |_| {
+                        show_prompts.set(!show_prompts());
+                    },
+                    span { class: "settings-icon", "📝" }
+                    span { class: "settings-tooltip", "Prompts" }
+                }
+                button {
+                    class: "settings-btn big",
+                    onclick: move |_| {
+                        show_personas.set(!show_personas());
+                    },
+                    span { class: "settings-icon", "🎭" }
+                    span { class: "settings-tooltip", "Personas" }
+                }
+                button {
+                    class: "settings-btn big",
+                    onclick: move |_| {
+                        show_backups.set(!show_backups());
+                    },
+                    span { class: "settings-icon", "💾" }
+                    span { class: "settings-tooltip", "Backups" }
+                }
+                a { class: "repo-icon", href: "https://github.com/KPCOFGS/RustyChat", target: "_blank", title: "click here to see the repository", "🔗" }
+            }
+        }
+    }
+}
+
+// sidebar section of an unfiled chat: pinned chats, then by the day it was last active
+fn activity_group(activity: Option<&storage::ChatActivity>) -> &'static str {
+    match activity {
+        Some(a) if a.pinned => "📌 Pinned",
+        Some(a) if a.days_ago <= 0 => "Today",
+        Some(a) if a.days_ago == 1 => "Yesterday",
+        Some(a) if a.days_ago < 7 => "Last 7 days",
+        _ => "Older",
+    }
+}
+
+// what is being dragged in the sidebar
+#[derive(Clone, PartialEq)]
+enum DragItem {
+    Chat(String),
+    Folder(i64),
+}
+
+/* ================= STRUCTURED OUTPUT ================= */
+
+// header panel for choosing the chat's structured output mode and schema
+#[component]
+fn StructuredOutputPanel(chat_id: String, show_panel: Signal<bool>, chat_format: Signal<Option<String>>) -> Element {
+    // "off", "json" or "schema"
+    let mut mode = use_signal(|| match chat_format().as_deref() {
+        None => "off".to_string(),
+        Some("json") => "json".to_string(),
+        Some(_) => "schema".to_string(),
+    });
+    let mut schema_text = use_signal(|| match chat_format() {
+        Some(f) if f != "json" => serde_json::from_str::<Value>(&f)
+            .ok()
+            .and_then(|v| serde_json::to_string_pretty(&v).ok())
+            .unwrap_or(f),
+        _ => "".to_string(),
+    });
+    let mut saved = use_signal(|| list_saved_schemas(&init_db()));
+    let mut save_name = use_signal(|| "".to_string());
+    let mut panel_error = use_signal(|| Option::<String>::None);
+
+    let apply = move |_| {
+        let format = match mode().as_str() {
+            "json" => Some("json".to_string()),
+            "schema" => match serde_json::from_str::<Value>(&schema_text()) {
+                Ok(v) if v.is_object() => Some(v.to_string()),
+                Ok(_) => {
+                    panel_error.set(Some("The schema must be a JSON object".to_string()));
+                    return;
+                }
+                Err(e) => {
+                    panel_error.set(Some(format!("Schema is not valid JSON: {}", e)));
+                    return;
+                }
+            },
+            _ => None,
+        };
+        if let Err(e) = try_init_db().and_then(|conn| save_chat_format(&conn, &chat_id, format.as_deref())) {
+            panel_error.set(Some(format!("Could not save the output format: {}", e)));
+            return;
+        }
+        chat_format.set(format);
+        show_panel.set(false);
+    };
+
+    rsx! {
+        div { class: "structured-panel",
+            div { class: "row",
+                label { "Reply format" }
+                select {
+                    class: "input",
+                    value: "{mode}",
+                    onchange: move |e| mode.set(e.value()),
+                    option { selected: mode() == "off", value: "off", "Free text" }
+                    option { selected: mode() == "json", value: "json", "Any JSON" }
+                    option { selected: mode() == "schema", value: "schema", "JSON matching a schema" }
+                }
+            }
+
+            if mode() == "schema" {
+                if !saved().is_empty() {
+                    div { class: "row",
+                        select {
+                            class: "input",
+                            value: "",
+                            onchange: move |e| {
+                                let name = e.value();
+                                if let Some((_, schema)) = saved().iter().find(|(n, _)| n == &name) {
+                                    let pretty = serde_json::from_str::<Value>(schema)
+                                        .ok()
+                                        .and_then(|v| serde_json::to_string_pretty(&v).ok())
+                                        .unwrap_or(schema.clone());
+                                    schema_text.set(pretty);
+                                    save_name.set(name);
+                                }
+                            },
+                            option { value: "", "- Load a saved schema -" }
+                            {saved().iter().map(|(name, _)| rsx!( option { value: "{name}", "{name}" } ))}
+                        }
+                        if saved().iter().any(|(n, _)| n == &save_name()) {
+                            button {
+                                class: "rename-cancel",
+                                onclick: move |_| {
+                                    let conn = match try_init_db() {
+                                        Ok(conn) => conn,
+                                        Err(e) => return panel_error.set(Some(format!("Could not delete the schema: {}", e))),
+                                    };
+                                    if let Err(e) = delete_schema(&conn, &save_name()) {
+                                        return panel_error.set(Some(format!("Could not delete the schema: {}", e)));
+                                    }
+                                    saved.set(list_saved_schemas(&conn));
+                                    save_name.set("".to_string());
+                                },
+                                "Delete saved"
+                            }
+                        }
+                    }
+                }
+                textarea {
+                    class: "textarea schema-text",
+                    placeholder: "JSON Schema, e.g. an object with type, properties and required",
+                    value: "{schema_text}",
+                    oninput: move |e| schema_text.set(e.value()),
+                }
+                div { class: "row",
+                    input {
+                        class: "input",
+                        placeholder: "name to save this schema as",
+                        value: "{save_name}",
+                        oninput: move |e| save_name.set(e.value()),
+                    }
+                    button {
+                        class: "rename-save",
+                        disabled: save_name().trim().is_empty(),
+                        onclick: move |_| {
+                            match serde_json::from_str::<Value>(&schema_text()) {
+                                Ok(v) if v.is_object() => {
+                                    let conn = match try_init_db() {
+                                        Ok(conn) => conn,
+                                        Err(e) => return panel_error.set(Some(format!("Could not save the schema: {}", e))),
+                                    };
+                                    if let Err(e) = save_schema(&conn, save_name().trim(), &v.to_string()) {
+                                        return panel_error.set(Some(format!("Could not save the schema: {}", e)));
+                                    }
+                                    saved.set(list_saved_schemas(&conn));
+                                    panel_error.set(None);
+                                }
+                                _ => panel_error.set(Some("Only a valid JSON object can be saved as a schema".to_string())),
+                            }
+                        },
+                        "Save schema"
+                    }
+                }
+            }
+
+            if let Some(err) = panel_error() {
+                p { class: "dim-text warning-text", "{err}" }
+            }
+
+            div { class: "row",
+                button { class: "rename-save", onclick: apply, "Apply" }
+                button { class: "rename-cancel", onclick: move |_| show_panel.set(false), "Cancel" }
+            }
+        }
+    }
+}
+
+/* ================= KNOWLEDGE BASE ================= */
+
+// chunk size and overlap (characters) used when splitting documents
+const KB_CHUNK_CHARS: usize = 1200;
+const KB_CHUNK_OVERLAP: usize = 200;
+// files larger than this are skipped while indexing
+const KB_MAX_FILE_BYTES: u64 = 1024 * 1024;
+// texts per /api/embed request
+const KB_EMBED_BATCH: usize = 16;
+// file types indexed into the knowledge base
+const KB_EXTENSIONS: [&str; 32] = [
+    "txt", "md", "markdown", "rst", "adoc", "org", "rs", "py", "js", "ts", "tsx", "jsx", "go",
+    "java", "kt", "c", "h", "cpp", "hpp", "cs", "rb", "php", "swift", "sh", "sql", "toml", "yaml",
+    "yml", "json", "html", "css", "scss",
+];
+
+// text-like files under `root`, skipping hidden folders and build/dependency output
+fn collect_kb_files(root: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let mut files = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if !matches!(name.as_str(), "target" | "node_modules" | "dist" | "build") {
+                    stack.push(path);
+                }
+            } else if path.is_dir() {
+                // a symlinked folder: not followed, a link back up the tree would never end
+                continue;
+            } else if path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| KB_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+                && std::fs::metadata(&path).map(|m| m.len() <= KB_MAX_FILE_BYTES).unwrap_or(false)
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+// (re)index a folder: chunk every file, embed the chunks and replace what was stored for it.
+// `progress` is called with (files done, total files).
+async fn index_kb_folder(
+    client: &Client,
+    model: &str,
+    folder: &str,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<usize, String> {
+    let root = std::fs::canonicalize(folder.trim()).map_err(|e| format!("{}: {}", folder, e))?;
+    if !root.is_dir() {
+        return Err(format!("{} is not a folder", root.display()));
+    }
+    let files = collect_kb_files(&root);
+
+    // embed everything first so a failure leaves the previous index intact
+    let mut rows: Vec<KbChunk> = Vec::new();
+    for (done, file) in files.iter().enumerate() {
+        progress(done, files.len());
+        let Ok(bytes) = std::fs::read(file) else {
+            continue;
+        };
+        let text = String::from_utf8_lossy(&bytes);
+        let relative = file
+            .strip_prefix(&root)
+            .unwrap_or(file)
+            .to_string_lossy()
+            .to_string();
+        let chunks = chunk_text(&text, KB_CHUNK_CHARS, KB_CHUNK_OVERLAP);
+        for (batch_no, batch) in chunks.chunks(KB_EMBED_BATCH).enumerate() {
+            // prefix with the file name so the embedding knows where the text came from
+            let inputs: Vec<String> = batch
+                .iter()
+                .map(|c| format!("{}\n{}", relative, c))
+                .collect();
+            let embeddings = embed_texts(client, OLLAMA_BASE_URL, model, &inputs).await?;
+            for (i, (chunk, embedding)) in batch.iter().zip(embeddings).enumerate() {
+                rows.push(KbChunk {
+                    file_path: relative.clone(),
+                    chunk_index: (batch_no * KB_EMBED_BATCH + i) as i64,
+                    content: chunk.clone(),
+                    embedding,
+                });
+            }
+        }
+    }
+    progress(files.len(), files.len());
+
+    let path = root.to_string_lossy().to_string();
+    try_init_db()
+        .and_then(|conn| storage::replace_kb_source(&conn, &path, model, &rows))
+        .map_err(|e| e.to_string())?;
+    Ok(rows.len())
+}
+
+#[component]
+fn KnowledgeBaseModal(show_kb: Signal<bool>, settings: Signal<Settings>) -> Element {
+    let http_client = use_signal(Client::new);
+    let mut sources = use_signal(|| list_kb_sources(&init_db()));
+    let mut folder_input = use_signal(|| "".to_string());
+    // folder being indexed with (files done, total files)
+    let mut indexing = use_signal(|| Option::<(String, usize, usize)>::None);
+    let mut status = use_signal(|| Option::<String>::None);
+
+    let mut start_index = move |folder: String| {
+        let model = settings().embed_model.clone();
+        if model.trim().is_empty() {
+            status.set(Some("Pick an embedding model in Settings first (e.g. nomic-embed-text).".to_string()));
+            return;
+        }
+        if folder.trim().is_empty() || indexing().is_some() {
+            return;
+        }
+        indexing.set(Some((folder.clone(), 0, 0)));
+        status.set(None);
+        spawn(async move {
+            let result = index_kb_folder(&http_client(), &model, &folder, |done, total| {
+                indexing.set(Some((folder.clone(), done, total)));
+            })
+            .await;
+            indexing.set(None);
+            match result {
+                Ok(count) => {
+                    status.set(Some(format!("Indexed {} chunks from {}", count, folder)));
+                    folder_input.set("".to_string());
+                }
+                Err(e) => status.set(Some(format!("Indexing failed: {}", e))),
+            }
+            sources.set(list_kb_sources(&init_db()));
+        });
+    };
+
+    rsx! {
+        div { class: "settings-overlay",
+            div { class: "settings-modal models-modal",
+                h3 { "Knowledge base" }
+                p { class: "dim-text",
+                    "Indexed folders are searched for each message in chats with the knowledge base switched on. Embedding model: "
+                    {if settings().embed_model.is_empty() { "none selected".to_string() } else { settings().embed_model.clone() }}
+                }
+
+                label { "Add a folder of text, Markdown or code files" }
+                div { class: "row",
+                    input {
+                        class: "input",
+                        value: "{folder_input}",
+                        placeholder: "/home/me/docs",
+                        oninput: move |e| folder_input.set(e.value()),
+                    }
+                    button {
+                        class: "model-action",
+                        disabled: indexing().is_some() || folder_input().trim().is_empty(),
+                        onclick: move |_| start_index(folder_input()),
+                        "Index"
+                    }
+                }
+
+                if let Some((folder, done, total)) = indexing() {
+                    div { class: "pull-row",
+                        div { class: "pull-label",
+                            span { "{folder}" }
+                            span { class: "dim-text", "{done} / {total} files" }
+                        }
+                        div { class: "progress-bar",
+                            div {
+                                class: "progress-fill",
+                                style: format!("width: {:.0}%;", if total > 0 { done as f64 / total as f64 * 100.0 } else { 0.0 }),
+                            }
+                        }
+                    }
+                }
+
+                if let Some(text) = status() {
+                    p { class: "dim-text", "{text}" }
+                }
+
+                h4 { "Indexed folders" }
+                {sources().iter().map(|src| {
+                    let path = src.path.clone();
+                    let id = src.id;
+                    let info = format!("{} chunks · {} · {}", src.chunks, src.embed_model, src.indexed_at);
+                    rsx! {
+                        div { class: "model-row",
+                            div { class: "model-row-head",
+                                span { class: "model-name", "{path}" }
+                                span { class: "dim-text", "{info}" }
+                                div { class: "chat-actions",
+                                    button {
+                                        class: "rename-btn",
+                                        disabled: indexing().is_some(),
+                                        onclick: move |_| start_index(path.clone()),
+                                        "Re-index"
+                                    }
+                                    button {
+                                        class: "delete-chat-btn big",
+                                        disabled: indexing().is_some(),
+                                        onclick: move |_| {
+                                            if let Err(e) = try_init_db().and_then(|conn| remove_kb_source(&conn, id)) {
+                                                report_error(format!("Could not remove the folder: {}", e));
+                                            }
+                                            sources.set(list_kb_sources(&init_db()));
+                                        },
+                                        "Remove"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                })}
+                if sources().is_empty() {
+                    p { class: "dim-text", "No folders indexed yet." }
+                }
+
+                div { class: "modal-actions",
+                    button { onclick: move |_| show_kb.set(false), "Close" }
+                }
+            }
+        }
+    }
+}
+
+/* ================= SEMANTIC SEARCH ================= */
+
+// message text beyond this many characters is not embedded
+const MSG_EMBED_MAX_CHARS: usize = 4000;
+// how long the background embedder waits when there is nothing to do
+const MSG_EMBED_IDLE_SECS: u64 = 5;
+// characters of a matching message shown in search results
+const MSG_SNIPPET_CHARS: usize = 160;
+
+// a search started from the sidebar or from a message's "Related" action
+#[derive(Clone, Debug, PartialEq)]
+struct SemanticQuery {
+    text: String,
+    // leave out the chat the query came from and list each chat once
+    exclude_chat: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct SimilarMessage {
+    chat_id: String,
+    chat_title: String,
+    role: String,
+    snippet: String,
+    score: f32,
+}
+
+// embed the next batch of pending messages; returns how many were stored.
+// progress lives in the database, so an interrupted run simply resumes here.
+async fn embed_pending_messages(client: &Client, model: &str) -> Result<usize, String> {
+    let pending = pending_message_embeddings(&init_db(), model, KB_EMBED_BATCH).map_err(|e| e.to_string())?;
+    if pending.is_empty() {
+        return Ok(0);
+    }
+    let inputs: Vec<String> = pending
+        .iter()
+        .map(|(_, content)| content.chars().take(MSG_EMBED_MAX_CHARS).collect())
+        .collect();
+    let embeddings = embed_texts(client, OLLAMA_BASE_URL, model, &inputs).await?;
+
+    let stored: Vec<(i64, Vec<f32>)> = pending.iter().map(|(id, _)| *id).zip(embeddings).collect();
+    try_init_db()
+        .and_then(|conn| storage::store_message_embeddings(&conn, model, &stored))
+        .map_err(|e| e.to_string())?;
+    Ok(pending.len())
+}
+
+// past messages ranked by similarity to `query`
+async fn search_similar_messages(
+    client: &Client,
+    model: &str,
+    query: &SemanticQuery,
+    limit: usize,
+) -> Result<Vec<SimilarMessage>, String> {
+    let query_embedding = embed_texts(client, OLLAMA_BASE_URL, model, std::slice::from_ref(&query.text))
+        .await?
+        .pop()
+        .unwrap_or_default();
+
+    let mut hits: Vec<SimilarMessage> = storage::embedded_messages(&init_db(), model)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|m| query.exclude_chat.as_ref() != Some(&m.chat_id))
+        .map(|m| {
+            let mut snippet: String = m.content.chars().take(MSG_SNIPPET_CHARS).collect();
+            if m.content.chars().count() > MSG_SNIPPET_CHARS {
+                snippet.push('…');
+            }
+            SimilarMessage {
+                score: cosine_similarity(&query_embedding, &m.embedding),
+                chat_id: m.chat_id,
+                chat_title: m.chat_title,
+                role: m.role,
+                snippet,
+            }
+        })
+        .collect();
+
+    hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    if query.exclude_chat.is_some() {
+        // "related conversations": keep only the best match of each chat
+        let mut seen = HashSet::new();
+        hits.retain(|h| seen.insert(h.chat_id.clone()));
+    }
+    hits.truncate(limit);
+    Ok(hits)
+}
+
+// results panel shown in the sidebar; clicking a result opens its chat
+// "Related" under a message: look up earlier chats that discussed something similar
+#[component]
+fn RelatedButton(content: String, chat_id: Option<String>, semantic_query: Signal<Option<SemanticQuery>>) -> Element {
+    if content.trim().is_empty() || content.starts_with("Error:") {
+        return rsx! {};
+    }
+    rsx! {
+        button {
+            class: "related-btn",
+            title: "Related earlier conversations",
+            onclick: move |_| {
+                semantic_query.set(Some(SemanticQuery {
+                    text: content.chars().take(MSG_EMBED_MAX_CHARS).collect(),
+                    exclude_chat: chat_id.clone(),
+                }));
+            },
+            "🔗 Related"
+        }
+    }
+}
+
+#[component]
+fn SemanticResults(
+    semantic_query: Signal<Option<SemanticQuery>>,
+    current_chat_id: Signal<Option<String>>,
+    messages: Signal<Vec<ChatMessage>>,
+    settings: Signal<Settings>,
+) -> Element {
+    let http_client = use_signal(Client::new);
+    let mut results = use_signal(Vec::<SimilarMessage>::new);
+    let mut searching = use_signal(|| false);
+    let mut search_error = use_signal(|| Option::<String>::None);
+
+    // re-run whenever a new query is set
+    use_effect(move || {
+        let Some(query) = semantic_query() else {
+            return;
+        };
+        let model = settings.peek().embed_model.clone();
+        if model.is_empty() {
+            search_error.set(Some("Select an embedding model in Settings first.".to_string()));
+            results.set(vec![]);
+            return;
+        }
+        searching.set(true);
+        search_error.set(None);
+        spawn(async move {
+            match search_similar_messages(&http_client(), &model, &query, 10).await {
+                Ok(hits) => results.set(hits),
+                Err(e) => {
+                    results.set(vec![]);
+                    search_error.set(Some(e));
+                }
+            }
+            searching.set(false);
+        });
+    });
+
+    let Some(query) = semantic_query() else {
+        return rsx! { Fragment {} };
+    };
+    let heading = if query.exclude_chat.is_some() {
+        "Related earlier conversations"
+    } else {
+        "Similar messages"
+    };
+
+    rsx! {
+        div { class: "semantic-results",
+            div { class: "semantic-head",
+                span { class: "tool-label", "{heading}" }
+                button {
+                    class: "rename-cancel",
+                    onclick: move |_| semantic_query.set(None),
+                    "Close"
+                }
+            }
+            if searching() {
+                p { class: "dim-text", "Searching..." }
+            } else if let Some(err) = search_error() {
+                p { class: "dim-text warning-text", "Search failed: {err}" }
+            } else if results().is_empty() {
+                p { class: "dim-text", "No similar messages found. Messages are searchable once they have been embedded." }
+            }
+            {results().into_iter().map(|hit| {
+                let score = format!("{:.2}", hit.score);
+                let title = if hit.chat_title.is_empty() { "Untitled chat".to_string() } else { hit.chat_title.clone() };
+                let chat_id = hit.chat_id.clone();
+                rsx! {
+                    div {
+                        class: "semantic-hit",
+                        onclick: move |_| {
+                            messages.set(or_report(open_store().and_then(|s| s.load_messages(&chat_id)), "load the chat"));
+                            current_chat_id.set(Some(chat_id.clone()));
+                        },
+                        p { class: "semantic-hit-title", "{title} · {score}" }
+                        p { class: "dim-text", "{hit.role}: {hit.snippet}" }
+                    }
+                }
+            })}
+        }
+    }
+}
+
+/* ================= PROMPT TEMPLATES ================= */
+
+// file suggested for importing/exporting the prompt library
+const PROMPTS_EXPORT_PATH: &str = "prompts.json";
+
+// write the whole library as a JSON array
+fn export_prompt_templates(conn: &Connection, path: &str) -> Result<usize, String> {
+    let templates = list_prompt_templates(conn).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(&templates).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| format!("could not write {}: {}", path, e))?;
+    Ok(templates.len())
+}
+
+// read a JSON array of templates; existing templates with the same name are replaced.
+// One bad entry rejects the whole file
+fn import_prompt_templates(conn: &Connection, path: &str) -> Result<usize, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    let templates: Vec<PromptTemplate> =
+        serde_json::from_str(&text).map_err(|e| format!("invalid prompt library: {}", e))?;
+    templates.iter().try_for_each(check_template)?;
+    storage::save_prompt_templates(conn, &templates).map_err(|e| e.to_string())?;
+    Ok(templates.len())
+}
+
+#[component]
+fn PromptLibraryModal(show_prompts: Signal<bool>) -> Element {
+    let mut templates = use_signal(|| or_report(list_prompt_templates(&init_db()), "load prompt templates"));
+    // template being edited: the name it was loaded under (None = new template)
+    let mut editing = use_signal(|| Option::<String>::None);
+    let mut edit_name = use_signal(|| "".to_string());
+    let mut edit_description = use_signal(|| "".to_string());
+    let mut edit_body = use_signal(|| "".to_string());
+    let mut file_path = use_signal(|| PROMPTS_EXPORT_PATH.to_string());
+    let mut status = use_signal(|| Option::<String>::None);
+
+    let mut clear_editor = move || {
+        editing.set(None);
+        edit_name.set("".to_string());
+        edit_description.set("".to_string());
+        edit_body.set("".to_string());
+    };
+
+    let save = move |_| {
+        let template = PromptTemplate {
+            name: edit_name().trim().to_string(),
+            description: edit_description().trim().to_string(),
+            body: edit_body(),
+        };
+        let conn = match try_init_db() {
+            Ok(conn) => conn,
+            Err(e) => return status.set(Some(e.to_string())),
+        };
+        match check_template(&template).and_then(|()| save_prompt_template(&conn, &template).map_err(|e| e.to_string())) {
+            Ok(()) => {
+                // saving under a new name renames the template
+                if let Some(old) = editing() {
+                    if old != template.name {
+                        if let Err(e) = delete_prompt_template(&conn, &old) {
+                            report_error(format!("Could not remove /{} after renaming it: {}", old, e));
+                        }
+                    }
+                }
+                status.set(Some(format!("Saved /{}", template.name)));
+                templates.set(or_report(list_prompt_templates(&conn), "load prompt templates"));
+                clear_editor();
+            }
+            Err(e) => status.set(Some(e)),
+        }
+    };
+
+    let variables = template_variables(&edit_body());
+
+    rsx! {
+        div { class: "settings-overlay",
+            div { class: "settings-modal models-modal",
+                h3 { "Prompt library" }
+                p { class: "dim-text",
+                    "Type / followed by a template name in the message box to insert it. Placeholders like {{{{language}}}} are asked for when the template is inserted."
+                }
+
+                {templates().into_iter().map(|t| {
+                    let for_edit = t.clone();
+                    let name_for_delete = t.name.clone();
+                    rsx! {
+                        div { class: "model-row",
+                            div { class: "model-row-head",
+                                span { class: "model-name", "/{t.name}" }
+                                span { class: "dim-text", "{t.description}" }
+                                button {
+                                    class: "model-action",
+                                    onclick: move |_| {
+                                        editing.set(Some(for_edit.name.clone()));
+                                        edit_name.set(for_edit.name.clone());
+                                        edit_description.set(for_edit.description.clone());
+                                        edit_body.set(for_edit.body.clone());
+                                        status.set(None);
+                                    },
+                                    "Edit"
+                                }
+                                button {
+                                    class: "model-action",
+                                    onclick: move |_| {
+                                        let conn = match try_init_db() {
+                                            Ok(conn) => conn,
+                                            Err(e) => return report_error(format!("Could not delete /{}: {}", name_for_delete, e)),
+                                        };
+                                        if let Err(e) = delete_prompt_template(&conn, &name_for_delete) {
+                                            return report_error(format!("Could not delete /{}: {}", name_for_delete, e));
+                                        }
+                                        if editing().as_deref() == Some(name_for_delete.as_str()) {
+                                            clear_editor();
+                                        }
+                                        templates.set(or_report(list_prompt_templates(&conn), "load prompt templates"));
+                                    },
+                                    "Delete"
+                                }
+                            }
+                        }
+                    }
+                })}
+                if templates().is_empty() {
+                    p { class: "dim-text", "No templates yet." }
+                }
+
+                label {
+                    if let Some(name) = editing() { "Edit /{name}" } else { "New template" }
+                }
+                input {
+                    class: "input",
+                    value: "{edit_name}",
+                    placeholder: "Name, used as the slash command (e.g. review)",
+                    oninput: move |e| edit_name.set(e.value()),
+                }
+                input {
+                    class: "input",
+                    value: "{edit_description}",
+                    placeholder: "Short description (optional)",
+                    oninput: move |e| edit_description.set(e.value()),
+                }
+                textarea {
+                    class: "textarea prompt-body",
+                    value: "{edit_body}",
+                    placeholder: "Review this {{{{language}}}} code for bugs:\n\n{{{{code}}}}",
+                    oninput: move |e| edit_body.set(e.value()),
+                }
+                if !variables.is_empty() {
+                    p { class: "dim-text", "Variables: {variables.join(\", \")}" }
+                }
+                div { class: "row",
+                    button {
+                        class: "rename-save",
+                        disabled: edit_name().trim().is_empty() || edit_body().trim().is_empty(),
+                        onclick: save,
+                        "Save template"
+                    }
+                    if editing().is_some() {
+                        button { class: "rename-cancel", onclick: move |_| clear_editor(), "Cancel" }
+                    }
+                }
+
+                label { "Import / export the library as JSON" }
+                div { class: "row",
+                    input {
+                        class: "input",
+                        value: "{file_path}",
+                        oninput: move |e| file_path.set(e.value()),
+                    }
+                    button {
+                        class: "model-action",
+                        onclick: move |_| {
+                            let conn = match try_init_db() {
+                                Ok(conn) => conn,
+                                Err(e) => return status.set(Some(format!("Import failed: {}", e))),
+                            };
+                            match import_prompt_templates(&conn, file_path().trim()) {
+                                Ok(n) => status.set(Some(format!("Imported {} templates", n))),
+                                Err(e) => status.set(Some(format!("Import failed: {}", e))),
+                            }
+                            templates.set(or_report(list_prompt_templates(&conn), "load prompt templates"));
+                        },
+                        "Import"
+                    }
+                    button {
+                        class: "model-action",
+                        onclick: move |_| {
+                            match export_prompt_templates(&init_db(), file_path().trim()) {
+                                Ok(n) => status.set(Some(format!("Exported {} templates to {}", n, file_path().trim()))),
+                                Err(e) => status.set(Some(format!("Export failed: {}", e))),
+                            }
+                        },
+                        "Export"
+                    }
+                }
+
+                if let Some(msg) = status() {
+                    p { class: "dim-text", "{msg}" }
+                }
+
+                div { class: "modal-actions",
+                    button { onclick: move |_| show_prompts.set(false), "Close" }
+                }
+            }
+        }
+    }
+}
+
+/* ================= PERSONAS ================= */
+
+// the persona select options ("" = no persona)
+fn persona_options(personas: &[Persona], selected: Option<i64>) -> Element {
+    rsx! {
+        option { selected: selected.is_none(), value: "", "No persona" }
+        {personas.iter().map(|p| {
+            let label = p.label();
+            rsx! { option { selected: selected == Some(p.id), value: "{p.id}", "{label}" } }
+        })}
+    }
+}
+
+#[component]
+fn PersonasModal(show_personas: Signal<bool>, personas_revision: Signal<u32>) -> Element {
+    let mut personas = use_signal(|| or_report(list_personas(&init_db()), "load personas"));
+    // persona being edited (id 0 = new); optional numbers are edited as text, "" = use settings
+    let mut draft = use_signal(Persona::default);
+    let mut temperature_text = use_signal(|| "".to_string());
+    let mut top_p_text = use_signal(|| "".to_string());
+    let mut max_tokens_text = use_signal(|| "".to_string());
+    let mut status = use_signal(|| Option::<String>::None);
+    let available_models = use_signal(Vec::<String>::new);
+
+    use_effect(move || {
+        let mut models_sig = available_models;
+        spawn(async move {
+            if let Ok(names) = fetch_model_names(&Client::new(), OLLAMA_BASE_URL).await {
+                models_sig.set(names);
+            }
+        });
+    });
+
+    let mut edit = move |p: Persona| {
+        temperature_text.set(p.temperature.map(|v| v.to_string()).unwrap_or_default());
+        top_p_text.set(p.top_p.map(|v| v.to_string()).unwrap_or_default());
+        max_tokens_text.set(p.max_tokens.map(|v| v.to_string()).unwrap_or_default());
+        draft.set(p);
+        status.set(None);
+    };
+
+    let save = move |_| {
+        // unparsable numbers are reported instead of silently dropped
+        let parse_opt = |text: String, what: &str| -> Result<Option<f64>, String> {
+            let t = text.trim();
+            if t.is_empty() {
+                Ok(None)
+            } else {
+                t.parse::<f64>().map(Some).map_err(|_| format!("{} must be a number", what))
+            }
+        };
+        let parsed = (|| -> Result<Persona, String> {
+            if draft().name.trim().is_empty() {
+                return Err("a persona needs a name".to_string());
+            }
+            Ok(Persona {
+                temperature: parse_opt(temperature_text(), "Temperature")?.map(|v| v.clamp(0.0, 2.0)),
+                top_p: parse_opt(top_p_text(), "Top P")?.map(|v| v.clamp(0.0, 1.0)),
+                max_tokens: parse_opt(max_tokens_text(), "Max tokens")?.map(|v| clamp_to_i32(v as i64).max(1)),
+                ..draft()
+            })
+        })();
+        let conn = match try_init_db() {
+            Ok(conn) => conn,
+            Err(e) => return status.set(Some(e.to_string())),
+        };
+        match parsed.and_then(|p| save_persona(&conn, &p).map_err(|e| e.to_string())) {
+            Ok(_) => {
+                status.set(Some(format!("Saved {}", draft().name.trim())));
+                personas.set(or_report(list_personas(&conn), "load personas"));
+                personas_revision += 1;
+                edit(Persona::default());
+            }
+            Err(e) => status.set(Some(e)),
+        }
+    };
+
+    let d = draft();
+    let color_value = d.accent().to_string();
+
+    rsx! {
+        div { class: "settings-overlay",
+            div { class: "settings-modal models-modal",
+                h3 { "Personas" }
+                p { class: "dim-text",
+                    "A persona bundles a system prompt, model and options. Pick one when creating a chat or from the chat header."
+                }
+
+                {personas().into_iter().map(|p| {
+                    let for_edit = p.clone();
+                    let id = p.id;
+                    let label = p.label();
+                    let summary = if p.model.is_empty() { "settings model".to_string() } else { p.model.clone() };
+                    let accent = p.accent().to_string();
+                    rsx! {
+                        div { class: "model-row persona-row", style: "border-left-color: {accent};",
+                            div { class: "model-row-head",
+                                span { class: "model-name", "{label}" }
+                                span { class: "dim-text", "{summary}" }
+                                button {
+                                    class: "model-action",
+                                    onclick: move |_| edit(for_edit.clone()),
+                                    "Edit"
+                                }
+                                button {
+                                    class: "model-action",
+                                    onclick: move |_| {
+                                        let conn = match try_init_db() {
+                                            Ok(conn) => conn,
+                                            Err(e) => return report_error(format!("Could not delete the persona: {}", e)),
+                                        };
+                                        if let Err(e) = delete_persona(&conn, id) {
+                                            return report_error(format!("Could not delete the persona: {}", e));
+                                        }
+                                        if draft().id == id {
+                                            edit(Persona::default());
+                                        }
+                                        personas.set(or_report(list_personas(&conn), "load personas"));
+                                        personas_revision += 1;
+                                    },
+                                    "Delete"
+                                }
+                            }
+                        }
+                    }
+                })}
+                if personas().is_empty() {
+                    p { class: "dim-text", "No personas yet." }
+                }
+
+                label { if d.id == 0 { "New persona" } else { "Edit persona" } }
+                div { class: "row",
+                    input {
+                        class: "input persona-avatar",
+                        value: "{d.avatar}",
+                        placeholder: "🙂",
+                        maxlength: "4",
+                        oninput: move |e| draft.write().avatar = e.value(),
+                    }
+                    input {
+                        class: "input",
+                        value: "{d.name}",
+                        placeholder: "Name (e.g. Code reviewer)",
+                        oninput: move |e| draft.write().name = e.value(),
+                    }
+                    input {
+                        r#type: "color",
+                        value: "{color_value}",
+                        oninput: move |e| draft.write().color = e.value(),
+                    }
+                }
+
+                label { "System prompt (empty = the one from Settings)" }
+                textarea {
+                    class: "textarea",
+                    value: "{d.system_prompt}",
+                    oninput: move |e| draft.write().system_prompt = e.value(),
+                }
+
+                label { "Model" }
+                select {
+                    class: "input",
+                    value: "{d.model}",
+                    onchange: move |e| draft.write().model = e.value(),
+                    option { selected: d.model.is_empty(), value: "", "- Use the model from Settings -" }
+                    {available_models().iter().map(|m| rsx!( option { selected: m == &d.model, value: "{m}", "{m}" } ))}
+                    if !d.model.is_empty() && !available_models().contains(&d.model) {
+                        option { selected: true, value: "{d.model}", "{d.model}" }
+                    }
+                }
+
+                label { "Options (empty = use Settings)" }
+                div { class: "row",
+                    input {
+                        class: "input",
+                        value: "{temperature_text}",
+                        placeholder: "Temperature",
+                        oninput: move |e| temperature_text.set(e.value()),
+                    }
+                    input {
+                        class: "input",
+                        value: "{top_p_text}",
+                        placeholder: "Top P",
+                        oninput: move |e| top_p_text.set(e.value()),
+                    }
+                    input {
+                        class: "input",
+                        value: "{max_tokens_text}",
+                        placeholder: "Max tokens",
+                        oninput: move |e| max_tokens_text.set(e.value()),
+                    }
+                }
+
+                div { class: "row",
+                    button {
+                        class: "rename-save",
+                        disabled: d.name.trim().is_empty(),
+                        onclick: save,
+                        "Save persona"
+                    }
+                    if d.id != 0 {
+                        button { class: "rename-cancel", onclick: move |_| edit(Persona::default()), "Cancel" }
+                    }
+                }
+
+                if let Some(msg) = status() {
+                    p { class: "dim-text", "{msg}" }
+                }
+
+                div { class: "modal-actions",
+                    button { onclick: move |_| show_personas.set(false), "Close" }
+                }
+            }
+        }
+    }
+}
+
+/* ================= MODEL COMPARISON ================= */
+
+// a comparison runs one prompt against this many models/variants at most
+const MAX_COMPARE_COLUMNS: usize = 4;
+
+// one column of a comparison: a model and the options it runs with
+#[derive(Clone, Debug, PartialEq)]
+struct CompareVariant {
+    model: String,
+    temperature: f64,
+    top_p: f64,
+}
+
+impl CompareVariant {
+    fn params(&self, max_tokens: i32) -> Value {
+        sampling_options(self.temperature, self.top_p, max_tokens)
+    }
+}
+
+// stream one column's reply into `columns[index]`, then store it; like a chat send it waits
+// for a free generation slot first (`limit` = the max_generations setting)
+async fn run_compare_column(
+    client: Client,
+    request: OllamaChatRequest,
+    index: usize,
+    mut columns: Signal<Vec<CompareColumn>>,
+    limit: i32,
+) {
+    // closing the panel drops this task, and with it the place in the queue
+    let _slot = GENERATION_SLOTS.acquire(limit, || false).await;
+    let started = std::time::Instant::now();
+    let result = stream_chat_lines(&client, OLLAMA_BASE_URL, &request, |line| {
+        let mut cols = columns.write();
+        let Some(col) = cols.get_mut(index) else {
+            return;
+        };
+        if let Some(err) = line.get("error").and_then(|e| e.as_str()) {
+            col.error = Some(err.to_string());
+        }
+        let piece = line
+            .get("message")
+            .and_then(|m| m.get("content"))
+            .and_then(|c| c.as_str())
+            .unwrap_or("");
+        if !piece.is_empty() {
+            if col.first_token_ms.is_none() {
+                col.first_token_ms = Some(started.elapsed().as_millis() as i64);
+            }
+            col.content.push_str(piece);
+        }
+        if line.get("done").and_then(|d| d.as_bool()).unwrap_or(false) {
+            let count = line.get("eval_count").and_then(|v| v.as_i64());
+            let duration_ns = line.get("eval_duration").and_then(|v| v.as_i64());
+            col.eval_count = count;
+            if let (Some(n), Some(ns)) = (count, duration_ns) {
+                if ns > 0 {
+                    col.tokens_per_sec = Some(n as f64 / (ns as f64 / 1e9));
+                }
+            }
+        }
+    })
+    .await;
+
+    let finished = {
+        let mut cols = columns.write();
+        let Some(col) = cols.get_mut(index) else {
+            return;
+        };
+        if let Err(e) = result {
+            col.error = Some(e);
+        }
+        col.total_ms = Some(started.elapsed().as_millis() as i64);
+        col.done = true;
+        col.clone()
+    };
+    if let Err(e) = try_init_db().and_then(|conn| finish_compare_reply(&conn, &finished)) {
+        report_error(format!("Could not save {}'s reply to the comparison: {}", finished.model, e));
+    }
+}
+
+// compare view: one prompt, several models side by side
+#[component]
+fn ComparePanel(
+    chat_id: String,
+    messages: Signal<Vec<ChatMessage>>,
+    settings: Signal<Settings>,
+    show_compare: Signal<bool>,
+) -> Element {
+    let http_client = use_signal(Client::new);
+    let available_models = use_signal(Vec::<String>::new);
+    let mut variants = use_signal(|| {
+        let s = settings();
+        vec![
+            CompareVariant { model: s.model.clone(), temperature: s.temperature, top_p: s.top_p },
+            CompareVariant { model: "".to_string(), temperature: s.temperature, top_p: s.top_p },
+        ]
+    });
+    let mut prompt = use_signal(|| "".to_string());
+    let mut columns = use_signal(Vec::<CompareColumn>::new);
+    let mut comparison_id = use_signal(|| Option::<i64>::None);
+    let mut panel_error = use_signal(|| Option::<String>::None);
+    let mut history = use_signal({
+        let chat_id = chat_id.clone();
+        move || or_report(list_comparisons(&init_db(), &chat_id), "load earlier comparisons")
+    });
+
+    use_effect(move || {
+        let mut models_sig = available_models;
+        spawn(async move {
+            if let Ok(names) = fetch_model_names(&http_client(), OLLAMA_BASE_URL).await {
+                models_sig.set(names);
+            }
+        });
+    });
+
+    let running = columns().iter().any(|c| !c.done);
+
+    let run = {
+        let chat_id = chat_id.clone();
+        move |_| {
+            let text = prompt().trim().to_string();
+            let chosen: Vec<CompareVariant> = variants()
+                .into_iter()
+                .filter(|v| !v.model.trim().is_empty())
+                .collect();
+            if text.is_empty() || chosen.len() < 2 {
+                panel_error.set(Some("Enter a prompt and pick at least two models.".to_string()));
+                return;
+            }
+            panel_error.set(None);
+
+            // same context as a normal send: the chat's (persona) system prompt and history
+            let conn = match try_init_db() {
+                Ok(conn) => conn,
+                Err(e) => return panel_error.set(Some(e.to_string())),
+            };
+            let base = match chat_persona(&conn, &chat_id) {
+                Ok(Some(p)) => apply_persona(&settings(), &p),
+                Ok(None) => settings(),
+                Err(e) => return panel_error.set(Some(e.to_string())),
+            };
+            let mut context = Vec::new();
+            if !base.system_prompt.is_empty() {
+                context.push(OllamaMessage::new("system", base.system_prompt.clone()));
+            }
+            context.extend(messages().iter().map(OllamaMessage::from_chat));
+            context.push(OllamaMessage::new("user", text.clone()));
+
+            let stored_columns: Vec<(String, String)> = chosen
+                .iter()
+                .map(|v| (v.model.clone(), v.params(base.max_tokens).to_string()))
+                .collect();
+            let (id, reply_ids) = match storage::create_comparison(&conn, &chat_id, &text, &stored_columns) {
+                Ok(ids) => ids,
+                Err(e) => {
+                    panel_error.set(Some(e.to_string()));
+                    return;
+                }
+            };
+            comparison_id.set(Some(id));
+            columns.set(
+                chosen
+                    .iter()
+                    .zip(&reply_ids)
+                    .map(|(v, reply_id)| CompareColumn {
+                        reply_id: *reply_id,
+                        model: v.model.clone(),
+                        options: v.params(base.max_tokens).to_string(),
+                        ..CompareColumn::default()
+                    })
+                    .collect(),
+            );
+
+            // every column streams on its own task so they fill in side by side
+            for (index, variant) in chosen.iter().enumerate() {
+                let request = OllamaChatRequest {
+                    model: variant.model.clone(),
+                    messages: context.clone(),
+                    stream: true,
+                    options: Some(variant.params(base.max_tokens)),
+                    tools: None,
+                    format: None,
+                };
+                spawn(run_compare_column(http_client(), request, index, columns, settings().max_generations));
+            }
+        }
+    };
+
+    // the chosen reply (and the prompt) become the next turn of the chat
+    let continue_with = {
+        let chat_id = chat_id.clone();
+        move |index: usize| {
+            let (Some(id), Some(col)) = (comparison_id(), columns().get(index).cloned()) else {
+                return;
+            };
+            let conn = match try_init_db() {
+                Ok(conn) => conn,
+                Err(e) => return report_error(format!("Could not add the reply to the chat: {}", e)),
+            };
+            let question = ChatMessage::new("user", prompt().trim());
+            let reply = ChatMessage {
+                model: Some(col.model.clone()),
+                ..ChatMessage::new("assistant", col.content.clone())
+            };
+            let meta = GenerationMeta {
+                endpoint: OLLAMA_BASE_URL.to_string(),
+                system_prompt_hash: None,
+                options: Some(col.options.clone()),
+            };
+            let stored = open_store().and_then(|s| {
+                s.insert_message(&chat_id, &question, None)?;
+                s.insert_message(&chat_id, &reply, Some(&meta))
+            });
+            if let Err(e) = stored {
+                return report_error(format!("Could not add the reply to the chat: {}", e));
+            }
+            if let Err(e) = storage::choose_compare_reply(&conn, id, index) {
+                report_error(format!("Could not mark the chosen reply: {}", e));
+            }
+
+            messages.push(question);
+            messages.push(reply);
+            prompt.set("".to_string());
+            columns.set(vec![]);
+            comparison_id.set(None);
+            history.set(or_report(list_comparisons(&conn, &chat_id), "load earlier comparisons"));
+            show_compare.set(false);
+        }
+    };
+
+    let column_count = columns().len().max(1);
+
+    rsx! {
+        div { class: "structured-panel compare-panel",
+            {variants().into_iter().enumerate().map(|(i, v)| {
+                let model_value = v.model.clone();
+                rsx! {
+                    div { class: "row",
+                        select {
+                            class: "input",
+                            value: "{model_value}",
+                            onchange: move |e| variants.write()[i].model = e.value(),
+                            option { selected: v.model.is_empty(), value: "", "- Select a model -" }
+                            {available_models().iter().map(|m| rsx!( option { selected: m == &v.model, value: "{m}", "{m}" } ))}
+                        }
+                        input {
+                            class: "input compare-num",
+                            r#type: "number",
+                            step: "0.05",
+                            min: "0",
+                            max: "2",
+                            title: "Temperature",
+                            value: "{v.temperature}",
+                            oninput: move |e| {
+                                if let Ok(t) = e.value().parse::<f64>() {
+                                    variants.write()[i].temperature = t.clamp(0.0, 2.0);
+                                }
+                            },
+                        }
+                        input {
+                            class: "input compare-num",
+                            r#type: "number",
+                            step: "0.05",
+                            min: "0",
+                            max: "1",
+                            title: "Top P",
+                            value: "{v.top_p}",
+                            oninput: move |e| {
+                                if let Ok(p) = e.value().parse::<f64>() {
+                                    variants.write()[i].top_p = p.clamp(0.0, 1.0);
+                                }
+                            },
+                        }
+                        if variants().len() > 2 {
+                            button {
+                                class: "rename-cancel",
+                                onclick: move |_| {
+                                    variants.write().remove(i);
+                                },
+                                "✕"
+                            }
+                        }
+                    }
+                }
+            })}
+            div { class: "row",
+                if variants().len() < MAX_COMPARE_COLUMNS {
+                    button {
+                        class: "rename-btn",
+                        onclick: move |_| {
+                            let s = settings();
+                            variants.push(CompareVariant { model: "".to_string(), temperature: s.temperature, top_p: s.top_p });
+                        },
+                        "+ Add model"
+                    }
+                }
+            }
+
+            textarea {
+                class: "textarea",
+                value: "{prompt}",
+                placeholder: "Prompt sent to every model…",
+                oninput: move |e| prompt.set(e.value()),
+            }
+            div { class: "row",
+                button {
+                    class: "rename-save",
+                    disabled: running || prompt().trim().is_empty(),
+                    onclick: run,
+                    if running { "Running…" } else { "Run comparison" }
+                }
+                button { class: "rename-cancel", onclick: move |_| show_compare.set(false), "Close" }
+            }
+            if let Some(err) = panel_error() {
+                p { class: "dim-text warning-text", "{err}" }
+            }
+
+            if !columns().is_empty() {
+                div { class: "compare-columns", style: "grid-template-columns: repeat({column_count}, minmax(0, 1fr));",
+                    {columns().into_iter().enumerate().map(|(i, col)| {
+                        let metrics = col.metrics();
+                        let mut continue_with = continue_with.clone();
+                        rsx! {
+                            div { class: "compare-column",
+                                span { class: "model-badge", "{col.model}" }
+                                p { class: "tool-label", "{metrics}" }
+                                if let Some(err) = col.error.clone() {
+                                    p { class: "dim-text warning-text", "Error: {err}" }
+                                }
+                                p { class: "dim-text compare-content", "{col.content}" }
+                                if !col.done {
+                                    div { class: "loading-dots" }
+                                } else if col.error.is_none() && !col.content.is_empty() && !running {
+                                    button {
+                                        class: "rename-save",
+                                        onclick: move |_| continue_with(i),
+                                        "Continue with this one"
+                                    }
+                                }
+                            }
+                        }
+                    })}
+                }
+            }
+
+            if !history().is_empty() {
+                details { class: "compare-history",
+                    summary { "Earlier comparisons in this chat ({history().len()})" }
+                    {history().into_iter().map(|c| {
+                        let count = c.columns.len().max(1);
+                        rsx! {
+                            div { class: "compare-history-item",
+                                p { class: "tool-label", "{c.created_at} · {c.prompt}" }
+                                div { class: "compare-columns", style: "grid-template-columns: repeat({count}, minmax(0, 1fr));",
+                                    {c.columns.iter().enumerate().map(|(i, col)| {
+                                        let metrics = col.metrics();
+                                        let chosen = c.chosen == Some(i as i64);
+                                        rsx! {
+                                            div { class: if chosen { "compare-column compare-chosen" } else { "compare-column" },
+                                                span { class: "model-badge", "{col.model}" }
+                                                if chosen {
+                                                    span { class: "tool-label", " ✓ continued" }
+                                                }
+                                                p { class: "tool-label", "{metrics}" }
+                                                if let Some(err) = col.error.clone() {
+                                                    p { class: "dim-text warning-text", "Error: {err}" }
+                                                }
+                                                p { class: "dim-text compare-content", "{col.content}" }
+                                            }
+                                        }
+                                    })}
+                                }
+                            }
+                        }
+                    })}
+                }
+            }
+        }
+    }
+}
+
+/* ================= TOOLS ================= */
+
+// read_file refuses to read more than this from a single file
+const MAX_TOOL_FILE_BYTES: u64 = 64 * 1024;
+// query_history returns at most this many rows and gives up after this long
+const MAX_TOOL_ROWS: usize = 50;
+const TOOL_QUERY_TIMEOUT_SECS: u64 = 5;
+
+// definitions of the built-in tools offered to the model; read_file only when a folder was approved
+fn tool_definitions(settings: &Settings) -> Vec<Value> {
+    let mut tools = vec![
+        tool_definition(
+            "current_time",
+            "Get the current local date, time and weekday, plus the UTC time.",
+            serde_json::json!({ "type": "object", "properties": {} }),
+        ),
+        tool_definition(
+            "calculator",
+            "Evaluate an arithmetic expression. Supports + - * / % ^, parentheses, pi, e and the functions sqrt, abs, exp, ln, log, sin, cos, tan, floor, ceil, round.",
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "expression": { "type": "string", "description": "e.g. (3 + 4) * sqrt(2)" }
+                },
+                "required": ["expression"]
+            }),
+        ),
+        tool_definition(
+            "query_history",
+            "Run a read-only SQLite SELECT over the user's chat history (chats in the Trash are left out). Tables: chats(id TEXT, title TEXT, created_at DATETIME, updated_at DATETIME, pinned INTEGER, archived INTEGER, folder_id INTEGER) and messages(id INTEGER, chat_id TEXT, role TEXT, content TEXT, timestamp DATETIME, model TEXT, persona TEXT, tool_name TEXT, tool_calls TEXT). No other tables can be read.",
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "sql": { "type": "string", "description": "a single SELECT statement" }
+                },
+                "required": ["sql"]
+            }),
+        ),
+    ];
+
+    if !settings.tool_folder.trim().is_empty() {
+        tools.push(tool_definition(
+            "read_file",
+            "Read a text file (or list a directory) inside the folder the user approved. Paths are relative to that folder; use \".\" to list it.",
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "path relative to the approved folder" }
+                },
+                "required": ["path"]
+            }),
+        ));
+    }
+
+    tools
+}
+
+// run one tool call; errors are handed back to the model as text so it can recover
+fn run_tool(name: &str, arguments: &Value, settings: &Settings) -> String {
+    // some models send the arguments as a JSON string instead of an object
+    let args = match arguments {
+        Value::String(raw) => serde_json::from_str::<Value>(raw).unwrap_or(Value::Null),
+        other => other.clone(),
+    };
+    let arg = |key: &str| -> Result<String, String> {
+        args.get(key)
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+            .ok_or_else(|| format!("missing argument '{}'", key))
+    };
+
+    let result = match name {
+        "current_time" => tool_current_time(),
+        "calculator" => arg("expression").and_then(|e| eval_expression(&e).map(format_number)),
+        "read_file" => arg("path").and_then(|p| tool_read_file(&settings.tool_folder, &p)),
+        "query_history" => arg("sql").and_then(|q| tool_query_history(&q)),
+        other => Err(format!("unknown tool '{}'", other)),
+    };
+
+    truncate_tool_output(match result {
+        Ok(out) => out,
+        Err(e) => format!("Error: {}", e),
+    })
+}
+
+fn tool_current_time() -> Result<String, String> {
+    const WEEKDAYS: [&str; 7] = [
+        "Sunday",
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+    ];
+    let (local, utc, weekday) = storage::current_time().map_err(|e| e.to_string())?;
+    let day = WEEKDAYS.get(weekday as usize).copied().unwrap_or("");
+    Ok(format!("Local time: {} ({}). UTC: {}", local, day, utc))
+}
+
+fn tool_read_file(folder: &str, path: &str) -> Result<String, String> {
+    if folder.trim().is_empty() {
+        return Err("no folder has been approved for file access".to_string());
+    }
+    let root = std::fs::canonicalize(folder.trim())
+        .map_err(|e| format!("approved folder is not accessible: {}", e))?;
+    let target = std::fs::canonicalize(root.join(path))
+        .map_err(|e| format!("cannot open '{}': {}", path, e))?;
+    // canonical paths resolve ".." and symlinks, so this also catches escapes through them
+    if !target.starts_with(&root) {
+        return Err(format!("'{}' is outside the approved folder", path));
+    }
+
+    if target.is_dir() {
+        let mut entries: Vec<String> = std::fs::read_dir(&target)
+            .map_err(|e| e.to_string())?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.path().is_dir() {
+                    name + "/"
+                } else {
+                    name
+                }
+            })
+            .collect();
+        entries.sort();
+        return Ok(entries.join("\n"));
+    }
+
+    let size = std::fs::metadata(&target).map_err(|e| e.to_string())?.len();
+    let mut bytes = Vec::new();
+    std::io::Read::read_to_end(
+        &mut std::io::Read::take(
+            std::fs::File::open(&target).map_err(|e| e.to_string())?,
+            MAX_TOOL_FILE_BYTES,
+        ),
+        &mut bytes,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut text = String::from_utf8_lossy(&bytes).to_string();
+    if size > MAX_TOOL_FILE_BYTES {
+        text.push_str(&format!(
+            "\n[file truncated: showing {} of {} bytes]",
+            MAX_TOOL_FILE_BYTES, size
+        ));
+    }
+    Ok(text)
+}
+
+// only sees chats and messages outside the Trash; see storage::query_history
+fn tool_query_history(sql: &str) -> Result<String, String> {
+    let (rows, truncated) =
+        storage::query_history(&db_path(), sql, MAX_TOOL_ROWS, Duration::from_secs(TOOL_QUERY_TIMEOUT_SECS))
+            .map_err(|e| e.to_string())?;
+    let mut text = serde_json::to_string(&rows).map_err(|e| e.to_string())?;
+    if truncated {
+        text.push_str(&format!("\n[only the first {} rows shown]", MAX_TOOL_ROWS));
+    }
+    Ok(text)
+}
+
+/* ---------- user-defined command tools ---------- */
+
+// a tool declared in tools.json; `command` is a template with {{argument}} placeholders
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+struct UserTool {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default = "default_tool_parameters")]
+    parameters: Value,
+    command: String,
+    #[serde(default = "default_tool_timeout")]
+    timeout_secs: u64,
+}
+
+fn default_tool_parameters() -> Value {
+    serde_json::json!({ "type": "object", "properties": {} })
+}
+
+fn default_tool_timeout() -> u64 {
+    30
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ApprovalDecision {
+    AllowOnce,
+    AlwaysAllow,
+    Deny,
+}
+
+// a command tool call waiting for the user to approve or deny it
+#[derive(Clone)]
+struct PendingApproval {
+    chat_id: String,
+    tool: String,
+    arguments: String,
+    command: String,
+    responder: Arc<Mutex<Option<oneshot::Sender<ApprovalDecision>>>>,
+}
+
+impl PendingApproval {
+    fn respond(&self, decision: ApprovalDecision) {
+        if let Some(tx) = self.responder.lock().ok().and_then(|mut r| r.take()) {
+            let _ = tx.send(decision);
+        }
+    }
+}
+
+// read tools.json; a missing file just means no user tools
+fn load_user_tools() -> Result<Vec<UserTool>, String> {
+    let text = match std::fs::read_to_string(USER_TOOLS_PATH) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(format!("{}: {}", USER_TOOLS_PATH, e)),
+    };
+    let tools: Vec<UserTool> =
+        serde_json::from_str(&text).map_err(|e| format!("{}: {}", USER_TOOLS_PATH, e))?;
+
+    let builtins = ["current_time", "calculator", "read_file", "query_history"];
+    let mut seen = HashSet::new();
+    for tool in &tools {
+        if tool.name.is_empty()
+            || !tool
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!("invalid tool name '{}'", tool.name));
+        }
+        if builtins.contains(&tool.name.as_str()) || !seen.insert(tool.name.clone()) {
+            return Err(format!("tool name '{}' is already taken", tool.name));
+        }
+    }
+    Ok(tools)
+}
+
+// definitions for the user's tools, leaving out the ones set to deny
+fn user_tool_definitions(conn: &Connection, tools: &[UserTool]) -> Vec<Value> {
+    tools
+        .iter()
+        .filter(|t| load_tool_policy(conn, &t.name) != ToolPolicy::Deny)
+        .map(|t| tool_definition(&t.name, &t.description, t.parameters.clone()))
+        .collect()
+}
+
+// run a rendered command through the shell, killing it when the timeout expires
+async fn run_user_command(command: &str, timeout_secs: u64) -> (String, Option<i32>) {
+    let mut cmd = if cfg!(windows) {
+        let mut c = tokio::process::Command::new("cmd");
+        c.arg("/C").arg(command);
+        c
+    } else {
+        let mut c = tokio::process::Command::new("sh");
+        c.arg("-c").arg(command);
+        c
+    };
+    cmd.stdin(std::process::Stdio::null()).kill_on_drop(true);
+
+    match tokio::time::timeout(Duration::from_secs(timeout_secs.max(1)), cmd.output()).await {
+        Ok(Ok(output)) => {
+            let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if !stderr.trim().is_empty() {
+                text.push_str("\n[stderr]\n");
+                text.push_str(&stderr);
+            }
+            let code = output.status.code();
+            if !output.status.success() {
+                text.push_str(&format!(
+                    "\n[exit code {}]",
+                    code.map(|c| c.to_string()).unwrap_or_else(|| "unknown".into())
+                ));
+            }
+            (text, code)
+        }
+        Ok(Err(e)) => (format!("Error: could not start command: {}", e), None),
+        Err(_) => (
+            format!("Error: command timed out after {} seconds", timeout_secs),
+            None,
+        ),
+    }
+}
+
+// show an approval prompt in the chat window and wait for the user's answer
+async fn request_tool_approval(
+    mut approvals: Signal<Vec<PendingApproval>>,
+    chat_id: &str,
+    tool: &str,
+    arguments: &str,
+    command: &str,
+) -> ApprovalDecision {
+    let (tx, rx) = oneshot::channel();
+    approvals.push(PendingApproval {
+        chat_id: chat_id.to_string(),
+        tool: tool.to_string(),
+        arguments: arguments.to_string(),
+        command: command.to_string(),
+        responder: Arc::new(Mutex::new(Some(tx))),
+    });
+    // a dropped prompt (e.g. interrupted generation) counts as a denial
+    rx.await.unwrap_or(ApprovalDecision::Deny)
+}
+
+// run one tool call from the model: built-ins directly, user tools subject to their policy.
+// Every run is written to the tool_runs audit log.
+async fn execute_tool_call(
+    chat_id: &str,
+    call: &OllamaFunctionCall,
+    settings: &Settings,
+    user_tools: &[UserTool],
+    approvals: Signal<Vec<PendingApproval>>,
+) -> String {
+    let started = std::time::Instant::now();
+    let arguments = call.arguments.to_string();
+
+    let Some(tool) = user_tools.iter().find(|t| t.name == call.name) else {
+        // built-in tools read files and the database, so they run off the UI thread
+        let (name, args, tool_settings) = (call.name.clone(), call.arguments.clone(), settings.clone());
+        let output = tokio::task::spawn_blocking(move || run_tool(&name, &args, &tool_settings))
+            .await
+            .unwrap_or_else(|e| format!("Error: {}", e));
+        let run = ToolRun {
+            chat_id,
+            tool: &call.name,
+            arguments: &arguments,
+            command: None,
+            decision: "builtin",
+            exit_code: None,
+            output: &output,
+            duration_ms: started.elapsed().as_millis() as i64,
+        };
+        let logged = try_init_db().and_then(|conn| storage::log_tool_run(&conn, &run));
+        if let Err(e) = logged {
+            report_error(format!("The run of '{}' was not written to the tool log: {}", call.name, e));
+        }
+        return output;
+    };
+
+    let args = match &call.arguments {
+        Value::String(raw) => serde_json::from_str::<Value>(raw).unwrap_or(Value::Null),
+        other => other.clone(),
+    };
+    let command = match render_command(&tool.command, &args) {
+        Ok(command) => command,
+        Err(e) => return format!("Error: {}", e),
+    };
+
+    let policy = load_tool_policy(&init_db(), &tool.name);
+    let decision = match policy {
+        ToolPolicy::Allow => ApprovalDecision::AllowOnce,
+        ToolPolicy::Deny => ApprovalDecision::Deny,
+        ToolPolicy::Ask => {
+            request_tool_approval(approvals, chat_id, &tool.name, &arguments, &command).await
+        }
+    };
+    if decision == ApprovalDecision::AlwaysAllow {
+        if let Err(e) = try_init_db().and_then(|conn| save_tool_policy(&conn, &tool.name, ToolPolicy::Allow)) {
+            report_error(format!("Could not remember to always allow '{}': {}", tool.name, e));
+        }
+    }
+
+    let (output, exit_code, decision_label) = if decision == ApprovalDecision::Deny {
+        (
+            format!("Error: the user did not allow running '{}'", tool.name),
+            None,
+            "denied",
+        )
+    } else {
+        let (output, code) = run_user_command(&command, tool.timeout_secs).await;
+        (
+            truncate_tool_output(output),
+            code,
+            if policy == ToolPolicy::Allow { "auto" } else { "approved" },
+        )
+    };
+
+    let run = ToolRun {
+        chat_id,
+        tool: &tool.name,
+        arguments: &arguments,
+        command: Some(&command),
+        decision: decision_label,
+        exit_code,
+        output: &output,
+        duration_ms: started.elapsed().as_millis() as i64,
+    };
+    let logged = try_init_db().and_then(|conn| storage::log_tool_run(&conn, &run));
+    if let Err(e) = logged {
+        report_error(format!("The run of '{}' was not written to the tool log: {}", tool.name, e));
+    }
+    output
+}
+
+/* ================= ERRORS ================= */
+
+// show a failed request right away if its chat is open (`record` is it stored, or why it isn't)
+fn show_chat_error(
+    mut chat_errors: Signal<Vec<ChatErrorRecord>>,
+    current_chat_id: Signal<Option<String>>,
+    chat_id: &str,
+    err: &ChatError,
+    user_message: &str,
+    record: Result<ChatErrorRecord, StorageError>,
+) {
+    // shown even if it can't be stored; it just won't be there after a restart
+    let record = record.unwrap_or_else(|e| {
+        report_error(format!("Could not store the failed request: {}", e));
+        ChatErrorRecord {
+            id: 0,
+            kind: err.kind().to_string(),
+            message: err.to_string(),
+            user_message: Some(user_message.to_string()),
+        }
+    });
+    if current_chat_id.peek().as_deref() == Some(chat_id) {
+        chat_errors.push(record);
+    }
+}
+
+/* ================= CHAT WINDOW ================= */
+
+// one generation limit for chat sends, compare columns, the local API and the proxy
+static GENERATION_SLOTS: LazyLock<GenerationSlots> = LazyLock::new(GenerationSlots::default);
+
+// a request in flight for one chat
+#[derive(Clone, Debug)]
+struct ChatRun {
+    cancel: Arc<AtomicBool>,
+    // None while waiting for a free slot under the concurrency limit; removing the run from
+    // `active_runs` (interrupt, delete) drops it, which frees the slot right away
+    slot: Option<Rc<SlotGuard>>,
+}
+
+impl ChatRun {
+    fn running(&self) -> bool {
+        self.slot.is_some()
+    }
+}
+
+// forget a chat's run, unless it was interrupted and replaced by a newer one meanwhile
+fn finish_chat_run(
+    mut active_runs: Signal<HashMap<String, ChatRun>>,
+    chat_id: &str,
+    cancel: &Arc<AtomicBool>,
+) {
+    let same = active_runs
+        .peek()
+        .get(chat_id)
+        .map(|r| Arc::ptr_eq(&r.cancel, cancel))
+        .unwrap_or(false);
+    if same {
+        active_runs.write().remove(chat_id);
+    }
+}
+
+// wait for a free generation slot (limit <= 0 = no limit) and hand it to the chat's run;
+// returns false if the run was interrupted meanwhile
+// follows a chat send in the window: the partial reply, retry notes, tool approvals, stored
+// messages and errors of the visible chat, and the run's slot
+struct WindowReply {
+    chat_id: String,
+    user_message: String,
+    cancel: Arc<AtomicBool>,
+    settings: Settings,
+    user_tools: Vec<UserTool>,
+    active_runs: Signal<HashMap<String, ChatRun>>,
+    messages: Signal<Vec<ChatMessage>>,
+    partial_replies: Signal<HashMap<String, String>>,
+    retry_notes: Signal<HashMap<String, String>>,
+    chat_errors: Signal<Vec<ChatErrorRecord>>,
+    current_chat_id: Signal<Option<String>>,
+    tool_approvals: Signal<Vec<PendingApproval>>,
+}
+
+impl WindowReply {
+    fn visible(&self) -> bool {
+        self.current_chat_id.peek().as_deref() == Some(self.chat_id.as_str())
+    }
+}
+
+impl ReplyHooks for WindowReply {
+    // the slot lives in the chat's run, so interrupting frees it right away
+    fn started(&mut self, slot: SlotGuard) {
+        if let Some(run) = self.active_runs.write().get_mut(&self.chat_id) {
+            if Arc::ptr_eq(&run.cancel, &self.cancel) {
+                run.slot = Some(Rc::new(slot));
+            }
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed) || !self.active_runs.peek().contains_key(&self.chat_id)
+    }
+
+    fn partial(&mut self, text: Option<&str>) {
+        match text {
+            Some(text) => self.partial_replies.write().insert(self.chat_id.clone(), text.to_string()),
+            None => self.partial_replies.write().remove(&self.chat_id),
+        };
+    }
+
+    fn retrying(&mut self, note: Option<String>) {
+        match note {
+            Some(note) => self.retry_notes.write().insert(self.chat_id.clone(), note),
+            None => self.retry_notes.write().remove(&self.chat_id),
+        };
+    }
+
+    // push into in-memory messages only if that chat is currently visible
+    fn stored(&mut self, message: &ChatMessage) {
+        if self.visible() {
+            self.messages.push(message.clone());
+        }
+    }
+
+    fn failed(&mut self, err: &ChatError, record: Result<ChatErrorRecord, StorageError>) {
+        show_chat_error(self.chat_errors, self.current_chat_id, &self.chat_id, err, &self.user_message, record);
+    }
+
+    async fn run_tool(&mut self, call: &OllamaFunctionCall) -> String {
+        execute_tool_call(&self.chat_id, call, &self.settings, &self.user_tools, self.tool_approvals).await
+    }
+}
+
+// (chat title, start of the latest assistant message) for a background notification
+fn background_reply_summary(conn: &Connection, chat_id: &str) -> (String, String) {
+    let (title, reply) = storage::latest_reply(conn, chat_id).unwrap_or_else(|_| ("RustyChat".to_string(), String::new()));
+    let mut preview: String = reply.chars().take(140).collect();
+    if reply.chars().count() > 140 {
+        preview.push('…');
+    }
+    (title, preview)
+}
+
+// best-effort desktop notification through the platform's own tool; failures are only logged
+fn desktop_notify(title: &str, body: &str) {
+    #[cfg(target_os = "linux")]
+    let mut cmd = {
+        let mut c = std::process::Command::new("notify-send");
+        c.arg("--app-name=RustyChat").arg(title).arg(body);
+        c
+    };
+    #[cfg(target_os = "macos")]
+    let mut cmd = {
+        // AppleScript string literals: escape backslashes and quotes
+        let esc = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut c = std::process::Command::new("osascript");
+        c.arg("-e").arg(format!(
+            "display notification \"{}\" with title \"{}\"",
+            esc(body),
+            esc(title)
+        ));
+        c
+    };
+    #[cfg(target_os = "windows")]
+    let mut cmd = {
+        // title and body reach PowerShell as environment variables, so they need no quoting
+        let script = "[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
+            $xml = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+            $t = $xml.GetElementsByTagName('text'); \
+            $t.Item(0).AppendChild($xml.CreateTextNode($env:RC_TITLE)) > $null; \
+            $t.Item(1).AppendChild($xml.CreateTextNode($env:RC_BODY)) > $null; \
+            [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('RustyChat').Show([Windows.UI.Notifications.ToastNotification]::new($xml))";
+        let mut c = std::process::Command::new("powershell");
+        c.args(["-NoProfile", "-Command", script])
+            .env("RC_TITLE", title)
+            .env("RC_BODY", body);
+        c
+    };
+    // waited on from a thread so the UI never blocks and no zombie process is left behind
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+    std::thread::spawn(move || {
+        if let Err(e) = cmd.status() {
+            eprintln!("Could not show notification: {}", e);
+        }
+    });
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    let _ = (title, body);
+}
+
+#[component]
+fn ChatWindow(
+    current_chat_id: Signal<Option<String>>,
+    messages: Signal<Vec<ChatMessage>>,
+    settings: Signal<Settings>,
+    chats: Signal<Vec<(String, String)>>,
+    semantic_query: Signal<Option<SemanticQuery>>,
+    personas_revision: Signal<u32>,
+    active_runs: Signal<HashMap<String, ChatRun>>,
+    unread_chats: Signal<HashSet<String>>,
+    backend_status: Signal<BackendStatus>,
+) -> Element {
+    let mut input_text = use_signal(|| "".to_string());
+    // reply text streamed so far, per chat, shown when returning to a busy chat
+    let partial_replies = use_signal(HashMap::<String, String>::new);
+    // failed requests of the visible chat, shown as dismissible bubbles
+    let mut chat_errors = use_signal(Vec::<ChatErrorRecord>::new);
+    // per chat: why the request is being retried and when
+    let retry_notes = use_signal(HashMap::<String, String>::new);
+    // bumped whenever the message queue changes
+    let mut queue_revision = use_signal(|| 0_u32);
+    // chats whose queue waits for the user: interrupted ones, and any left over from the last run
+    let mut queue_paused = use_signal(|| or_report(chats_with_queue(&init_db()), "read the message queue"));
+    // queued message being edited in place
+    let mut editing_queued = use_signal(|| Option::<(i64, String)>::None);
+    // the visible chat's request, if it has one in flight
+    let current_run = current_chat_id().and_then(|cid| active_runs().get(&cid).cloned());
+    let http_client = use_signal(Client::new);
+    // command tool calls waiting for the user's approval
+    let mut tool_approvals = use_signal(Vec::<PendingApproval>::new);
+
+    // structured output mode of the visible chat and its settings panel
+    let mut show_format_panel = use_signal(|| false);
+    let mut chat_format = use_signal(|| Option::<String>::None);
+    // whether the visible chat retrieves knowledge base excerpts
+    let mut chat_kb = use_signal(|| false);
+    // persona chosen for the visible chat
+    let mut chat_persona_id = use_signal(|| Option::<i64>::None);
+    // side-by-side model comparison for the visible chat
+    let mut show_compare = use_signal(|| false);
+    use_effect(move || {
+        let conn = init_db();
+        let format = current_chat_id()
+            .and_then(|cid| or_report(load_chat_format(&conn, &cid), "load the chat's output format"));
+        chat_format.set(format);
+        chat_kb.set(current_chat_id().map(|cid| chat_uses_kb(&conn, &cid)).unwrap_or(false));
+        chat_persona_id.set(
+            current_chat_id()
+                .and_then(|cid| or_report(chat_persona(&conn, &cid), "load the chat's persona"))
+                .map(|p| p.id),
+        );
+        chat_errors.set(
+            current_chat_id()
+                .map(|cid| or_report(list_chat_errors(&conn, &cid), "load the chat's failed requests"))
+                .unwrap_or_default(),
+        );
+        show_format_panel.set(false);
+        show_compare.set(false);
+    });
+
+    // re-read whenever personas are edited
+    let personas = {
+        let _ = personas_revision();
+        or_report(list_personas(&init_db()), "load personas")
+    };
+    let active_persona = chat_persona_id().and_then(|id| personas.iter().find(|p| p.id == id).cloned());
+
+    // template waiting for its {{variables}} before it goes into the input
+    let mut filling_template = use_signal(|| Option::<(PromptTemplate, Vec<(String, String)>)>::None);
+    let mut insert_template = move |template: PromptTemplate| {
+        let vars = template_variables(&template.body);
+        if vars.is_empty() {
+            input_text.set(template.body);
+        } else {
+            filling_template.set(Some((template, vars.into_iter().map(|v| (v, String::new())).collect())));
+        }
+    };
+    // prompt templates matching a "/command" being typed
+    let suggestions = if input_text().starts_with('/') {
+        slash_matches(&or_report(list_prompt_templates(&init_db()), "load prompt templates"), &input_text())
+    } else {
+        vec![]
+    };
+    let first_suggestion = suggestions.first().cloned();
+
+    // the approval prompt shown for the visible chat (oldest first)
+    let pending_approval = current_chat_id().and_then(|cid| {
+        tool_approvals()
+            .into_iter()
+            .find(|a| a.chat_id == cid)
+    });
+
+    // compute header title outside rsx! to avoid let-binding in the macro context
+    let header_title = {
+        if let Some(id) = current_chat_id() {
+            // find title from chats signal, fallback to id
+            chats()
+                .iter()
+                .find(|(cid, _)| cid == &id)
+                .map(|(_, t)| t.clone())
+                .unwrap_or(id.clone())
+        } else {
+            "No Chat Selected".to_string()
+        }
+    };
+
+    // compute model display for the header (show friendly notice when empty)
+    let model_display = {
+        let m = match &active_persona {
+            Some(p) => apply_persona(&settings(), p).model,
+            None => settings().model.clone(),
+        };
+        if m.trim().is_empty() {
+            "No model selected".to_string()
+        } else {
+            m
+        }
+    };
+
+    // send_to_ollama respects a per-request cancellation flag and clears the chat's entry in active_runs
+    let send_to_ollama = {
+        // include current_chat_id so the async task can check whether the user is currently viewing the target chat
+        to_owned![
+            messages,
+            http_client,
+            active_runs,
+            partial_replies,
+            unread_chats,
+            current_chat_id,
+            tool_approvals,
+            chat_errors,
+            retry_notes
+        ];
+        move |chat_id: String, user_message: String, settings: Settings, cancel_flag: Arc<AtomicBool>| {
+            async move {
+                // tools.json is re-read on every send so edits apply without a restart
+                let user_tools = if settings.tools_enabled {
+                    load_user_tools().unwrap_or_else(|e| {
+                        eprintln!("Ignoring user tools: {}", e);
+                        vec![]
+                    })
+                } else {
+                    vec![]
+                };
+                let tools = if settings.tools_enabled {
+                    let mut defs = tool_definitions(&settings);
+                    defs.extend(user_tool_definitions(&init_db(), &user_tools));
+                    defs
+                } else {
+                    vec![]
+                };
+
+                // the chat's persona, output format and knowledge base are applied by the shared pipeline
+                let (client, path) = (http_client(), db_path());
+                let ctx = ReplyContext {
+                    client: &client,
+                    db_path: &path,
+                    base_url: OLLAMA_BASE_URL,
+                    slots: &GENERATION_SLOTS,
+                    settings: &settings,
+                    model: None,
+                    tools,
+                };
+                let mut hooks = WindowReply {
+                    chat_id: chat_id.clone(),
+                    user_message: user_message.clone(),
+                    cancel: cancel_flag.clone(),
+                    settings: settings.clone(),
+                    user_tools,
+                    active_runs,
+                    messages,
+                    partial_replies,
+                    retry_notes,
+                    chat_errors,
+                    current_chat_id,
+                    tool_approvals,
+                };
+                let reply = generate_reply(&ctx, &chat_id, &user_message, &mut hooks).await;
+
+                // a reply landed in a chat the user isn't looking at: flag it (and optionally notify);
+                // errors and cancels don't
+                if matches!(reply, Ok(Some(_))) && current_chat_id().as_deref() != Some(chat_id.as_str()) {
+                    if let Err(e) = try_init_db().and_then(|conn| mark_chat_unread(&conn, &chat_id, true)) {
+                        report_error(format!("Could not mark the chat as unread: {}", e));
+                    }
+                    unread_chats.write().insert(chat_id.clone());
+                    if settings.notify_background {
+                        let (title, reply) = background_reply_summary(&init_db(), &chat_id);
+                        desktop_notify(&title, &reply);
+                    }
+                }
+
+                // done for this request: free the chat (and its generation slot)
+                finish_chat_run(active_runs, &chat_id, &cancel_flag);
+            }
+        }
+    };
+
+    // request a reply to `user_text`, which is already stored in `chat_id`
+    let mut start_run = move |chat_id: String, user_text: String| {
+        // prepare cancellation flag and mark which chat is loading
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        active_runs.write().insert(
+            chat_id.clone(),
+            ChatRun {
+                cancel: cancel_flag.clone(),
+                slot: None,
+            },
+        );
+        spawn(send_to_ollama(chat_id, user_text, settings(), cancel_flag));
+    };
+
+    // store a user message in `chat_id` and start its request; nothing is sent if it can't be stored
+    let mut start_send = move |chat_id: String, text: String| -> Result<(), StorageError> {
+        let conn = try_init_db()?;
+
+        // ensure we don't attempt to insert extremely long content: clamp to a reasonable max (e.g., 1_000_000 chars)
+        let mut user_text = text;
+        const MAX_MESSAGE_LEN: usize = 1_000_000;
+        if user_text.len() > MAX_MESSAGE_LEN {
+            user_text.truncate(MAX_MESSAGE_LEN);
+        }
+
+        // the store keeps the history within its limit
+        open_store()?.insert_message(&chat_id, &ChatMessage::new("user", user_text.clone()), None)?;
+
+        // a new turn supersedes errors from earlier ones
+        clear_chat_errors(&conn, &chat_id)?;
+
+        // push the user's message into the visible messages buffer if that chat is open
+        if current_chat_id().as_deref() == Some(chat_id.as_str()) {
+            messages.push(ChatMessage::new("user", user_text.clone()));
+            chat_errors.clear();
+        }
+
+        start_run(chat_id, user_text);
+        Ok(())
+    };
+
+    // whenever a chat becomes free, send the next message waiting in its queue
+    use_effect(move || {
+        let runs = active_runs();
+        let paused = queue_paused();
+        let _ = queue_revision();
+        // held back while the backend is down; picked up again on reconnect
+        if backend_status().is_offline() {
+            return;
+        }
+        let conn = match try_init_db() {
+            Ok(conn) => conn,
+            Err(e) => return report_error(format!("Could not read the message queue: {}", e)),
+        };
+        for chat_id in or_report(chats_with_queue(&conn), "read the message queue") {
+            if runs.contains_key(&chat_id) || paused.contains(&chat_id) {
+                continue;
+            }
+            // only taken off the queue once it's stored; on failure the queue waits for the user
+            let Some(next) = or_report(list_queued_messages(&conn, &chat_id), "read the message queue").into_iter().next() else {
+                continue;
+            };
+            match start_send(chat_id.clone(), next.content) {
+                Ok(()) => {
+                    if let Err(e) = remove_queued_message(&conn, next.id) {
+                        // left in the queue it would be sent twice; hold the chat's queue instead
+                        report_error(format!("Could not take a sent message off the queue: {}", e));
+                        queue_paused.write().insert(chat_id);
+                    }
+                }
+                Err(e) => {
+                    report_error(format!("Could not send a queued message: {}", e));
+                    queue_paused.write().insert(chat_id);
+                }
+            }
+        }
+    });
+
+    // follow-ups waiting for the visible chat
+    let queued = {
+        let _ = queue_revision();
+        current_chat_id()
+            .map(|cid| or_report(list_queued_messages(&init_db(), &cid), "read the message queue"))
+            .unwrap_or_default()
+    };
+    let chat_busy = current_run.is_some();
+    let backend_offline = backend_status().is_offline();
+    let queue_is_paused = current_chat_id()
+        .map(|cid| queue_paused().contains(&cid))
+        .unwrap_or(false);
+
+    rsx! {
+        div { class: "chat-window",
+
+            div { class: "chat-header",
+                h2 { "{header_title}" }
+                // new model indicator under the chat title
+                p { class: "model-indicator", "Model: {model_display}" }
+                p {
+                    class: "backend-status",
+                    title: match backend_status() {
+                        BackendStatus::Offline { error } => format!("{}: {}", OLLAMA_BASE_URL, error),
+                        _ => OLLAMA_BASE_URL.to_string(),
+                    },
+                    span { class: backend_status().dot_class() }
+                    "{backend_status().label()}"
+                }
+
+                if let Some(cid) = current_chat_id() {
+                    div { class: "header-actions",
+                        if !personas.is_empty() {
+                            select {
+                                class: "input persona-select",
+                                title: "Persona answering in this chat",
+                                onchange: {
+                                    let cid = cid.clone();
+                                    move |e: Event<FormData>| {
+                                        let id = e.value().parse::<i64>().ok();
+                                        if let Err(e) = try_init_db().and_then(|conn| set_chat_persona(&conn, &cid, id)) {
+                                            return report_error(format!("Could not change the persona: {}", e));
+                                        }
+                                        chat_persona_id.set(id);
+                                    }
+                                },
+                                {persona_options(&personas, active_persona.as_ref().map(|p| p.id))}
+                            }
+                        }
+                        button {
+                            class: "rename-btn",
+                            onclick: move |_| show_format_panel.set(!show_format_panel()),
+                            match chat_format().as_deref() {
+                                None => "{{ }} Free text",
+                                Some("json") => "{{ }} JSON",
+                                Some(_) => "{{ }} JSON schema",
+                            }
+                        }
+                        button {
+                            class: if chat_kb() { "rename-btn toggle-on" } else { "rename-btn" },
+                            title: "Answer using the indexed knowledge base",
+                            onclick: {
+                                let cid = cid.clone();
+                                move |_| {
+                                    let enabled = !chat_kb();
+                                    if let Err(e) = try_init_db().and_then(|conn| set_chat_uses_kb(&conn, &cid, enabled)) {
+                                        return report_error(format!("Could not change the knowledge base setting: {}", e));
+                                    }
+                                    chat_kb.set(enabled);
+                                }
+                            },
+                            if chat_kb() { "📚 Knowledge base: on" } else { "📚 Knowledge base: off" }
+                        }
+                        button {
+                            class: if show_compare() { "rename-btn toggle-on" } else { "rename-btn" },
+                            title: "Send one prompt to several models side by side",
+                            onclick: move |_| show_compare.set(!show_compare()),
+                            "⚖️ Compare"
+                        }
+                    }
+                    if show_format_panel() {
+                        StructuredOutputPanel {
+                            key: "{cid}",
+                            chat_id: cid.clone(),
+                            show_panel: show_format_panel,
+                            chat_format
+                        }
+                    }
+                    if show_compare() {
+                        ComparePanel {
+                            key: "{cid}",
+                            chat_id: cid.clone(),
+                            messages,
+                            settings,
+                            show_compare
+                        }
+                    }
+                }
+            }
+
+            div { class: "chat-messages",
+                {messages().iter().map(|msg| {
+                    rsx! {
+                        Message {
+                            role: msg.role.clone(),
+                            content: msg.content.clone(),
+                            model: msg.model.clone(),
+                            tool_calls: msg.tool_calls.clone(),
+                            tool_name: msg.tool_name.clone(),
+                            sources: msg.sources.clone(),
+                            // replies keep the persona's look even if it was renamed away or deleted
+                            persona: msg.persona.as_ref().map(|name| {
+                                let persona = personas
+                                    .iter()
+                                    .find(|p| &p.name == name)
+                                    .cloned()
+                                    .unwrap_or(Persona { name: name.clone(), ..Persona::default() });
+                                (persona.label(), persona.accent().to_string())
+                            }),
+                            RelatedButton { content: msg.content.clone(), chat_id: current_chat_id(), semantic_query }
+                        }
+                    }
+                })}
+
+                // failed requests: never part of the history, dismissible, and retryable when the chat is free
+                {chat_errors().into_iter().map(|err| {
+                    let id = err.id;
+                    rsx! {
+                        div { key: "{id}", class: "message error-message",
+                            p { class: "error-text", "⚠️ {err.message}" }
+                            div { class: "row",
+                                if let Some(text) = err.user_message.clone() {
+                                    button {
+                                        class: "rename-save",
+                                        disabled: chat_busy || backend_offline,
+                                        onclick: move |_| {
+                                            if let Some(cid) = current_chat_id() {
+                                                if let Err(e) = try_init_db().and_then(|conn| dismiss_chat_error(&conn, id)) {
+                                                    report_error(format!("Could not remove the failed request: {}", e));
+                                                }
+                                                chat_errors.retain(|e| e.id != id);
+                                                start_run(cid, text.clone());
+                                            }
+                                        },
+                                        "↻ Retry"
+                                    }
+                                }
+                                button {
+                                    class: "rename-cancel",
+                                    onclick: move |_| {
+                                        if let Err(e) = try_init_db().and_then(|conn| dismiss_chat_error(&conn, id)) {
+                                            return report_error(format!("Could not remove the failed request: {}", e));
+                                        }
+                                        chat_errors.retain(|e| e.id != id);
+                                    },
+                                    "Dismiss"
+                                }
+                            }
+                        }
+                    }
+                })}
+
+                // show the "thinking" bubble only if the visible chat has a request in flight
+                if let Some(run) = current_run.clone() {
+                    PendingReply {
+                        note: current_chat_id().and_then(|cid| retry_notes().get(&cid).cloned()),
+                        partial: current_chat_id().and_then(|cid| partial_replies().get(&cid).cloned()),
+                        status: if run.running() { "Thinking..." } else { "Waiting for a free generation slot..." },
+                    }
+                }
+            }
+
+            if let Some(approval) = pending_approval {
+                div { class: "tool-approval",
+                    p { class: "tool-label", "🔧 The model wants to run \"{approval.tool}\" with {approval.arguments}" }
+                    pre { class: "tool-output", "{approval.command}" }
+                    div { class: "row",
+                        button {
+                            class: "rename-save",
+                            onclick: {
+                                let approval = approval.clone();
+                                move |_| {
+                                    approval.respond(ApprovalDecision::AllowOnce);
+                                    tool_approvals.retain(|a| !Arc::ptr_eq(&a.responder, &approval.responder));
+                                }
+                            },
+                            "Allow once"
+                        }
+                        button {
+                            class: "rename-save",
+                            onclick: {
+                                let approval = approval.clone();
+                                move |_| {
+                                    approval.respond(ApprovalDecision::AlwaysAllow);
+                                    tool_approvals.retain(|a| !Arc::ptr_eq(&a.responder, &approval.responder));
+                                }
+                            },
+                            "Always allow"
+                        }
+                        button {
+                            class: "delete-chat-btn big",
+                            onclick: {
+                                let approval = approval.clone();
+                                move |_| {
+                                    approval.respond(ApprovalDecision::Deny);
+                                    tool_approvals.retain(|a| !Arc::ptr_eq(&a.responder, &approval.responder));
+                                }
+                            },
+                            "Deny"
+                        }
+                    }
+                }
+            }
+
+            if let Some((template, values)) = filling_template() {
+                div { class: "tool-approval",
+                    p { class: "tool-label", "📝 /{template.name}" }
+                    {values.into_iter().enumerate().map(|(i, (name, value))| rsx! {
+                        label { "{name}" }
+                        textarea {
+                            class: "textarea template-value",
+                            value: "{value}",
+                            oninput: move |e| {
+                                if let Some((_, values)) = filling_template.write().as_mut() {
+                                    values[i].1 = e.value();
+                                }
+                            },
+                        }
+                    })}
+                    div { class: "row",
+                        button {
+                            class: "rename-save",
+                            onclick: move |_| {
+                                if let Some((template, values)) = filling_template() {
+                                    input_text.set(fill_template(&template.body, &values));
+                                }
+                                filling_template.set(None);
+                            },
+                            "Insert"
+                        }
+                        button {
+                            class: "rename-cancel",
+                            onclick: move |_| filling_template.set(None),
+                            "Cancel"
+                        }
+                    }
+                }
+            }
+
+            if !suggestions.is_empty() {
+                div { class: "slash-suggestions",
+                    {suggestions.into_iter().map(|t| {
+                        let name = t.name.clone();
+                        let description = t.description.clone();
+                        rsx! {
+                            div {
+                                class: "slash-item",
+                                onclick: move |_| insert_template(t.clone()),
+                                span { class: "model-name", "/{name}" }
+                                span { class: "dim-text", "{description}" }
+                            }
+                        }
+                    })}
+                }
+            }
+
+            if !queued.is_empty() {
+                div { class: "message-queue",
+                    div { class: "row",
+                        span { class: "tool-label",
+                            if queue_is_paused { "Queued follow-ups (paused)" } else { "Queued follow-ups" }
+                        }
+                        if queue_is_paused {
+                            button {
+                                class: "rename-save",
+                                onclick: move |_| {
+                                    if let Some(cid) = current_chat_id() {
+                                        queue_paused.write().remove(&cid);
+                                    }
+                                },
+                                "▶ Send queued"
+                            }
+                        }
+                    }
+                    {queued.iter().cloned().map(|item| {
+                        let id = item.id;
+                        let editing_this = editing_queued().filter(|(eid, _)| *eid == id);
+                        rsx! {
+                            div { class: "queued-item",
+                                if let Some((_, draft)) = editing_this {
+                                    textarea {
+                                        class: "textarea template-value",
+                                        value: "{draft}",
+                                        oninput: move |e| editing_queued.set(Some((id, e.value()))),
+                                    }
+                                    button {
+                                        class: "rename-save",
+                                        onclick: move |_| {
+                                            if let Some((_, text)) = editing_queued() {
+                                                if !text.trim().is_empty() {
+                                                    if let Err(e) = try_init_db().and_then(|conn| update_queued_message(&conn, id, &text)) {
+                                                        return report_error(format!("Could not change the queued message: {}", e));
+                                                    }
+                                                }
+                                            }
+                                            editing_queued.set(None);
+                                            queue_revision += 1;
+                                        },
+                                        "Save"
+                                    }
+                                } else {
+                                    p { class: "dim-text queued-text", "{item.content}" }
+                                    button {
+                                        class: "rename-btn",
+                                        onclick: move |_| editing_queued.set(Some((id, item.content.clone()))),
+                                        "Edit"
+                                    }
+                                }
+                                button {
+                                    class: "rename-cancel",
+                                    onclick: move |_| {
+                                        if let Err(e) = try_init_db().and_then(|conn| remove_queued_message(&conn, id)) {
+                                            return report_error(format!("Could not remove the queued message: {}", e));
+                                        }
+                                        if editing_queued().map(|(eid, _)| eid == id).unwrap_or(false) {
+                                            editing_queued.set(None);
+                                        }
+                                        queue_revision += 1;
+                                    },
+                                    "✕"
+                                }
+                            }
+                        }
+                    })}
+                }
+            }
+
+            if backend_offline {
+                div { class: "backend-offline",
+                    "Ollama isn't reachable at {OLLAMA_BASE_URL}, so sending is paused. "
+                    "This reconnects automatically; queued messages go out once it's back."
+                }
+            }
+
+            div { class: "chat-input-area",
+                textarea {
+                    class: "chat-input",
+                    placeholder: "Send a message... (type / for prompt templates)",
+                    value: "{input_text}",
+                    oninput: move |e| input_text.set(e.value()),
+                    // Tab completes the first matching slash command
+                    onkeydown: move |e| {
+                        if e.key() == Key::Tab {
+                            if let Some(template) = first_suggestion.clone() {
+                                e.prevent_default();
+                                insert_template(template);
+                            }
+                        }
+                    },
+                }
+
+                // If current chat has an in-flight request, show interrupt button
+                if current_run.is_some() {
+                    button {
+                        class: "interrupt-button big",
+                        onclick: move |_| {
+                            if let Some(cid) = current_chat_id() {
+                                // set cancellation flag so the background task won't push the final reply;
+                                // removing the run clears the thinking bubble and frees its slot right away
+                                if let Some(run) = active_runs.write().remove(&cid) {
+                                    run.cancel.store(true, Ordering::Relaxed);
+                                }
+                                // drop any approval prompts for this chat; the waiting call is treated as denied
+                                tool_approvals.retain(|a| a.chat_id != cid);
+                                // hold queued follow-ups until the user resumes them
+                                if !or_report(list_queued_messages(&init_db(), &cid), "read the message queue").is_empty() {
+                                    queue_paused.write().insert(cid);
+                                }
+                            }
+                            // Do NOT push any "[Interrupted]" message — conversation simply stops.
+                        },
+                        "Interrupt"
+                    }
+                }
+
+                button {
+                    class: "send-button big",
+                    // disable send when there is no current chat, the input is empty or the backend is down
+                    // (a busy chat still accepts follow-ups into its queue)
+                    disabled: current_chat_id().is_none() ||
+                              input_text().trim().is_empty() ||
+                              (backend_offline && !chat_busy),
+                    onclick: move |_| {
+                        if let Some(chat_id) = current_chat_id() {
+                            let text = input_text();
+
+                            if text.trim().is_empty() {
+                                return;
+                            }
+                            input_text.set("".to_string());
+
+                            // busy chat (or earlier follow-ups still waiting): queue it to keep the order
+                            if current_run.is_some() || !queued.is_empty() {
+                                if let Err(e) = try_init_db().and_then(|conn| queue_message(&conn, &chat_id, &text)) {
+                                    input_text.set(text);
+                                    return report_error(format!("Could not queue the message: {}", e));
+                                }
+                                queue_paused.write().remove(&chat_id);
+                                queue_revision += 1;
+                            } else if let Err(e) = start_send(chat_id, text.clone()) {
+                                // keep what was typed so it isn't lost
+                                input_text.set(text);
+                                report_error(format!("Your message was not sent: {}", e));
+                            }
+                        }
+                    },
+                    if current_run.is_some() { "⏳ Queue" } else { "➤ Send" }
+                }
+            }
+        }
+    }
+}
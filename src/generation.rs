@@ -0,0 +1,569 @@
+// generating a chat's reply, for the desktop window and the local API alike: the chat's persona,
+// output format and knowledge base, retries, tool rounds and its recorded errors. Also the
+// generation slots: one limit on how many replies Ollama is asked for at once, shared by chat
+// sends, compare columns, the local API and the proxy
+
+use crate::backend::{self, read_chat_stream, ChatError, OllamaChatRequest, OllamaFunctionCall, OllamaMessage, MAX_SEND_ATTEMPTS};
+use crate::conversation::{
+    build_chat_messages, check_structured_reply, format_correction_prompt, format_request_value, request_options,
+};
+use crate::knowledge::{kb_context_message, rank_kb_chunks, KbHit};
+use crate::storage::{
+    self, apply_persona, chat_persona, chat_uses_kb, hash_system_prompt, load_chat_format, record_chat_error,
+    ChatErrorRecord, ChatMessage, GenerationMeta, Settings, SqliteStore, StorageError,
+};
+use crate::store::ChatStore;
+use reqwest::Client;
+use rusqlite::Connection;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// how often a request waiting for a free slot checks again (the limit may change meanwhile)
+const SLOT_POLL_MS: u64 = 250;
+// how often a request waiting to retry checks whether it was cancelled
+const CANCEL_POLL_MS: u64 = 250;
+// how often a non-conforming structured reply is sent back with its validation errors
+pub const MAX_FORMAT_RETRIES: usize = 2;
+// how many call -> result -> continue rounds a single reply may take before we stop offering tools
+pub const MAX_TOOL_ROUNDS: usize = 8;
+
+/* ================= SLOTS ================= */
+
+#[derive(Debug, Default)]
+struct SlotState {
+    running: usize,
+    // tickets of the requests waiting, oldest first
+    waiting: VecDeque<u64>,
+    next_ticket: u64,
+}
+
+// cheap to clone; clones share the same slots
+#[derive(Clone, Debug, Default)]
+pub struct GenerationSlots {
+    state: Arc<Mutex<SlotState>>,
+}
+
+// a slot in use; dropping it frees the slot
+#[derive(Debug)]
+pub struct SlotGuard {
+    slots: GenerationSlots,
+}
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        let mut state = self.slots.lock();
+        state.running = state.running.saturating_sub(1);
+    }
+}
+
+// a place in the queue; a waiter that gives up (or whose future is dropped) leaves it
+struct Ticket<'a> {
+    slots: &'a GenerationSlots,
+    id: u64,
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        self.slots.lock().waiting.retain(|t| *t != self.id);
+    }
+}
+
+impl GenerationSlots {
+    fn lock(&self) -> std::sync::MutexGuard<'_, SlotState> {
+        // the state stays consistent even if a holder panicked
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // wait until fewer than `limit` replies are being generated and no older request is waiting
+    // (limit <= 0 = no limit); None if `cancelled` says so before a slot is free
+    pub async fn acquire(&self, limit: i32, cancelled: impl Fn() -> bool) -> Option<SlotGuard> {
+        let ticket = {
+            let mut state = self.lock();
+            let id = state.next_ticket;
+            state.next_ticket += 1;
+            state.waiting.push_back(id);
+            Ticket { slots: self, id }
+        };
+        loop {
+            if cancelled() {
+                return None;
+            }
+            {
+                let mut state = self.lock();
+                let first = state.waiting.front() == Some(&ticket.id);
+                if first && (limit <= 0 || state.running < limit as usize) {
+                    state.waiting.pop_front();
+                    state.running += 1;
+                    return Some(SlotGuard { slots: self.clone() });
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(SLOT_POLL_MS)).await;
+        }
+    }
+
+    // replies being generated right now
+    pub fn running(&self) -> usize {
+        self.lock().running
+    }
+}
+
+/* ================= REPLIES ================= */
+
+// what a chat's reply is generated with, besides the chat itself
+pub struct ReplyContext<'a> {
+    pub client: &'a Client,
+    // database shared by the GUI and the API; opened per step like everywhere else
+    pub db_path: &'a str,
+    // Ollama server the reply is generated with
+    pub base_url: &'a str,
+    pub slots: &'a GenerationSlots,
+    // the app settings; the chat's persona is applied on top
+    pub settings: &'a Settings,
+    // a model asked for by name (API requests); wins over the persona's and the settings' one
+    pub model: Option<String>,
+    // tool definitions offered to the model; empty = no tools
+    pub tools: Vec<Value>,
+}
+
+// how the caller follows a reply while it's generated; everything but `started` is optional
+pub trait ReplyHooks {
+    // the request got a generation slot; it stays taken until `slot` is dropped
+    fn started(&mut self, slot: SlotGuard);
+
+    // checked while waiting and before anything is stored; a cancelled reply stops silently
+    fn cancelled(&self) -> bool {
+        false
+    }
+
+    // the reply so far after every chunk; None once the stream has ended
+    fn partial(&mut self, _text: Option<&str>) {}
+
+    // why the request is about to be sent again; None once it has been
+    fn retrying(&mut self, _note: Option<String>) {}
+
+    // a message of the reply (tool call, tool result or the answer) was stored in the chat
+    fn stored(&mut self, _message: &ChatMessage) {}
+
+    // `err` was recorded in the chat's errors, or `record` says why it couldn't be
+    fn failed(&mut self, _err: &ChatError, _record: Result<ChatErrorRecord, StorageError>) {}
+
+    // answer one tool call; the output goes back to the model
+    fn run_tool(&mut self, call: &OllamaFunctionCall) -> impl Future<Output = String> {
+        let output = format!("Error: unknown tool '{}'", call.name);
+        async move { output }
+    }
+}
+
+// the settings a reply in `chat_id` is generated with (its persona applied, `model` if asked for)
+// and the persona's name
+pub fn reply_settings(
+    conn: &Connection,
+    chat_id: &str,
+    settings: &Settings,
+    model: Option<&str>,
+) -> Result<(Settings, Option<String>), StorageError> {
+    let persona = chat_persona(conn, chat_id)?;
+    let mut settings = match &persona {
+        Some(p) => apply_persona(settings, p),
+        None => settings.clone(),
+    };
+    if let Some(model) = model.filter(|m| !m.trim().is_empty()) {
+        settings.model = model.to_string();
+    }
+    Ok((settings, persona.map(|p| p.name)))
+}
+
+// answer `user_message`, already stored as the chat's latest turn. Messages are stored as they
+// arrive; a failure is recorded in the chat's errors as well as returned. None if cancelled
+pub async fn generate_reply(
+    ctx: &ReplyContext<'_>,
+    chat_id: &str,
+    user_message: &str,
+    hooks: &mut impl ReplyHooks,
+) -> Result<Option<ChatMessage>, ChatError> {
+    let result = run_reply(ctx, chat_id, user_message, hooks).await;
+    if let Err(err) = &result {
+        report_failure(ctx, chat_id, err, user_message, hooks);
+    }
+    result
+}
+
+fn report_failure(
+    ctx: &ReplyContext<'_>,
+    chat_id: &str,
+    err: &ChatError,
+    user_message: &str,
+    hooks: &mut impl ReplyHooks,
+) {
+    eprintln!("Request for chat {} failed: {:?}", chat_id, err);
+    let record = storage::open(ctx.db_path).and_then(|conn| record_chat_error(&conn, chat_id, err, user_message));
+    hooks.failed(err, record);
+}
+
+async fn run_reply(
+    ctx: &ReplyContext<'_>,
+    chat_id: &str,
+    user_message: &str,
+    hooks: &mut impl ReplyHooks,
+) -> Result<Option<ChatMessage>, ChatError> {
+    let not_loaded = |e: StorageError| ChatError::NotLoaded(e.to_string());
+    // history comes from the database so it's right even when the chat isn't the visible one;
+    // a history that can't be read is not sent as an empty one
+    let (settings, persona, history, output_format, use_kb) = {
+        let conn = storage::open(ctx.db_path).map_err(not_loaded)?;
+        let (settings, persona) =
+            reply_settings(&conn, chat_id, ctx.settings, ctx.model.as_deref()).map_err(not_loaded)?;
+        if settings.model.trim().is_empty() {
+            return Err(ChatError::NoModel);
+        }
+        let history = storage::load_chat_messages(&conn, chat_id).map_err(not_loaded)?;
+        let output_format = load_chat_format(&conn, chat_id).map_err(not_loaded)?;
+        (settings, persona, history, output_format, chat_uses_kb(&conn, chat_id))
+    };
+
+    // the limit is the app's, whatever the persona
+    let Some(slot) = ctx.slots.acquire(ctx.settings.max_generations, || hooks.cancelled()).await else {
+        return Ok(None);
+    };
+    hooks.started(slot);
+
+    // knowledge base: add the closest excerpts right before the new question
+    let mut kb_context: Option<String> = None;
+    let mut kb_sources: Option<String> = None;
+    if use_kb && !settings.embed_model.trim().is_empty() {
+        match retrieve_kb_chunks(ctx, &settings.embed_model, user_message, settings.kb_top_k.max(1) as usize).await {
+            Ok(hits) if !hits.is_empty() => {
+                kb_context = Some(kb_context_message(&hits));
+                kb_sources = serde_json::to_string(&hits).ok();
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Knowledge base lookup failed: {}", e),
+        }
+    }
+
+    let mut ollama_messages = build_chat_messages(&settings.system_prompt, &history, user_message, kb_context);
+    let params_json = request_options(&settings);
+    // remembered alongside the reply so it's clear what produced it
+    let meta = GenerationMeta {
+        endpoint: ctx.base_url.to_string(),
+        system_prompt_hash: hash_system_prompt(&settings.system_prompt),
+        options: Some(params_json.to_string()),
+    };
+    let store = SqliteStore::new(ctx.db_path);
+    let not_stored = |e: StorageError| ChatError::NotStored(e.to_string());
+    let mut tool_rounds = 0;
+    let mut format_retries = 0;
+
+    // each pass sends the conversation so far; tool calls are answered and the loop continues
+    // until the model replies without calling anything
+    loop {
+        let request = OllamaChatRequest {
+            model: settings.model.clone(),
+            messages: ollama_messages.clone(),
+            // streamed so a partial reply can be previewed while it's being written
+            stream: true,
+            options: Some(params_json.clone()),
+            tools: if ctx.tools.is_empty() || tool_rounds >= MAX_TOOL_ROUNDS {
+                None
+            } else {
+                Some(ctx.tools.clone())
+            },
+            format: output_format.as_deref().and_then(format_request_value),
+        };
+
+        // the underlying request can't be aborted, but nothing is stored once cancelled
+        let response = match send_with_retries(ctx, &request, hooks).await {
+            Ok(response) => response,
+            Err(_) if hooks.cancelled() => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let streamed = read_chat_stream(response, |text| {
+            if !hooks.cancelled() {
+                hooks.partial(Some(text));
+            }
+        })
+        .await;
+        hooks.partial(None);
+        let api_response = match streamed {
+            Ok(api_response) => api_response,
+            Err(_) if hooks.cancelled() => return Ok(None),
+            Err(e) => return Err(ChatError::Stream(e)),
+        };
+        if hooks.cancelled() {
+            return Ok(None);
+        }
+
+        let tool_calls = api_response.message.tool_calls.clone().filter(|calls| !calls.is_empty());
+        // problems with a structured reply that should be sent back for correction
+        let format_errors = match (&output_format, &tool_calls) {
+            (Some(format), None) => check_structured_reply(&api_response.message.content, format),
+            _ => vec![],
+        };
+
+        if !format_errors.is_empty() && format_retries < MAX_FORMAT_RETRIES {
+            // re-prompt with the validation errors; the rejected attempt is not stored
+            format_retries += 1;
+            ollama_messages.push(api_response.message);
+            ollama_messages.push(OllamaMessage::new("user", format_correction_prompt(&format_errors)));
+            continue;
+        }
+
+        if let Some(calls) = tool_calls {
+            tool_rounds += 1;
+            // keep the call request in the conversation so it's shown and replayed as context;
+            // a round that can't be stored stops here rather than carrying on with a
+            // conversation the history doesn't have
+            let call_msg = ChatMessage {
+                model: Some(settings.model.clone()),
+                tool_calls: Some(serde_json::to_string(&calls).unwrap_or_default()),
+                persona: persona.clone(),
+                ..ChatMessage::new("assistant", api_response.message.content.clone())
+            };
+            store.insert_message(chat_id, &call_msg, Some(&meta)).map_err(not_stored)?;
+            hooks.stored(&call_msg);
+            ollama_messages.push(api_response.message);
+
+            for call in calls {
+                let name = call.function.name.clone();
+                let output = hooks.run_tool(&call.function).await;
+                let result_msg = ChatMessage {
+                    tool_name: Some(name.clone()),
+                    ..ChatMessage::new("tool", output.clone())
+                };
+                store.insert_message(chat_id, &result_msg, None).map_err(not_stored)?;
+                hooks.stored(&result_msg);
+                ollama_messages.push(OllamaMessage {
+                    tool_name: Some(name),
+                    ..OllamaMessage::new("tool", output)
+                });
+            }
+            if hooks.cancelled() {
+                return Ok(None);
+            }
+            continue;
+        }
+
+        // a reply that isn't in the history isn't shown as if it were
+        let reply = ChatMessage {
+            model: Some(settings.model.clone()),
+            sources: kb_sources,
+            persona,
+            ..ChatMessage::new("assistant", api_response.message.content)
+        };
+        store.insert_message(chat_id, &reply, Some(&meta)).map_err(not_stored)?;
+        hooks.stored(&reply);
+
+        // still not conforming after the retries: say so under the reply
+        if !format_errors.is_empty() {
+            let err = ChatError::Format {
+                retries: MAX_FORMAT_RETRIES,
+                errors: format_errors,
+            };
+            report_failure(ctx, chat_id, &err, user_message, hooks);
+        }
+        return Ok(Some(reply));
+    }
+}
+
+// send `request`, retrying transient failures with a growing delay before giving up
+async fn send_with_retries(
+    ctx: &ReplyContext<'_>,
+    request: &OllamaChatRequest,
+    hooks: &mut impl ReplyHooks,
+) -> Result<reqwest::Response, ChatError> {
+    let mut attempt = 1;
+    let sent = loop {
+        match backend::send_chat(ctx.client, ctx.base_url, request).await {
+            Err(err) if err.is_transient() && attempt < MAX_SEND_ATTEMPTS => {
+                let delay = backend::retry_delay(attempt);
+                attempt += 1;
+                hooks.retrying(Some(format!(
+                    "{} Retrying in {}s (attempt {} of {})...",
+                    err,
+                    delay.as_secs(),
+                    attempt,
+                    MAX_SEND_ATTEMPTS
+                )));
+                let until = Instant::now() + delay;
+                while Instant::now() < until && !hooks.cancelled() {
+                    tokio::time::sleep(Duration::from_millis(CANCEL_POLL_MS)).await;
+                }
+                if hooks.cancelled() {
+                    break Err(err);
+                }
+            }
+            other => break other,
+        }
+    };
+    hooks.retrying(None);
+    sent
+}
+
+// the `top_k` chunks most similar to `query`, among folders indexed with `model`
+async fn retrieve_kb_chunks(
+    ctx: &ReplyContext<'_>,
+    model: &str,
+    query: &str,
+    top_k: usize,
+) -> Result<Vec<KbHit>, String> {
+    let query_embedding = backend::embed_texts(ctx.client, ctx.base_url, model, &[query.to_string()])
+        .await?
+        .pop()
+        .unwrap_or_default();
+
+    let conn = storage::open(ctx.db_path).map_err(|e| e.to_string())?;
+    let chunks = storage::kb_chunks(&conn, model).map_err(|e| e.to_string())?;
+    Ok(rank_kb_chunks(&query_embedding, chunks, top_k))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{client, http_response, mock_server_replies};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn slots_are_limited_and_freed_on_drop() {
+        let slots = GenerationSlots::default();
+        let first = slots.acquire(1, || false).await.unwrap();
+        assert_eq!(slots.running(), 1);
+
+        let waiting = {
+            let slots = slots.clone();
+            tokio::spawn(async move { slots.acquire(1, || false).await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        assert_eq!(waiting.await.unwrap(), Some(()));
+        assert_eq!(slots.running(), 0);
+
+        // no limit
+        let a = slots.acquire(0, || false).await.unwrap();
+        let b = slots.acquire(0, || false).await.unwrap();
+        assert_eq!(slots.running(), 2);
+        drop((a, b));
+    }
+
+    #[tokio::test]
+    async fn waiters_are_served_oldest_first_and_can_give_up() {
+        let slots = GenerationSlots::default();
+        let held = slots.acquire(1, || false).await.unwrap();
+        assert!(slots.acquire(1, || true).await.is_none());
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut waiters = Vec::new();
+        for n in 0..3 {
+            let (slots, order) = (slots.clone(), order.clone());
+            waiters.push(tokio::spawn(async move {
+                let _slot = slots.acquire(1, || false).await;
+                order.lock().unwrap().push(n);
+            }));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        // a waiter dropped while queued doesn't hold up the ones behind it
+        let abandoned = {
+            let slots = slots.clone();
+            tokio::spawn(async move { slots.acquire(1, || false).await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        abandoned.abort();
+
+        drop(held);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2]);
+        assert!(slots.lock().waiting.is_empty());
+    }
+
+    // keeps what the pipeline reported
+    #[derive(Default)]
+    struct Recorded {
+        slot: Option<SlotGuard>,
+        stored: Vec<String>,
+        tool_calls: Vec<String>,
+        failures: Vec<String>,
+    }
+
+    impl ReplyHooks for Recorded {
+        fn started(&mut self, slot: SlotGuard) {
+            self.slot = Some(slot);
+        }
+
+        fn stored(&mut self, message: &ChatMessage) {
+            self.stored.push(message.role.clone());
+        }
+
+        fn failed(&mut self, err: &ChatError, _record: Result<ChatErrorRecord, StorageError>) {
+            self.failures.push(err.kind().to_string());
+        }
+
+        async fn run_tool(&mut self, call: &OllamaFunctionCall) -> String {
+            self.tool_calls.push(call.name.clone());
+            "42".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn replies_answer_tool_calls_and_retry_malformed_structured_output() {
+        let path = std::env::temp_dir()
+            .join(format!("rusty-chat-reply-{}.db", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let _ = std::fs::remove_file(&path);
+        let conn = storage::open(&path).unwrap();
+        storage::create_chat(&conn, "c", "Chat", None).unwrap();
+        storage::insert_message(&conn, "c", &ChatMessage::new("user", "What is six times seven?"), None).unwrap();
+        storage::save_chat_format(&conn, "c", Some("json")).unwrap();
+
+        let call = r#"{"message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"calc","arguments":{"expr":"6*7"}}}]},"done":true}"#;
+        let prose = r#"{"message":{"role":"assistant","content":"It is 42."},"done":true}"#;
+        let json = r#"{"message":{"role":"assistant","content":"{\"answer\":42}"},"done":true}"#;
+        let (ollama, requests) = mock_server_replies(vec![
+            http_response("200 OK", call),
+            http_response("200 OK", prose),
+            http_response("200 OK", json),
+        ]);
+        let (client, slots) = (client(), GenerationSlots::default());
+        let settings = Settings {
+            model: "llama3".to_string(),
+            ..Settings::default()
+        };
+        let ctx = ReplyContext {
+            client: &client,
+            db_path: &path,
+            base_url: &ollama,
+            slots: &slots,
+            settings: &settings,
+            model: None,
+            tools: vec![json!({ "type": "function", "function": { "name": "calc" } })],
+        };
+        let mut hooks = Recorded::default();
+        let reply = generate_reply(&ctx, "c", "What is six times seven?", &mut hooks)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(reply.content, r#"{"answer":42}"#);
+        assert_eq!(hooks.tool_calls, vec!["calc"]);
+        assert_eq!(hooks.stored, vec!["assistant", "tool", "assistant"]);
+        assert!(hooks.failures.is_empty());
+        let sent: Vec<String> = requests.try_iter().collect();
+        assert!(sent[0].contains(r#""format":"json""#));
+        assert!(sent[1].contains(r#""content":"42""#));
+        assert!(sent[2].contains("not valid JSON"));
+        let roles: Vec<String> =
+            storage::load_chat_messages(&conn, "c").unwrap().into_iter().map(|m| m.role).collect();
+        assert_eq!(roles, vec!["user", "assistant", "tool", "assistant"]);
+        // the slot was handed over and is freed with it
+        assert_eq!(slots.running(), 1);
+        drop(hooks);
+        assert_eq!(slots.running(), 0);
+        std::fs::remove_file(path).ok();
+    }
+}
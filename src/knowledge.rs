@@ -0,0 +1,176 @@
+// retrieval for the knowledge base and semantic search: splitting documents, storing
+// embeddings and ranking them against a query
+
+use serde::{Deserialize, Serialize};
+
+// a retrieved chunk; stored (without content) on the reply as its sources
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KbHit {
+    pub path: String,
+    pub chunk_index: i64,
+    pub score: f32,
+    #[serde(default, skip_serializing)]
+    pub content: String,
+}
+
+// a stored chunk of an indexed file with its embedding
+#[derive(Clone, Debug, PartialEq)]
+pub struct KbChunk {
+    pub file_path: String,
+    pub chunk_index: i64,
+    pub content: String,
+    pub embedding: Vec<f32>,
+}
+
+// split text into overlapping chunks, preferring to break at line ends
+pub fn chunk_text(text: &str, size: usize, overlap: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + size).min(chars.len());
+        if end < chars.len() {
+            // back up to the last newline in the second half of the window
+            if let Some(nl) = chars[start + size / 2..end].iter().rposition(|c| *c == '\n') {
+                end = start + size / 2 + nl + 1;
+            }
+        }
+        let chunk: String = chars[start..end].iter().collect();
+        if !chunk.trim().is_empty() {
+            chunks.push(chunk);
+        }
+        if end >= chars.len() {
+            break;
+        }
+        start = end.saturating_sub(overlap).max(start + 1);
+    }
+    chunks
+}
+
+// embeddings are stored as little-endian f32 blobs
+pub fn embedding_to_blob(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn blob_to_embedding(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let mut dot = 0.0_f32;
+    let mut norm_a = 0.0_f32;
+    let mut norm_b = 0.0_f32;
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a.sqrt() * norm_b.sqrt())
+    }
+}
+
+// the `top_k` chunks most similar to `query`, best first
+pub fn rank_kb_chunks(query: &[f32], chunks: Vec<KbChunk>, top_k: usize) -> Vec<KbHit> {
+    let mut hits: Vec<KbHit> = chunks
+        .into_iter()
+        .map(|c| KbHit {
+            score: cosine_similarity(query, &c.embedding),
+            path: c.file_path,
+            chunk_index: c.chunk_index,
+            content: c.content,
+        })
+        .collect();
+    hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    hits.truncate(top_k);
+    hits
+}
+
+// system message carrying the retrieved excerpts, numbered so the model can cite them
+pub fn kb_context_message(hits: &[KbHit]) -> String {
+    let mut text = String::from(
+        "Use the following excerpts from the user's documents to answer. Cite them as [1], [2], ... when you use them. If they don't contain the answer, say so.\n",
+    );
+    for (i, hit) in hits.iter().enumerate() {
+        text.push_str(&format!(
+            "\n[{}] {} (part {})\n{}\n",
+            i + 1,
+            hit.path,
+            hit.chunk_index + 1,
+            hit.content.trim()
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(path: &str, embedding: Vec<f32>) -> KbChunk {
+        KbChunk {
+            file_path: path.to_string(),
+            chunk_index: 0,
+            content: format!("text of {}", path),
+            embedding,
+        }
+    }
+
+    #[test]
+    fn chunks_overlap_and_break_at_line_ends() {
+        let text = "aaaa\nbbbb\ncccc\ndddd\n";
+        let chunks = chunk_text(text, 12, 5);
+        assert_eq!(chunks, vec!["aaaa\nbbbb\n", "bbbb\ncccc\n", "cccc\ndddd\n"]);
+        assert!(chunk_text("  \n ", 12, 5).is_empty());
+        assert_eq!(chunk_text("short", 12, 5), vec!["short"]);
+    }
+
+    #[test]
+    fn chunking_always_advances() {
+        let text = "x".repeat(50);
+        let chunks = chunk_text(&text, 10, 20);
+        assert!(chunks.len() < 50);
+        assert_eq!(chunks.last().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn embeddings_survive_the_blob_round_trip() {
+        let embedding = vec![0.5, -1.25, 3.0e-7];
+        assert_eq!(blob_to_embedding(&embedding_to_blob(&embedding)), embedding);
+    }
+
+    #[test]
+    fn similarity_of_mismatched_or_empty_vectors_is_zero() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn best_chunks_come_first() {
+        let chunks = vec![
+            chunk("far.md", vec![0.0, 1.0]),
+            chunk("near.md", vec![1.0, 0.1]),
+            chunk("exact.md", vec![1.0, 0.0]),
+        ];
+        let hits = rank_kb_chunks(&[1.0, 0.0], chunks, 2);
+        let paths: Vec<&str> = hits.iter().map(|h| h.path.as_str()).collect();
+        assert_eq!(paths, vec!["exact.md", "near.md"]);
+        assert_eq!(hits[0].content, "text of exact.md");
+    }
+
+    #[test]
+    fn context_numbers_the_excerpts() {
+        let hits = rank_kb_chunks(&[1.0], vec![chunk("notes.md", vec![1.0])], 4);
+        let text = kb_context_message(&hits);
+        assert!(text.ends_with("\n[1] notes.md (part 1)\ntext of notes.md\n"));
+    }
+}
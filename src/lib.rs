@@ -0,0 +1,21 @@
+// RustyChat's core, independent of the UI: storage, the Ollama client, request building, the model's tools, retrieval and the local API server with its OpenAI-compatible proxy
+
+// the SQLite storage, the API server and reply generation (with its slots) only exist in the desktop build
+#[cfg(not(target_arch = "wasm32"))]
+pub mod api;
+pub mod backend;
+pub mod conversation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod generation;
+pub mod knowledge;
+pub mod modelfile;
+pub mod prompts;
+#[cfg(not(target_arch = "wasm32"))]
+mod proxy;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod storage;
+pub mod store;
+pub mod tools;
+
+#[cfg(test)]
+mod test_support;
@@ -0,0 +1,199 @@
+// Modelfiles: the text behind a model as /api/show prints it, and the structured fields
+// /api/create takes instead
+
+use serde_json::Value;
+
+// starting point for the Modelfile editor: /api/show's modelfile with FROM pointing at the
+// source model instead of the blob path Ollama prints
+pub fn seed_modelfile(source: &str, modelfile: &str) -> String {
+    let mut out = format!("FROM {}\n", source);
+    let mut from_replaced = false;
+    // inside a """ block every line is part of the value, `#` and FROM included
+    let mut in_block = false;
+    for line in modelfile.lines() {
+        let trimmed = line.trim_start();
+        let starts_in_block = in_block;
+        if line.matches("\"\"\"").count() % 2 == 1 {
+            in_block = !in_block;
+        }
+        if !starts_in_block {
+            if trimmed.starts_with('#') {
+                continue;
+            }
+            if !from_replaced && trimmed.to_ascii_uppercase().starts_with("FROM ") {
+                from_replaced = true;
+                continue;
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.trim_end().to_string() + "\n"
+}
+
+// PARAMETER values are sent typed: numbers and booleans as JSON, anything else as a string
+fn modelfile_param_value(raw: &str) -> Value {
+    if let Ok(i) = raw.parse::<i64>() {
+        return Value::from(i);
+    }
+    if let Ok(f) = raw.parse::<f64>() {
+        return Value::from(f);
+    }
+    match raw {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::String(raw.trim_matches('"').to_string()),
+    }
+}
+
+// split a Modelfile into the structured fields /api/create expects (from, system, template, ...)
+pub fn parse_modelfile(text: &str) -> Result<serde_json::Map<String, Value>, String> {
+    let mut out = serde_json::Map::new();
+    let mut parameters = serde_json::Map::new();
+    let mut messages: Vec<Value> = Vec::new();
+
+    let lines: Vec<&str> = text.lines().collect();
+    let mut i = 0;
+    while i < lines.len() {
+        let line_no = i + 1;
+        let line = lines[i].trim();
+        i += 1;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (instruction, rest) = match line.split_once(char::is_whitespace) {
+            Some((ins, rest)) => (ins.to_ascii_uppercase(), rest.trim()),
+            None => (line.to_ascii_uppercase(), ""),
+        };
+
+        // values may be """multi-line""" blocks, "quoted" or bare
+        let value = if let Some(after) = rest.strip_prefix("\"\"\"") {
+            if let Some(end) = after.find("\"\"\"") {
+                after[..end].to_string()
+            } else {
+                let mut block = vec![after.to_string()];
+                loop {
+                    if i >= lines.len() {
+                        return Err(format!("line {}: unterminated \"\"\" block", line_no));
+                    }
+                    let next = lines[i];
+                    i += 1;
+                    if let Some(end) = next.find("\"\"\"") {
+                        block.push(next[..end].to_string());
+                        break;
+                    }
+                    block.push(next.to_string());
+                }
+                block.join("\n").trim_start_matches('\n').to_string()
+            }
+        } else if rest.len() >= 2 && rest.starts_with('"') && rest.ends_with('"') {
+            rest[1..rest.len() - 1].to_string()
+        } else {
+            rest.to_string()
+        };
+
+        match instruction.as_str() {
+            "FROM" => {
+                out.insert("from".into(), Value::String(value));
+            }
+            "SYSTEM" => {
+                out.insert("system".into(), Value::String(value));
+            }
+            "TEMPLATE" => {
+                out.insert("template".into(), Value::String(value));
+            }
+            "LICENSE" => {
+                out.insert("license".into(), Value::String(value));
+            }
+            "PARAMETER" => {
+                let (name, raw) = value
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("line {}: PARAMETER needs a name and a value", line_no))?;
+                let param = modelfile_param_value(raw.trim());
+                // stop may be given several times and is sent as a list
+                if name == "stop" {
+                    let entry = parameters
+                        .entry("stop")
+                        .or_insert_with(|| Value::Array(vec![]));
+                    if let Value::Array(list) = entry {
+                        list.push(Value::String(raw.trim().trim_matches('"').to_string()));
+                    }
+                } else {
+                    parameters.insert(name.to_string(), param);
+                }
+            }
+            "MESSAGE" => {
+                let (role, content) = value
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("line {}: MESSAGE needs a role and content", line_no))?;
+                messages.push(serde_json::json!({ "role": role, "content": content.trim() }));
+            }
+            other => {
+                return Err(format!("line {}: unsupported instruction {}", line_no, other));
+            }
+        }
+    }
+
+    if !out.contains_key("from") {
+        return Err("Modelfile needs a FROM line".to_string());
+    }
+    if !parameters.is_empty() {
+        out.insert("parameters".into(), Value::Object(parameters));
+    }
+    if !messages.is_empty() {
+        out.insert("messages".into(), Value::Array(messages));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_points_from_at_the_source_model() {
+        let shown = "# Modelfile generated by \"ollama show\"\nFROM /blobs/sha256-abc\nSYSTEM be brief\nPARAMETER top_k 20\n";
+        assert_eq!(seed_modelfile("llama3", shown), "FROM llama3\nSYSTEM be brief\nPARAMETER top_k 20\n");
+    }
+
+    #[test]
+    fn seed_keeps_comment_lines_inside_blocks() {
+        let shown = "FROM /blobs/sha256-abc\nTEMPLATE \"\"\"\n# {{ .System }}\nFROM the user: {{ .Prompt }}\n\"\"\"\n# dropped\nSYSTEM \"\"\"# heading\"\"\"\n";
+        let seeded = seed_modelfile("llama3", shown);
+        assert_eq!(
+            seeded,
+            "FROM llama3\nTEMPLATE \"\"\"\n# {{ .System }}\nFROM the user: {{ .Prompt }}\n\"\"\"\nSYSTEM \"\"\"# heading\"\"\"\n"
+        );
+        assert_eq!(parse_modelfile(&seeded).unwrap()["template"], "# {{ .System }}\nFROM the user: {{ .Prompt }}\n");
+    }
+
+    #[test]
+    fn parses_instructions_into_create_fields() {
+        let text = "FROM llama3\n# a comment\nSYSTEM \"\"\"You are\nterse.\"\"\"\nPARAMETER temperature 0.2\nPARAMETER num_ctx 4096\nPARAMETER stop \"<|end|>\"\nPARAMETER stop User:\nMESSAGE user hi there\n";
+        let fields = parse_modelfile(text).unwrap();
+        assert_eq!(fields["from"], "llama3");
+        assert_eq!(fields["system"], "You are\nterse.");
+        assert_eq!(fields["parameters"]["temperature"], 0.2);
+        assert_eq!(fields["parameters"]["num_ctx"], 4096);
+        assert_eq!(fields["parameters"]["stop"], serde_json::json!(["<|end|>", "User:"]));
+        assert_eq!(fields["messages"], serde_json::json!([{ "role": "user", "content": "hi there" }]));
+    }
+
+    #[test]
+    fn rejects_what_create_would_not_understand() {
+        assert_eq!(parse_modelfile("SYSTEM hi").unwrap_err(), "Modelfile needs a FROM line");
+        assert_eq!(
+            parse_modelfile("FROM llama3\nADAPTER x").unwrap_err(),
+            "line 2: unsupported instruction ADAPTER"
+        );
+        assert_eq!(
+            parse_modelfile("FROM llama3\nSYSTEM \"\"\"open").unwrap_err(),
+            "line 2: unterminated \"\"\" block"
+        );
+        assert_eq!(
+            parse_modelfile("FROM llama3\nPARAMETER temperature").unwrap_err(),
+            "line 2: PARAMETER needs a name and a value"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+// the prompt library: reusable prompts inserted with a slash command, with `{{variable}}`
+// placeholders asked for on insert
+
+use serde::{Deserialize, Serialize};
+
+// a reusable prompt; `{{variable}}` placeholders are filled in when it is inserted
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub body: String,
+}
+
+// template names double as slash commands, so keep them to a single word
+fn valid_template_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 40
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// why a template can't be saved, if it can't
+pub fn check_template(template: &PromptTemplate) -> Result<(), String> {
+    if !valid_template_name(&template.name) {
+        return Err(format!(
+            "'{}' is not a valid name (letters, digits, '-' and '_' only)",
+            template.name
+        ));
+    }
+    if template.body.trim().is_empty() {
+        return Err(format!("template '{}' has an empty prompt", template.name));
+    }
+    Ok(())
+}
+
+// distinct `{{variable}}` names in order of first appearance
+pub fn template_variables(body: &str) -> Vec<String> {
+    let mut vars: Vec<String> = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let name = after[..end].trim();
+        if !name.is_empty() && !vars.iter().any(|v| v == name) {
+            vars.push(name.to_string());
+        }
+        rest = &after[end + 2..];
+    }
+    vars
+}
+
+// replace each `{{variable}}` with its value; placeholders without a value are kept as written
+pub fn fill_template(body: &str, values: &[(String, String)]) -> String {
+    let mut out = String::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let name = after[..end].trim();
+        match values.iter().find(|(k, _)| k == name) {
+            Some((_, value)) => out.push_str(value),
+            None => out.push_str(&rest[start..start + end + 4]),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// templates whose name starts with the slash command being typed ("/rev" -> review)
+pub fn slash_matches(templates: &[PromptTemplate], input: &str) -> Vec<PromptTemplate> {
+    let Some(command) = input.strip_prefix('/') else {
+        return vec![];
+    };
+    if command.contains(char::is_whitespace) {
+        return vec![];
+    }
+    templates
+        .iter()
+        .filter(|t| t.name.starts_with(command))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(name: &str, body: &str) -> PromptTemplate {
+        PromptTemplate {
+            name: name.to_string(),
+            description: String::new(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn variables_are_listed_once_in_order() {
+        assert_eq!(
+            template_variables("Review {{ language }} code for {{goal}}; {{language}} only. {{}} {{open"),
+            vec!["language", "goal"]
+        );
+    }
+
+    #[test]
+    fn fill_keeps_placeholders_without_a_value() {
+        let values = vec![("language".to_string(), "Rust".to_string())];
+        assert_eq!(
+            fill_template("Review {{ language }} code for {{goal}}", &values),
+            "Review Rust code for {{goal}}"
+        );
+        assert_eq!(fill_template("no end {{language", &values), "no end {{language");
+    }
+
+    #[test]
+    fn slash_command_matches_by_prefix() {
+        let templates = vec![template("review", "r"), template("refactor", "f"), template("explain", "e")];
+        let names = |input: &str| -> Vec<String> { slash_matches(&templates, input).into_iter().map(|t| t.name).collect() };
+        assert_eq!(names("/re"), vec!["review", "refactor"]);
+        assert_eq!(names("/"), vec!["review", "refactor", "explain"]);
+        assert!(names("re").is_empty());
+        assert!(names("/review this").is_empty());
+    }
+
+    #[test]
+    fn names_must_work_as_slash_commands() {
+        assert!(check_template(&template("fix-bug_2", "Fix it")).is_ok());
+        assert!(check_template(&template("two words", "x")).is_err());
+        assert!(check_template(&template("", "x")).is_err());
+        assert!(check_template(&template(&"a".repeat(41), "x")).is_err());
+        assert_eq!(
+            check_template(&template("empty", "  ")).unwrap_err(),
+            "template 'empty' has an empty prompt"
+        );
+    }
+}
@@ -0,0 +1,454 @@
+// OpenAI-compatible endpoints on the local API server. Requests go to Ollama's own OpenAI
+// endpoint unchanged (streaming included) and every conversation is recorded as a chat tagged
+// with the calling client. OpenAI requests carry the whole conversation, so a request continues
+// the chat whose recorded conversation equals everything before its new turns
+
+use crate::api::{ApiError, ApiEvent, ApiState};
+use crate::backend::ChatError;
+use crate::storage::{self, ChatMessage, GenerationMeta, StorageError};
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+// header a tool can send to choose the name its chats are tagged with (default: its User-Agent)
+const CLIENT_HEADER: &str = "x-client-name";
+const MAX_CLIENT_LEN: usize = 64;
+// chats created by the proxy are titled with the start of the first question
+const TITLE_CHARS: usize = 60;
+
+pub(crate) fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/models", get(list_models))
+}
+
+// name of the calling tool, as shown on its chats
+fn client_name(headers: &HeaderMap) -> String {
+    let name = [CLIENT_HEADER, header::USER_AGENT.as_str()]
+        .iter()
+        .filter_map(|h| headers.get(*h)?.to_str().ok())
+        .map(str::trim)
+        .find(|v| !v.is_empty())
+        .unwrap_or("unknown");
+    name.chars().take(MAX_CLIENT_LEN).collect()
+}
+
+// text of an OpenAI message `content`: a plain string or a list of parts of which only text is kept
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(s) => s.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+// (role, text) of every message in a request
+fn transcript(request: &Value) -> Vec<(String, String)> {
+    request
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .map(|messages| {
+            messages
+                .iter()
+                .map(|m| {
+                    let role = m.get("role").and_then(|r| r.as_str()).unwrap_or("user");
+                    (role.to_string(), content_text(m.get("content").unwrap_or(&Value::Null)))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// where the turns new in this request start: right after the last assistant reply
+fn new_turns_start(messages: &[(String, String)]) -> usize {
+    messages
+        .iter()
+        .rposition(|(role, _)| role == "assistant")
+        .map(|i| i + 1)
+        .unwrap_or(0)
+}
+
+fn fingerprint(messages: &[(String, String)]) -> String {
+    storage::fnv1a_hex(&json!(messages).to_string())
+}
+
+/* ================= FORWARDING ================= */
+
+async fn chat_completions(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(mut request): Json<Value>,
+) -> Result<Response, ApiError> {
+    let client = client_name(&headers);
+    let settings = storage::load_settings(&state.db()?)?;
+    // tools that don't pick a model get the one chosen in Settings
+    if request.get("model").and_then(|m| m.as_str()).is_none_or(|m| m.trim().is_empty()) {
+        if settings.model.is_empty() {
+            return Err(ApiError(StatusCode::BAD_REQUEST, ChatError::NoModel.to_string()));
+        }
+        request["model"] = Value::String(settings.model);
+    }
+    let model = request["model"].as_str().unwrap_or_default().to_string();
+    let stream = request.get("stream").and_then(|s| s.as_bool()).unwrap_or(false);
+    let messages = transcript(&request);
+
+    // held until the reply has been passed on, like a chat sent from the window
+    let slot = state.config.slots.acquire(settings.max_generations, || false).await;
+    let url = format!("{}/v1/chat/completions", state.config.base_url);
+    let mut upstream = state
+        .client
+        .post(&url)
+        .json(&request)
+        .send()
+        .await
+        .map_err(|e| ApiError(StatusCode::BAD_GATEWAY, ChatError::from_reqwest(&e).to_string()))?;
+    let status = StatusCode::from_u16(upstream.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let content_type = upstream
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .cloned()
+        .unwrap_or(header::HeaderValue::from_static("application/json"));
+
+    // failed requests are passed back as they are and not recorded
+    if !status.is_success() || !stream {
+        let body = upstream
+            .bytes()
+            .await
+            .map_err(|e| ApiError(StatusCode::BAD_GATEWAY, e.to_string()))?;
+        if status.is_success() {
+            let reply = serde_json::from_slice::<Value>(&body)
+                .ok()
+                .map(|v| content_text(&v["choices"][0]["message"]["content"]))
+                .unwrap_or_default();
+            record(&state, &client, &model, &messages, &reply);
+        }
+        return Ok((status, [(header::CONTENT_TYPE, content_type)], body).into_response());
+    }
+
+    // passed through chunk by chunk while the reply text is collected for the record
+    let (chunks, receiver) = mpsc::unbounded_channel::<Result<Bytes, std::io::Error>>();
+    tokio::spawn(async move {
+        let _slot = slot;
+        let mut reply = StreamedReply::default();
+        loop {
+            match upstream.chunk().await {
+                Ok(Some(chunk)) => {
+                    reply.push(&chunk);
+                    let _ = chunks.send(Ok(chunk));
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = chunks.send(Err(std::io::Error::other(e.to_string())));
+                    return;
+                }
+            }
+        }
+        record(&state, &client, &model, &messages, &reply.finish());
+    });
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        let chunk = receiver.recv().await?;
+        Some((chunk, receiver))
+    });
+    Ok((status, [(header::CONTENT_TYPE, content_type)], Body::from_stream(body)).into_response())
+}
+
+async fn list_models(State(state): State<ApiState>) -> Result<Response, ApiError> {
+    let url = format!("{}/v1/models", state.config.base_url);
+    let upstream = state
+        .client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| ApiError(StatusCode::BAD_GATEWAY, ChatError::from_reqwest(&e).to_string()))?;
+    let status = StatusCode::from_u16(upstream.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let body = upstream
+        .bytes()
+        .await
+        .map_err(|e| ApiError(StatusCode::BAD_GATEWAY, e.to_string()))?;
+    Ok((status, [(header::CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+// reply text collected from a server-sent event stream of chat.completion.chunk objects
+#[derive(Default)]
+struct StreamedReply {
+    buf: Vec<u8>,
+    text: String,
+}
+
+impl StreamedReply {
+    fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            self.line(&String::from_utf8_lossy(&line));
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        let Some(data) = line.trim().strip_prefix("data:") else {
+            return;
+        };
+        if let Ok(event) = serde_json::from_str::<Value>(data.trim()) {
+            if let Some(text) = event["choices"][0]["delta"]["content"].as_str() {
+                self.text.push_str(text);
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let rest = String::from_utf8_lossy(&std::mem::take(&mut self.buf)).to_string();
+        self.line(&rest);
+        self.text
+    }
+}
+
+/* ================= RECORDING ================= */
+
+// a failure to record never fails the proxied request
+fn record(state: &ApiState, client: &str, model: &str, messages: &[(String, String)], reply: &str) {
+    if let Err(e) = try_record(state, client, model, messages, reply) {
+        eprintln!("Could not record proxied conversation from {}: {}", client, e);
+    }
+}
+
+fn try_record(
+    state: &ApiState,
+    client: &str,
+    model: &str,
+    messages: &[(String, String)],
+    reply: &str,
+) -> Result<(), StorageError> {
+    let conn = storage::open(&state.config.db_path)?;
+    let split = new_turns_start(messages);
+    let known = if split > 0 {
+        storage::find_proxy_chat(&conn, client, &fingerprint(&messages[..split]))?
+    } else {
+        None
+    };
+    let created = known.is_none();
+    let (chat_id, new_turns) = match known {
+        Some(chat_id) => (chat_id, &messages[split..]),
+        None => {
+            let chat_id = uuid::Uuid::new_v4().to_string();
+            let title = messages
+                .iter()
+                .find(|(role, text)| role == "user" && !text.trim().is_empty())
+                .map(|(_, text)| text.trim().lines().next().unwrap_or("").chars().take(TITLE_CHARS).collect())
+                .unwrap_or_else(|| format!("Chat from {}", client));
+            storage::create_chat(&conn, &chat_id, &storage::clamp_title(&title), None)?;
+            (chat_id, messages)
+        }
+    };
+
+    // system prompts aren't shown in chats; their hash is kept with the reply like for the GUI
+    for (role, text) in new_turns.iter().filter(|(role, _)| role == "user" || role == "assistant") {
+        storage::insert_message(&conn, &chat_id, &ChatMessage::new(role, text.clone()), None)?;
+    }
+    let system_prompt = messages
+        .iter()
+        .find(|(role, _)| role == "system")
+        .map(|(_, text)| text.as_str())
+        .unwrap_or("");
+    let meta = GenerationMeta {
+        endpoint: state.config.base_url.clone(),
+        system_prompt_hash: storage::hash_system_prompt(system_prompt),
+        options: None,
+    };
+    let answer = ChatMessage {
+        model: Some(model.to_string()),
+        ..ChatMessage::new("assistant", reply)
+    };
+    storage::insert_message(&conn, &chat_id, &answer, Some(&meta))?;
+    storage::enforce_history_limit(&conn, &chat_id);
+
+    let mut conversation = messages.to_vec();
+    conversation.push(("assistant".to_string(), reply.to_string()));
+    storage::set_proxy_chat(&conn, &chat_id, client, &fingerprint(&conversation))?;
+    // announced once it's tagged, so the sidebar shows the client right away
+    if created {
+        state.notify(ApiEvent::ChatsChanged);
+    }
+    state.notify(ApiEvent::MessagesChanged(chat_id));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{self, ApiConfig};
+    use crate::generation::GenerationSlots;
+    use crate::test_support::{client, http_response, mock_server_replies};
+    use tokio::sync::broadcast;
+
+    fn turns(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(r, t)| (r.to_string(), t.to_string())).collect()
+    }
+
+    #[test]
+    fn transcript_keeps_text_parts_only() {
+        let request = json!({
+            "messages": [
+                { "role": "system", "content": "be brief" },
+                { "role": "user", "content": [
+                    { "type": "text", "text": "what is this?" },
+                    { "type": "image_url", "image_url": { "url": "data:..." } }
+                ] }
+            ]
+        });
+        assert_eq!(
+            transcript(&request),
+            turns(&[("system", "be brief"), ("user", "what is this?")])
+        );
+        assert!(transcript(&json!({})).is_empty());
+    }
+
+    #[test]
+    fn new_turns_follow_the_last_reply() {
+        assert_eq!(new_turns_start(&turns(&[("system", "s"), ("user", "a")])), 0);
+        let continued = turns(&[("user", "a"), ("assistant", "b"), ("user", "c")]);
+        assert_eq!(new_turns_start(&continued), 2);
+        assert_eq!(fingerprint(&continued[..2]), fingerprint(&turns(&[("user", "a"), ("assistant", "b")])));
+        assert_ne!(fingerprint(&continued[..2]), fingerprint(&continued));
+    }
+
+    #[test]
+    fn client_is_named_by_header_then_user_agent() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(client_name(&headers), "unknown");
+        headers.insert(header::USER_AGENT, "OpenAI/Python 1.40.0".parse().unwrap());
+        assert_eq!(client_name(&headers), "OpenAI/Python 1.40.0");
+        headers.insert(CLIENT_HEADER, "my-editor".parse().unwrap());
+        assert_eq!(client_name(&headers), "my-editor");
+    }
+
+    #[test]
+    fn streamed_reply_joins_deltas_split_across_chunks() {
+        let mut reply = StreamedReply::default();
+        reply.push(b"data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"Hel\"}}]}\n\ndata: {\"choi");
+        reply.push(b"ces\":[{\"delta\":{\"content\":\"lo\"}}]}\n\ndata: [DONE]");
+        assert_eq!(reply.finish(), "Hello");
+    }
+
+    #[tokio::test]
+    async fn conversations_are_recorded_per_client() {
+        let completion = json!({
+            "choices": [{ "message": { "role": "assistant", "content": "Paris." } }]
+        })
+        .to_string();
+        let stream = [
+            r#"data: {"choices":[{"delta":{"content":"About 2 "}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":"million."}}]}"#,
+            "data: [DONE]",
+        ]
+        .join("\n\n");
+        let (ollama, requests) = mock_server_replies(vec![
+            http_response("200 OK", &completion),
+            http_response("200 OK", &stream),
+            http_response("200 OK", &completion),
+        ]);
+        let path = std::env::temp_dir()
+            .join(format!("rusty-chat-proxy-{}.db", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let _ = std::fs::remove_file(&path);
+        let (events, _) = broadcast::channel(16);
+        let config = ApiConfig {
+            db_path: path.clone(),
+            port: 0,
+            token: "t".to_string(),
+            base_url: ollama,
+            slots: GenerationSlots::default(),
+        };
+        let server = api::start(config, events).await.unwrap();
+        let url = format!("http://{}/v1/chat/completions", server.addr());
+        let http = client();
+        let first = json!({
+            "model": "llama3",
+            "messages": [
+                { "role": "system", "content": "be brief" },
+                { "role": "user", "content": "Capital of France?" }
+            ]
+        });
+
+        let answer: Value = http
+            .post(&url)
+            .bearer_auth("t")
+            .header(CLIENT_HEADER, "editor")
+            .json(&first)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(answer["choices"][0]["message"]["content"], "Paris.");
+        assert!(requests.recv().unwrap().contains(r#""model":"llama3""#));
+
+        let mut second = first.clone();
+        second["stream"] = json!(true);
+        second["messages"].as_array_mut().unwrap().extend([
+            json!({ "role": "assistant", "content": "Paris." }),
+            json!({ "role": "user", "content": "Population?" }),
+        ]);
+        let streamed = http
+            .post(&url)
+            .bearer_auth("t")
+            .header(CLIENT_HEADER, "editor")
+            .json(&second)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(streamed, stream);
+
+        // same question from another tool starts its own chat
+        http.post(&url)
+            .bearer_auth("t")
+            .header(CLIENT_HEADER, "script")
+            .json(&first)
+            .send()
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+
+        // the streamed reply is recorded once the stream has ended
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let conn = storage::open(&path).unwrap();
+        let chats = storage::list_chats(&conn).unwrap();
+        assert_eq!(chats.len(), 2);
+        let clients = storage::chat_clients(&conn);
+        let editor_chat = chats.iter().find(|(id, _)| clients[id] == "editor").unwrap();
+        assert_eq!(editor_chat.1, "Capital of France?");
+        let recorded: Vec<(String, String)> = storage::load_chat_messages(&conn, &editor_chat.0)
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.role, m.content))
+            .collect();
+        assert_eq!(
+            recorded,
+            turns(&[
+                ("user", "Capital of France?"),
+                ("assistant", "Paris."),
+                ("user", "Population?"),
+                ("assistant", "About 2 million."),
+            ])
+        );
+        drop(conn);
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,2892 @@
+// chats, messages and settings in SQLite: the schema, its upgrades and the queries the UI needs.
+// Desktop only; the browser build keeps its data through `store::KvStore`
+
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use rusqlite::{backup::Backup, params, types::ValueRef, Connection, OpenFlags, Row};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::backend::ChatError;
+use crate::knowledge::{blob_to_embedding, embedding_to_blob, KbChunk};
+use crate::prompts::PromptTemplate;
+pub use crate::store::{
+    clamp_title, clamp_to_i32, fnv1a_hex, hash_system_prompt, ChatMessage, ChatStore, GenerationMeta, Settings,
+    StorageError, DEFAULT_API_PORT, MAX_HISTORY_MESSAGES, MAX_TITLE_LEN,
+};
+use crate::tools::ToolPolicy;
+
+// how long a query waits for another connection to release a lock before failing
+const BUSY_TIMEOUT_SECS: u64 = 5;
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        StorageError::Query(e.to_string())
+    }
+}
+
+// open (creating if needed) the database at `path` and bring its schema up to date
+pub fn open(path: &str) -> Result<Connection, StorageError> {
+    let open_error = |e: rusqlite::Error| StorageError::Open {
+        path: path.to_string(),
+        reason: e.to_string(),
+    };
+    let conn = Connection::open(path).map_err(open_error)?;
+    conn.busy_timeout(Duration::from_secs(BUSY_TIMEOUT_SECS))
+        .map_err(open_error)?;
+    // a file that isn't a database (or can't be written) only shows up once it's queried
+    migrate(&conn).map_err(|e| match e {
+        StorageError::Query(reason) => StorageError::Open {
+            path: path.to_string(),
+            reason,
+        },
+        other => other,
+    })?;
+    Ok(conn)
+}
+
+// the SQLite database as a ChatStore; opens the file for every call, like the API server does
+pub struct SqliteStore {
+    path: String,
+}
+
+impl SqliteStore {
+    pub fn new(path: &str) -> Self {
+        SqliteStore {
+            path: path.to_string(),
+        }
+    }
+}
+
+impl ChatStore for SqliteStore {
+    fn load_settings(&self) -> Result<Settings, StorageError> {
+        load_settings(&open(&self.path)?)
+    }
+
+    fn save_settings(&self, settings: &Settings) -> Result<(), StorageError> {
+        save_settings(&open(&self.path)?, settings)
+    }
+
+    fn list_chats(&self) -> Result<Vec<(String, String)>, StorageError> {
+        list_chats(&open(&self.path)?)
+    }
+
+    fn create_chat(&self, id: &str, title: &str) -> Result<(), StorageError> {
+        create_chat(&open(&self.path)?, id, title, None)
+    }
+
+    fn rename_chat(&self, id: &str, title: &str) -> Result<String, StorageError> {
+        rename_chat(&open(&self.path)?, id, title)
+    }
+
+    fn delete_chat(&self, id: &str) -> Result<(), StorageError> {
+        delete_chat(&open(&self.path)?, id)
+    }
+
+    fn load_messages(&self, chat_id: &str) -> Result<Vec<ChatMessage>, StorageError> {
+        load_chat_messages(&open(&self.path)?, chat_id)
+    }
+
+    fn insert_message(
+        &self,
+        chat_id: &str,
+        msg: &ChatMessage,
+        meta: Option<&GenerationMeta>,
+    ) -> Result<(), StorageError> {
+        let conn = open(&self.path)?;
+        insert_message(&conn, chat_id, msg, meta)?;
+        enforce_history_limit(&conn, chat_id);
+        Ok(())
+    }
+}
+
+// the error texts versions before `chat_errors` stored as assistant replies
+const LEGACY_ERROR_MATCH: &str = "(content = 'Error: No model selected. Please open Settings and choose a model before sending messages.'
+     OR content = 'Error: Failed to parse response from Ollama'
+     OR content LIKE 'Error: Ollama API returned status %'
+     OR content = 'Error: Could not connect to Ollama. Make sure Ollama is running at http://localhost:11434')";
+
+// create missing tables and columns; safe to run on every open
+pub fn migrate(conn: &Connection) -> Result<(), StorageError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chats (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            output_format TEXT,
+            use_kb INTEGER DEFAULT 0,
+            persona_id INTEGER,
+            unread INTEGER DEFAULT 0,
+            client TEXT,
+            proxy_fingerprint TEXT,
+            folder_id INTEGER,
+            created_at DATETIME,
+            updated_at DATETIME,
+            pinned INTEGER DEFAULT 0,
+            archived INTEGER DEFAULT 0,
+            deleted_at DATETIME
+        )",
+        [],
+    )?;
+
+    // structured output per chat: NULL = free text, 'json' = any JSON, otherwise a JSON Schema
+    ensure_column(conn, "chats", "output_format", "TEXT")?;
+    ensure_column(conn, "chats", "use_kb", "INTEGER DEFAULT 0")?;
+    // persona answering in the chat (NULL = plain settings)
+    ensure_column(conn, "chats", "persona_id", "INTEGER")?;
+    // set when a reply arrives while the chat isn't open
+    ensure_column(conn, "chats", "unread", "INTEGER DEFAULT 0")?;
+    // chats recorded by the OpenAI-compatible proxy: the calling client and a hash of the
+    // conversation so far, which the client's next request is matched against
+    ensure_column(conn, "chats", "client", "TEXT")?;
+    ensure_column(conn, "chats", "proxy_fingerprint", "TEXT")?;
+    // folder the chat is filed in (NULL = none)
+    ensure_column(conn, "chats", "folder_id", "INTEGER")?;
+    // when the chat was created and last got a message (UTC); pinned chats head the list and
+    // archived ones are only listed on request
+    ensure_column(conn, "chats", "created_at", "DATETIME")?;
+    ensure_column(conn, "chats", "updated_at", "DATETIME")?;
+    ensure_column(conn, "chats", "pinned", "INTEGER DEFAULT 0")?;
+    ensure_column(conn, "chats", "archived", "INTEGER DEFAULT 0")?;
+    // set while the chat is in the Trash (UTC); NULL = not deleted
+    ensure_column(conn, "chats", "deleted_at", "DATETIME")?;
+
+    // user-created folders in the sidebar, in `position` order
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS folders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            position INTEGER NOT NULL DEFAULT 0,
+            collapsed INTEGER DEFAULT 0
+        )",
+        [],
+    )?;
+
+    // free-form tags; a chat can have any number of them
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chat_tags (
+            chat_id TEXT NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY (chat_id, tag)
+        )",
+        [],
+    )?;
+
+    // named assistant profiles; NULL options fall back to the global settings
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS personas (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            system_prompt TEXT,
+            model TEXT,
+            temperature REAL,
+            top_p REAL,
+            max_tokens INTEGER,
+            color TEXT,
+            avatar TEXT
+        )",
+        [],
+    )?;
+
+    // knowledge base: indexed folders and their embedded chunks
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS kb_sources (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            path TEXT NOT NULL UNIQUE,
+            embed_model TEXT NOT NULL,
+            indexed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS kb_chunks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source_id INTEGER NOT NULL,
+            file_path TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            embedding BLOB NOT NULL
+        )",
+        [],
+    )?;
+
+    // JSON Schemas saved for reuse in structured output mode
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS json_schemas (
+            name TEXT PRIMARY KEY,
+            schema TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chat_id TEXT NOT NULL,
+            role TEXT NOT NULL,
+            content TEXT NOT NULL,
+            timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+            model TEXT,
+            endpoint TEXT,
+            system_prompt_hash TEXT,
+            options TEXT,
+            tool_calls TEXT,
+            tool_name TEXT,
+            sources TEXT,
+            persona TEXT
+        )",
+        [],
+    )?;
+
+    // databases created before generation metadata was recorded lack these columns
+    ensure_column(conn, "messages", "model", "TEXT")?;
+    ensure_column(conn, "messages", "endpoint", "TEXT")?;
+    ensure_column(conn, "messages", "system_prompt_hash", "TEXT")?;
+    ensure_column(conn, "messages", "options", "TEXT")?;
+    // tool calling: JSON tool_calls on assistant rows, tool_name on 'tool' result rows
+    ensure_column(conn, "messages", "tool_calls", "TEXT")?;
+    ensure_column(conn, "messages", "tool_name", "TEXT")?;
+    // knowledge base chunks a reply was grounded on (JSON list)
+    ensure_column(conn, "messages", "sources", "TEXT")?;
+    // name of the persona that wrote an assistant reply
+    ensure_column(conn, "messages", "persona", "TEXT")?;
+
+    // chats from before they were dated take the times of their first and last message
+    conn.execute(
+        "UPDATE chats SET created_at = COALESCE(
+             (SELECT MIN(timestamp) FROM messages WHERE chat_id = chats.id), CURRENT_TIMESTAMP)
+         WHERE created_at IS NULL",
+        [],
+    )?;
+    conn.execute(
+        "UPDATE chats SET updated_at = COALESCE(
+             (SELECT MAX(timestamp) FROM messages WHERE chat_id = chats.id), created_at)
+         WHERE updated_at IS NULL",
+        [],
+    )?;
+
+    // semantic search: one embedding per user/assistant message, filled in the background
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS message_embeddings (
+            message_id INTEGER PRIMARY KEY,
+            embed_model TEXT NOT NULL,
+            embedding BLOB NOT NULL
+        )",
+        [],
+    )?;
+
+    // model comparisons: one prompt sent to several models, one reply row per column
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS comparisons (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chat_id TEXT NOT NULL,
+            prompt TEXT NOT NULL,
+            chosen INTEGER,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS comparison_replies (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            comparison_id INTEGER NOT NULL,
+            column_index INTEGER NOT NULL,
+            model TEXT NOT NULL,
+            options TEXT,
+            content TEXT NOT NULL,
+            error TEXT,
+            first_token_ms INTEGER,
+            total_ms INTEGER,
+            eval_count INTEGER,
+            tokens_per_sec REAL
+        )",
+        [],
+    )?;
+
+    // failed requests, kept apart from `messages` so they're never sent to the model as history
+    let had_error_store = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'chat_errors'",
+            [],
+            |r| r.get::<_, i64>(0),
+        )?
+        > 0;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chat_errors (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chat_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            message TEXT NOT NULL,
+            user_message TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+    if !had_error_store {
+        // older versions stored errors as assistant messages; move them over once. Only the
+        // exact texts they wrote match: replies from that time have no model either, and a
+        // real answer may well start with "Error:"
+        let legacy = format!("role = 'assistant' AND model IS NULL AND {}", LEGACY_ERROR_MATCH);
+        conn.execute_batch(&format!(
+            "BEGIN;
+             INSERT INTO chat_errors (chat_id, kind, message, created_at)
+                 SELECT chat_id, 'legacy', content, timestamp FROM messages WHERE {legacy};
+             DELETE FROM messages WHERE {legacy};
+             COMMIT;",
+            legacy = legacy
+        ))?;
+    }
+
+    // follow-up messages typed while a chat was busy, sent in order once it is free
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS message_queue (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chat_id TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    // prompt library; the name is also the slash command that inserts the template
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS prompt_templates (
+            name TEXT PRIMARY KEY,
+            description TEXT,
+            body TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // approval policy per user-defined tool: 'allow', 'ask' or 'deny'
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tool_policies (
+            name TEXT PRIMARY KEY,
+            policy TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // audit log of every tool invocation, including denied ones
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tool_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chat_id TEXT NOT NULL,
+            tool_name TEXT NOT NULL,
+            arguments TEXT,
+            command TEXT,
+            decision TEXT NOT NULL,
+            exit_code INTEGER,
+            output TEXT,
+            duration_ms INTEGER,
+            timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            model TEXT NOT NULL,
+            system_prompt TEXT,
+            temperature REAL,
+            top_p REAL,
+            max_tokens INTEGER,
+            zoom INTEGER,
+            maximized INTEGER,
+            window_width INTEGER,
+            window_height INTEGER,
+            tools_enabled INTEGER DEFAULT 0,
+            tool_folder TEXT,
+            embed_model TEXT,
+            kb_top_k INTEGER,
+            embed_messages INTEGER DEFAULT 0,
+            max_generations INTEGER DEFAULT 0,
+            notify_background INTEGER DEFAULT 0,
+            api_enabled INTEGER DEFAULT 0,
+            api_port INTEGER,
+            api_token TEXT,
+            trash_days INTEGER DEFAULT 30,
+            backup_hours INTEGER DEFAULT 24,
+            backup_keep INTEGER DEFAULT 10
+        )",
+        [],
+    )?;
+
+    ensure_column(conn, "settings", "tools_enabled", "INTEGER DEFAULT 0")?;
+    ensure_column(conn, "settings", "tool_folder", "TEXT")?;
+    ensure_column(conn, "settings", "embed_model", "TEXT")?;
+    ensure_column(conn, "settings", "kb_top_k", "INTEGER")?;
+    ensure_column(conn, "settings", "embed_messages", "INTEGER DEFAULT 0")?;
+    ensure_column(conn, "settings", "max_generations", "INTEGER DEFAULT 0")?;
+    ensure_column(conn, "settings", "notify_background", "INTEGER DEFAULT 0")?;
+    ensure_column(conn, "settings", "api_enabled", "INTEGER DEFAULT 0")?;
+    ensure_column(conn, "settings", "api_port", "INTEGER")?;
+    ensure_column(conn, "settings", "api_token", "TEXT")?;
+    ensure_column(conn, "settings", "trash_days", "INTEGER DEFAULT 30")?;
+    ensure_column(conn, "settings", "backup_hours", "INTEGER DEFAULT 24")?;
+    ensure_column(conn, "settings", "backup_keep", "INTEGER DEFAULT 10")?;
+
+    let exists: bool = conn
+        .prepare("SELECT EXISTS(SELECT 1 FROM settings WHERE id = 1)")?
+        .query_row([], |r| r.get(0))?;
+
+    if !exists {
+        conn.execute(
+            "INSERT INTO settings (id, model, system_prompt, temperature, top_p, max_tokens, zoom, maximized, window_width, window_height)
+             VALUES (1, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                "", // no default model — user must pick one
+                "",
+                0.7_f64,
+                0.95_f64,
+                512_i32,
+                100_i32, // zoom %
+                1_i32,   // maximized true by default (kept in DB, but user cannot change)
+                1024_i32,
+                768_i32
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+// add a column to an existing table when an older chat.db doesn't have it yet
+pub fn ensure_column(conn: &Connection, table: &str, column: &str, decl: &str) -> rusqlite::Result<()> {
+    let exists = conn
+        .prepare(&format!(
+            "SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1",
+            table
+        ))?
+        .exists(params![column])?;
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn load_settings(conn: &Connection) -> Result<Settings, StorageError> {
+    let settings = conn.query_row(
+        "SELECT model, system_prompt, temperature, top_p, max_tokens, zoom, maximized, window_width, window_height, tools_enabled, tool_folder, embed_model, kb_top_k, embed_messages, max_generations, notify_background, api_enabled, api_port, api_token, trash_days, backup_hours, backup_keep FROM settings WHERE id = 1",
+        [],
+        |row: &Row| {
+            Ok(Settings {
+                model: row.get::<_, String>(0)?,
+                system_prompt: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                temperature: row.get::<_, Option<f64>>(2)?.unwrap_or(0.7),
+                top_p: row.get::<_, Option<f64>>(3)?.unwrap_or(0.95),
+                max_tokens: clamp_to_i32(row.get::<_, Option<i64>>(4)?.unwrap_or(512)),
+                zoom: clamp_to_i32(row.get::<_, Option<i64>>(5)?.unwrap_or(100)),
+                // always treat maximized as true on start (we still read DB value for compatibility)
+                maximized: true,
+                window_width: clamp_to_i32(row.get::<_, Option<i64>>(7)?.unwrap_or(1024)),
+                window_height: clamp_to_i32(row.get::<_, Option<i64>>(8)?.unwrap_or(768)),
+                tools_enabled: row.get::<_, Option<i64>>(9)?.unwrap_or(0) != 0,
+                tool_folder: row.get::<_, Option<String>>(10)?.unwrap_or_default(),
+                embed_model: row.get::<_, Option<String>>(11)?.unwrap_or_default(),
+                kb_top_k: clamp_to_i32(row.get::<_, Option<i64>>(12)?.unwrap_or(4)),
+                embed_messages: row.get::<_, Option<i64>>(13)?.unwrap_or(0) != 0,
+                max_generations: clamp_to_i32(row.get::<_, Option<i64>>(14)?.unwrap_or(0)),
+                notify_background: row.get::<_, Option<i64>>(15)?.unwrap_or(0) != 0,
+                api_enabled: row.get::<_, Option<i64>>(16)?.unwrap_or(0) != 0,
+                api_port: clamp_to_i32(row.get::<_, Option<i64>>(17)?.unwrap_or(DEFAULT_API_PORT.into())),
+                api_token: row.get::<_, Option<String>>(18)?.unwrap_or_default(),
+                trash_days: clamp_to_i32(row.get::<_, Option<i64>>(19)?.unwrap_or(30)),
+                backup_hours: clamp_to_i32(row.get::<_, Option<i64>>(20)?.unwrap_or(24)),
+                backup_keep: clamp_to_i32(row.get::<_, Option<i64>>(21)?.unwrap_or(10)),
+            })
+        },
+    )?;
+    Ok(settings)
+}
+
+pub fn save_settings(conn: &Connection, s: &Settings) -> Result<(), StorageError> {
+    // ensure fields are within i32 bounds
+    let max_tokens: i64 = s.max_tokens.into();
+    let zoom: i64 = s.zoom.into();
+    let width: i64 = s.window_width.into();
+    let height: i64 = s.window_height.into();
+
+    conn.execute(
+        "UPDATE settings SET model = ?1, system_prompt = ?2, temperature = ?3, top_p = ?4, max_tokens = ?5, zoom = ?6, maximized = ?7, window_width = ?8, window_height = ?9, tools_enabled = ?10, tool_folder = ?11, embed_model = ?12, kb_top_k = ?13, embed_messages = ?14, max_generations = ?15, notify_background = ?16, api_enabled = ?17, api_port = ?18, api_token = ?19, trash_days = ?20, backup_hours = ?21, backup_keep = ?22 WHERE id = 1",
+        params![
+            s.model,
+            s.system_prompt,
+            s.temperature,
+            s.top_p,
+            clamp_to_i32(max_tokens),
+            clamp_to_i32(zoom),
+            if s.maximized { 1 } else { 0 },
+            clamp_to_i32(width),
+            clamp_to_i32(height),
+            if s.tools_enabled { 1 } else { 0 },
+            s.tool_folder,
+            s.embed_model,
+            s.kb_top_k,
+            if s.embed_messages { 1 } else { 0 },
+            s.max_generations,
+            if s.notify_background { 1 } else { 0 },
+            if s.api_enabled { 1 } else { 0 },
+            s.api_port,
+            s.api_token,
+            s.trash_days,
+            s.backup_hours,
+            s.backup_keep
+        ],
+    )?;
+    Ok(())
+}
+
+/* Helper to enforce history length in DB per chat - deletes oldest messages beyond MAX_HISTORY_MESSAGES */
+pub fn enforce_history_limit(conn: &Connection, chat_id: &str) {
+    // count messages first
+    let count: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM messages WHERE chat_id = ?1",
+            params![chat_id],
+            |r| r.get(0),
+        )
+        .unwrap_or(0);
+
+    if count <= MAX_HISTORY_MESSAGES {
+        return;
+    }
+
+    // get cutoff id (the newest message beyond the MAX_HISTORY_MESSAGES kept)
+    if let Ok(cutoff_id) = conn.query_row(
+        "SELECT id FROM messages WHERE chat_id = ?1 ORDER BY id DESC LIMIT 1 OFFSET ?2",
+        params![chat_id, MAX_HISTORY_MESSAGES],
+        |r| r.get::<_, i64>(0),
+    ) {
+        let _ = conn.execute(
+            "DELETE FROM messages WHERE chat_id = ?1 AND id <= ?2",
+            params![chat_id, cutoff_id],
+        );
+    }
+}
+
+// load only up to MAX_HISTORY_MESSAGES newest messages of a chat, in chronological order
+pub fn load_chat_messages(conn: &Connection, chat_id: &str) -> Result<Vec<ChatMessage>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT role, content, model, tool_calls, tool_name, sources, persona FROM messages
+         WHERE chat_id = ? ORDER BY id DESC LIMIT ?",
+    )?;
+
+    let rows = stmt.query_map(params![chat_id, MAX_HISTORY_MESSAGES], |row| {
+        Ok(ChatMessage {
+            role: row.get::<_, String>(0)?,
+            content: row.get::<_, String>(1)?,
+            model: row.get::<_, Option<String>>(2)?,
+            tool_calls: row.get::<_, Option<String>>(3)?,
+            tool_name: row.get::<_, Option<String>>(4)?,
+            sources: row.get::<_, Option<String>>(5)?,
+            persona: row.get::<_, Option<String>>(6)?,
+        })
+    })?;
+
+    let mut collected = rows.collect::<rusqlite::Result<Vec<ChatMessage>>>()?;
+    collected.reverse(); // chronological
+    Ok(collected)
+}
+
+// distinct models that have produced stored replies (for the history filter)
+pub fn list_used_models(conn: &Connection) -> Result<Vec<String>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT model FROM messages WHERE model IS NOT NULL AND model != '' ORDER BY model",
+    )?;
+    let rows = stmt.query_map([], |r| r.get::<_, String>(0))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+// ids of chats with replies the user hasn't opened yet
+pub fn list_unread_chats(conn: &Connection) -> HashSet<String> {
+    let mut stmt = match conn.prepare("SELECT id FROM chats WHERE unread = 1") {
+        Ok(stmt) => stmt,
+        Err(_) => return HashSet::new(),
+    };
+    stmt.query_map([], |r| r.get::<_, String>(0))
+        .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default()
+}
+
+pub fn mark_chat_unread(conn: &Connection, chat_id: &str, unread: bool) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE chats SET unread = ?1 WHERE id = ?2",
+        params![if unread { 1 } else { 0 }, chat_id],
+    )?;
+    Ok(())
+}
+
+// client name of every chat recorded by the proxy
+pub fn chat_clients(conn: &Connection) -> HashMap<String, String> {
+    let mut stmt = match conn.prepare("SELECT id, client FROM chats WHERE client IS NOT NULL") {
+        Ok(stmt) => stmt,
+        Err(_) => return HashMap::new(),
+    };
+    stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))
+        .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default()
+}
+
+// the chat of `client` whose conversation so far hashes to `fingerprint`
+pub fn find_proxy_chat(conn: &Connection, client: &str, fingerprint: &str) -> Result<Option<String>, StorageError> {
+    let mut stmt = conn.prepare("SELECT id FROM chats WHERE client = ?1 AND proxy_fingerprint = ?2 AND deleted_at IS NULL LIMIT 1")?;
+    let mut rows = stmt.query(params![client, fingerprint])?;
+    Ok(match rows.next()? {
+        Some(row) => Some(row.get(0)?),
+        None => None,
+    })
+}
+
+pub fn set_proxy_chat(conn: &Connection, chat_id: &str, client: &str, fingerprint: &str) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE chats SET client = ?1, proxy_fingerprint = ?2 WHERE id = ?3",
+        params![client, fingerprint, chat_id],
+    )?;
+    Ok(())
+}
+
+// ids of chats containing at least one reply written by `model`
+pub fn chats_with_model(conn: &Connection, model: &str) -> HashSet<String> {
+    let mut stmt = match conn.prepare("SELECT DISTINCT chat_id FROM messages WHERE model = ?1") {
+        Ok(stmt) => stmt,
+        Err(_) => return HashSet::new(),
+    };
+    stmt.query_map(params![model], |r| r.get::<_, String>(0))
+        .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default()
+}
+
+/* ================= CHATS ================= */
+
+// all chats outside the Trash as (id, title): pinned ones first, then by last activity, newest first
+pub fn list_chats(conn: &Connection) -> Result<Vec<(String, String)>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title FROM chats WHERE deleted_at IS NULL ORDER BY pinned DESC, updated_at DESC, rowid DESC",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+pub fn create_chat(
+    conn: &Connection,
+    id: &str,
+    title: &str,
+    persona_id: Option<i64>,
+) -> Result<(), StorageError> {
+    conn.execute(
+        "INSERT INTO chats (id, title, persona_id, created_at, updated_at)
+         VALUES (?1, ?2, ?3, strftime('%Y-%m-%d %H:%M:%f', 'now'), strftime('%Y-%m-%d %H:%M:%f', 'now'))",
+        params![id, title, persona_id],
+    )?;
+    Ok(())
+}
+
+// true for chats outside the Trash
+pub fn chat_exists(conn: &Connection, id: &str) -> Result<bool, StorageError> {
+    Ok(conn
+        .prepare("SELECT 1 FROM chats WHERE id = ?1 AND deleted_at IS NULL")?
+        .exists(params![id])?)
+}
+
+// rename a chat; titles longer than MAX_TITLE_LEN are cut. Returns the stored title
+pub fn rename_chat(conn: &Connection, id: &str, title: &str) -> Result<String, StorageError> {
+    let title = clamp_title(title);
+    conn.execute(
+        "UPDATE chats SET title = ?1 WHERE id = ?2",
+        params![title, id],
+    )?;
+    Ok(title)
+}
+
+// remove a chat with everything stored for it, for good (see `trash_chats` for the undoable delete)
+pub fn delete_chat(conn: &Connection, id: &str) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    delete_chat_rows(&tx, id)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn delete_chat_rows(conn: &Connection, id: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM messages WHERE chat_id = ?1", params![id])?;
+    conn.execute("DELETE FROM message_queue WHERE chat_id = ?1", params![id])?;
+    conn.execute("DELETE FROM chat_errors WHERE chat_id = ?1", params![id])?;
+    conn.execute("DELETE FROM chat_tags WHERE chat_id = ?1", params![id])?;
+    conn.execute(
+        "DELETE FROM comparison_replies WHERE comparison_id IN (SELECT id FROM comparisons WHERE chat_id = ?1)",
+        params![id],
+    )?;
+    conn.execute("DELETE FROM comparisons WHERE chat_id = ?1", params![id])?;
+    conn.execute("DELETE FROM chats WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+pub fn delete_all_chats(conn: &Connection) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM messages", [])?;
+    tx.execute("DELETE FROM chat_errors", [])?;
+    tx.execute("DELETE FROM message_queue", [])?;
+    tx.execute("DELETE FROM chat_tags", [])?;
+    tx.execute("DELETE FROM comparison_replies", [])?;
+    tx.execute("DELETE FROM comparisons", [])?;
+    tx.execute("DELETE FROM chats", [])?;
+    tx.commit()?;
+    Ok(())
+}
+
+/* ================= TRASH ================= */
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrashedChat {
+    pub id: String,
+    pub title: String,
+    // local time, "YYYY-MM-DD HH:MM"
+    pub deleted_at: String,
+}
+
+// move chats to the Trash; they keep everything and can be restored until purged
+pub fn trash_chats(conn: &Connection, ids: &[String]) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    for id in ids {
+        tx.execute(
+            "UPDATE chats SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?1 AND deleted_at IS NULL",
+            params![id],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn restore_chats(conn: &Connection, ids: &[String]) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    for id in ids {
+        tx.execute("UPDATE chats SET deleted_at = NULL WHERE id = ?1", params![id])?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+// chats in the Trash, most recently deleted first
+pub fn list_trash(conn: &Connection) -> Result<Vec<TrashedChat>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, strftime('%Y-%m-%d %H:%M', deleted_at, 'localtime') FROM chats
+         WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC, rowid DESC",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(TrashedChat {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            deleted_at: row.get(2)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+// delete for good the chats that have been in the Trash for more than `days` days
+// (`days` = 0 empties it); returns how many were removed
+pub fn purge_trash(conn: &Connection, days: i32) -> Result<usize, StorageError> {
+    let ids: Vec<String> = conn
+        .prepare("SELECT id FROM chats WHERE deleted_at IS NOT NULL AND deleted_at <= datetime('now', ?1)")?
+        .query_map(params![format!("-{} days", days.max(0))], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    for id in &ids {
+        delete_chat(conn, id)?;
+    }
+    Ok(ids.len())
+}
+
+/* ================= ACTIVITY, PINS AND ARCHIVE ================= */
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatActivity {
+    // local time, "YYYY-MM-DD HH:MM"
+    pub created_at: String,
+    pub updated_at: String,
+    // calendar days (local time) since the last activity: 0 = today, 1 = yesterday, ...
+    pub days_ago: i64,
+    pub pinned: bool,
+    pub archived: bool,
+}
+
+// dates, pin and archive state of every chat
+pub fn chat_activity(conn: &Connection) -> Result<HashMap<String, ChatActivity>, StorageError> {
+    let activity = conn.prepare(
+        "SELECT id,
+                strftime('%Y-%m-%d %H:%M', created_at, 'localtime'),
+                strftime('%Y-%m-%d %H:%M', updated_at, 'localtime'),
+                CAST(julianday(date('now', 'localtime')) - julianday(date(updated_at, 'localtime')) AS INTEGER),
+                pinned, archived
+         FROM chats",
+    )
+    .and_then(|mut stmt| {
+        stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                ChatActivity {
+                    created_at: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                    updated_at: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    days_ago: row.get::<_, Option<i64>>(3)?.unwrap_or(0),
+                    pinned: row.get::<_, Option<i64>>(4)?.unwrap_or(0) != 0,
+                    archived: row.get::<_, Option<i64>>(5)?.unwrap_or(0) != 0,
+                },
+            ))
+        })?
+        .collect::<rusqlite::Result<_>>()
+    })?;
+    Ok(activity)
+}
+
+pub fn set_chat_pinned(conn: &Connection, id: &str, pinned: bool) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE chats SET pinned = ?1 WHERE id = ?2",
+        params![pinned as i32, id],
+    )?;
+    Ok(())
+}
+
+// archived chats keep everything but leave the main list
+pub fn set_chat_archived(conn: &Connection, id: &str, archived: bool) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE chats SET archived = ?1 WHERE id = ?2",
+        params![archived as i32, id],
+    )?;
+    Ok(())
+}
+
+/* ================= FOLDERS AND TAGS ================= */
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Folder {
+    pub id: i64,
+    pub name: String,
+    pub collapsed: bool,
+}
+
+// folders in the order the user arranged them
+pub fn list_folders(conn: &Connection) -> Result<Vec<Folder>, StorageError> {
+    let folders = conn
+        .prepare("SELECT id, name, collapsed FROM folders ORDER BY position, id")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| {
+                Ok(Folder {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    collapsed: row.get::<_, Option<i64>>(2)?.unwrap_or(0) != 0,
+                })
+            })?
+            .collect()
+        })?;
+    Ok(folders)
+}
+
+// new folder at the end of the list; returns its id
+pub fn create_folder(conn: &Connection, name: &str) -> Result<i64, StorageError> {
+    conn.execute(
+        "INSERT INTO folders (name, position) VALUES (?1, (SELECT COALESCE(MAX(position), -1) + 1 FROM folders))",
+        params![clamp_title(name.trim())],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn rename_folder(conn: &Connection, id: i64, name: &str) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE folders SET name = ?1 WHERE id = ?2",
+        params![clamp_title(name.trim()), id],
+    )?;
+    Ok(())
+}
+
+// remove a folder; its chats stay, unfiled
+pub fn delete_folder(conn: &Connection, id: i64) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("UPDATE chats SET folder_id = NULL WHERE folder_id = ?1", params![id])?;
+    tx.execute("DELETE FROM folders WHERE id = ?1", params![id])?;
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn set_folder_collapsed(conn: &Connection, id: i64, collapsed: bool) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE folders SET collapsed = ?1 WHERE id = ?2",
+        params![collapsed as i32, id],
+    )?;
+    Ok(())
+}
+
+// move folder `id` right before `before` (None = to the end)
+pub fn move_folder(conn: &Connection, id: i64, before: Option<i64>) -> Result<(), StorageError> {
+    let mut order: Vec<i64> = list_folders(conn)?.into_iter().map(|f| f.id).filter(|f| *f != id).collect();
+    let at = before
+        .and_then(|b| order.iter().position(|f| *f == b))
+        .unwrap_or(order.len());
+    order.insert(at, id);
+    let tx = conn.unchecked_transaction()?;
+    for (position, folder) in order.iter().enumerate() {
+        tx.execute(
+            "UPDATE folders SET position = ?1 WHERE id = ?2",
+            params![position as i64, folder],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+// file chats in a folder (None = take them out of any folder)
+pub fn set_chats_folder(conn: &Connection, chat_ids: &[String], folder: Option<i64>) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    for id in chat_ids {
+        tx.execute("UPDATE chats SET folder_id = ?1 WHERE id = ?2", params![folder, id])?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+// folder of every filed chat
+pub fn chat_folders(conn: &Connection) -> HashMap<String, i64> {
+    conn.prepare("SELECT id, folder_id FROM chats WHERE folder_id IS NOT NULL")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// tags are trimmed and compared without regard to case; the first spelling used is kept
+fn normalize_tag(tag: &str) -> String {
+    tag.trim().trim_start_matches('#').trim().to_string()
+}
+
+pub fn add_tag(conn: &Connection, chat_ids: &[String], tag: &str) -> Result<(), StorageError> {
+    let tag = normalize_tag(tag);
+    if tag.is_empty() {
+        return Ok(());
+    }
+    // reuse the spelling of an existing tag so "Work" and "work" stay one tag
+    let tag: String = conn
+        .query_row(
+            "SELECT tag FROM chat_tags WHERE tag = ?1 COLLATE NOCASE LIMIT 1",
+            params![tag],
+            |row| row.get(0),
+        )
+        .unwrap_or(tag);
+    let tx = conn.unchecked_transaction()?;
+    for id in chat_ids {
+        tx.execute(
+            "INSERT OR IGNORE INTO chat_tags (chat_id, tag) VALUES (?1, ?2)",
+            params![id, tag],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn remove_tag(conn: &Connection, chat_ids: &[String], tag: &str) -> Result<(), StorageError> {
+    let tag = normalize_tag(tag);
+    let tx = conn.unchecked_transaction()?;
+    for id in chat_ids {
+        tx.execute(
+            "DELETE FROM chat_tags WHERE chat_id = ?1 AND tag = ?2 COLLATE NOCASE",
+            params![id, tag],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+// tags of every tagged chat, alphabetically
+pub fn chat_tags(conn: &Connection) -> Result<HashMap<String, Vec<String>>, StorageError> {
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    let rows: Vec<(String, String)> = conn
+        .prepare("SELECT chat_id, tag FROM chat_tags ORDER BY tag COLLATE NOCASE")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        })?;
+    for (chat_id, tag) in rows {
+        tags.entry(chat_id).or_default().push(tag);
+    }
+    Ok(tags)
+}
+
+// every tag in use, alphabetically
+pub fn list_tags(conn: &Connection) -> Vec<String> {
+    conn.prepare("SELECT DISTINCT tag FROM chat_tags ORDER BY tag COLLATE NOCASE")
+        .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+        .unwrap_or_default()
+}
+
+/* ================= MESSAGES ================= */
+
+// store a message of a chat (with how it was generated, for replies); returns its id
+pub fn insert_message(
+    conn: &Connection,
+    chat_id: &str,
+    msg: &ChatMessage,
+    meta: Option<&GenerationMeta>,
+) -> Result<i64, StorageError> {
+    conn.execute(
+        "INSERT INTO messages (chat_id, role, content, model, endpoint, system_prompt_hash, options, tool_calls, tool_name, sources, persona)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            chat_id,
+            msg.role,
+            msg.content,
+            msg.model,
+            meta.map(|m| m.endpoint.clone()),
+            meta.and_then(|m| m.system_prompt_hash.clone()),
+            meta.and_then(|m| m.options.clone()),
+            msg.tool_calls,
+            msg.tool_name,
+            msg.sources,
+            msg.persona
+        ],
+    )?;
+    let id = conn.last_insert_rowid();
+    conn.execute(
+        "UPDATE chats SET updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now') WHERE id = ?1",
+        params![chat_id],
+    )?;
+    Ok(id)
+}
+
+// the newest reply of a chat with the chat's title, for notifications
+pub fn latest_reply(conn: &Connection, chat_id: &str) -> Result<(String, String), StorageError> {
+    Ok(conn.query_row(
+        "SELECT c.title, COALESCE((SELECT content FROM messages m
+                WHERE m.chat_id = c.id AND m.role = 'assistant' ORDER BY m.id DESC LIMIT 1), '')
+         FROM chats c WHERE c.id = ?1",
+        params![chat_id],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?)
+}
+
+/* ================= STRUCTURED OUTPUT ================= */
+
+// the chat's output format: "json" or a JSON schema; None for free text
+// a chat that doesn't exist has no format; only a failed read is an error
+pub fn load_chat_format(conn: &Connection, chat_id: &str) -> Result<Option<String>, StorageError> {
+    let mut stmt = conn.prepare("SELECT output_format FROM chats WHERE id = ?1")?;
+    let mut rows = stmt.query(params![chat_id])?;
+    let format = match rows.next()? {
+        Some(row) => row.get::<_, Option<String>>(0)?,
+        None => None,
+    };
+    Ok(format.filter(|f| !f.trim().is_empty()))
+}
+
+pub fn save_chat_format(conn: &Connection, chat_id: &str, format: Option<&str>) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE chats SET output_format = ?1 WHERE id = ?2",
+        params![format, chat_id],
+    )?;
+    Ok(())
+}
+
+// (name, schema) of the schemas saved for reuse
+pub fn list_saved_schemas(conn: &Connection) -> Vec<(String, String)> {
+    let mut stmt = match conn.prepare("SELECT name, schema FROM json_schemas ORDER BY name") {
+        Ok(stmt) => stmt,
+        Err(_) => return vec![],
+    };
+    stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))
+        .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default()
+}
+
+pub fn save_schema(conn: &Connection, name: &str, schema: &str) -> Result<(), StorageError> {
+    conn.execute(
+        "INSERT INTO json_schemas (name, schema) VALUES (?1, ?2)
+         ON CONFLICT(name) DO UPDATE SET schema = excluded.schema",
+        params![name, schema],
+    )?;
+    Ok(())
+}
+
+pub fn delete_schema(conn: &Connection, name: &str) -> Result<(), StorageError> {
+    conn.execute("DELETE FROM json_schemas WHERE name = ?1", params![name])?;
+    Ok(())
+}
+
+/* ================= KNOWLEDGE BASE ================= */
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KbSource {
+    pub id: i64,
+    pub path: String,
+    pub embed_model: String,
+    pub chunks: i64,
+    pub indexed_at: String,
+}
+
+pub fn list_kb_sources(conn: &Connection) -> Vec<KbSource> {
+    let mut stmt = match conn.prepare(
+        "SELECT s.id, s.path, s.embed_model, s.indexed_at,
+                (SELECT COUNT(*) FROM kb_chunks c WHERE c.source_id = s.id)
+         FROM kb_sources s ORDER BY s.path",
+    ) {
+        Ok(stmt) => stmt,
+        Err(_) => return vec![],
+    };
+    stmt.query_map([], |r| {
+        Ok(KbSource {
+            id: r.get(0)?,
+            path: r.get(1)?,
+            embed_model: r.get(2)?,
+            indexed_at: r.get::<_, Option<String>>(3)?.unwrap_or_default(),
+            chunks: r.get(4)?,
+        })
+    })
+    .map(|rows| rows.filter_map(|r| r.ok()).collect())
+    .unwrap_or_default()
+}
+
+// replace what is stored for the folder at `path` with `chunks`, embedded by `model`
+pub fn replace_kb_source(conn: &Connection, path: &str, model: &str, chunks: &[KbChunk]) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO kb_sources (path, embed_model) VALUES (?1, ?2)
+         ON CONFLICT(path) DO UPDATE SET embed_model = excluded.embed_model, indexed_at = CURRENT_TIMESTAMP",
+        params![path, model],
+    )?;
+    let source_id: i64 = tx.query_row("SELECT id FROM kb_sources WHERE path = ?1", params![path], |r| r.get(0))?;
+    tx.execute("DELETE FROM kb_chunks WHERE source_id = ?1", params![source_id])?;
+    for chunk in chunks {
+        tx.execute(
+            "INSERT INTO kb_chunks (source_id, file_path, chunk_index, content, embedding) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                source_id,
+                chunk.file_path,
+                chunk.chunk_index,
+                chunk.content,
+                embedding_to_blob(&chunk.embedding)
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn remove_kb_source(conn: &Connection, id: i64) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM kb_chunks WHERE source_id = ?1", params![id])?;
+    tx.execute("DELETE FROM kb_sources WHERE id = ?1", params![id])?;
+    tx.commit()?;
+    Ok(())
+}
+
+// every chunk of the folders indexed with `model`
+pub fn kb_chunks(conn: &Connection, model: &str) -> Result<Vec<KbChunk>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT c.file_path, c.chunk_index, c.content, c.embedding
+         FROM kb_chunks c JOIN kb_sources s ON s.id = c.source_id
+         WHERE s.embed_model = ?1",
+    )?;
+    let rows = stmt.query_map(params![model], |r| {
+        Ok(KbChunk {
+            file_path: r.get(0)?,
+            chunk_index: r.get(1)?,
+            content: r.get(2)?,
+            embedding: blob_to_embedding(&r.get::<_, Vec<u8>>(3)?),
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+pub fn chat_uses_kb(conn: &Connection, chat_id: &str) -> bool {
+    conn.query_row(
+        "SELECT use_kb FROM chats WHERE id = ?1",
+        params![chat_id],
+        |r| r.get::<_, Option<i64>>(0),
+    )
+    .ok()
+    .flatten()
+    .unwrap_or(0)
+        != 0
+}
+
+pub fn set_chat_uses_kb(conn: &Connection, chat_id: &str, enabled: bool) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE chats SET use_kb = ?1 WHERE id = ?2",
+        params![if enabled { 1 } else { 0 }, chat_id],
+    )?;
+    Ok(())
+}
+
+/* ================= MESSAGE EMBEDDINGS ================= */
+
+// an embedded message of a chat that isn't in the Trash
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmbeddedMessage {
+    pub chat_id: String,
+    pub chat_title: String,
+    pub role: String,
+    pub content: String,
+    pub embedding: Vec<f32>,
+}
+
+// user/assistant messages that have no embedding from `model` yet (oldest first)
+pub fn pending_message_embeddings(conn: &Connection, model: &str, limit: usize) -> Result<Vec<(i64, String)>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.content FROM messages m
+         LEFT JOIN message_embeddings e ON e.message_id = m.id AND e.embed_model = ?1
+         WHERE e.message_id IS NULL
+           AND m.role IN ('user', 'assistant')
+           AND trim(m.content) != ''
+         ORDER BY m.id LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![model, limit as i64], |r| Ok((r.get(0)?, r.get(1)?)))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+// (embedded, embeddable) message counts for `model`
+pub fn message_embedding_progress(conn: &Connection, model: &str) -> (i64, i64) {
+    conn.query_row(
+        "SELECT COUNT(e.message_id), COUNT(*) FROM messages m
+         LEFT JOIN message_embeddings e ON e.message_id = m.id AND e.embed_model = ?1
+         WHERE m.role IN ('user', 'assistant')
+           AND trim(m.content) != ''",
+        params![model],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )
+    .unwrap_or((0, 0))
+}
+
+// (message id, embedding) pairs computed by `model`
+pub fn store_message_embeddings(conn: &Connection, model: &str, embeddings: &[(i64, Vec<f32>)]) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    for (id, embedding) in embeddings {
+        tx.execute(
+            "INSERT OR REPLACE INTO message_embeddings (message_id, embed_model, embedding) VALUES (?1, ?2, ?3)",
+            params![id, model, embedding_to_blob(embedding)],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+// drop embeddings of deleted messages
+pub fn prune_message_embeddings(conn: &Connection) -> Result<(), StorageError> {
+    conn.execute(
+        "DELETE FROM message_embeddings WHERE message_id NOT IN (SELECT id FROM messages)",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn embedded_messages(conn: &Connection, model: &str) -> Result<Vec<EmbeddedMessage>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT m.chat_id, COALESCE(c.title, ''), m.role, m.content, e.embedding
+         FROM message_embeddings e
+         JOIN messages m ON m.id = e.message_id
+         LEFT JOIN chats c ON c.id = m.chat_id
+         WHERE e.embed_model = ?1 AND c.deleted_at IS NULL",
+    )?;
+    let rows = stmt.query_map(params![model], |r| {
+        Ok(EmbeddedMessage {
+            chat_id: r.get(0)?,
+            chat_title: r.get(1)?,
+            role: r.get(2)?,
+            content: r.get(3)?,
+            embedding: blob_to_embedding(&r.get::<_, Vec<u8>>(4)?),
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/* ================= PROMPT TEMPLATES ================= */
+
+pub fn list_prompt_templates(conn: &Connection) -> Result<Vec<PromptTemplate>, StorageError> {
+    let mut stmt = conn.prepare("SELECT name, description, body FROM prompt_templates ORDER BY name")?;
+    let rows = stmt.query_map([], |r| {
+        Ok(PromptTemplate {
+            name: r.get(0)?,
+            description: r.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            body: r.get(2)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+// a template with the same name is replaced; check it with `prompts::check_template` first
+pub fn save_prompt_template(conn: &Connection, template: &PromptTemplate) -> Result<(), StorageError> {
+    conn.execute(
+        "INSERT INTO prompt_templates (name, description, body) VALUES (?1, ?2, ?3)
+         ON CONFLICT(name) DO UPDATE SET description = excluded.description, body = excluded.body",
+        params![template.name, template.description, template.body],
+    )?;
+    Ok(())
+}
+
+// all or nothing, so a failed import doesn't leave a half-imported library
+pub fn save_prompt_templates(conn: &Connection, templates: &[PromptTemplate]) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    for template in templates {
+        save_prompt_template(&tx, template)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn delete_prompt_template(conn: &Connection, name: &str) -> Result<(), StorageError> {
+    conn.execute("DELETE FROM prompt_templates WHERE name = ?1", params![name])?;
+    Ok(())
+}
+
+/* ================= PERSONAS ================= */
+
+// accent used for personas that don't pick a color
+const DEFAULT_PERSONA_COLOR: &str = "#7c8cff";
+
+// a named assistant profile; empty/None fields fall back to the global settings
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Persona {
+    pub id: i64,
+    pub name: String,
+    pub system_prompt: String,
+    pub model: String,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub max_tokens: Option<i32>,
+    pub color: String,
+    pub avatar: String,
+}
+
+impl Persona {
+    // "🦀 Reviewer", or just the name without an avatar
+    pub fn label(&self) -> String {
+        if self.avatar.trim().is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} {}", self.avatar.trim(), self.name)
+        }
+    }
+
+    pub fn accent(&self) -> &str {
+        if self.color.is_empty() {
+            DEFAULT_PERSONA_COLOR
+        } else {
+            &self.color
+        }
+    }
+}
+
+// the settings a chat with this persona is sent with
+pub fn apply_persona(settings: &Settings, persona: &Persona) -> Settings {
+    let mut s = settings.clone();
+    if !persona.system_prompt.trim().is_empty() {
+        s.system_prompt = persona.system_prompt.clone();
+    }
+    if !persona.model.trim().is_empty() {
+        s.model = persona.model.clone();
+    }
+    if let Some(t) = persona.temperature {
+        s.temperature = t;
+    }
+    if let Some(p) = persona.top_p {
+        s.top_p = p;
+    }
+    if let Some(m) = persona.max_tokens {
+        s.max_tokens = m;
+    }
+    s
+}
+
+fn persona_from_row(r: &Row) -> rusqlite::Result<Persona> {
+    Ok(Persona {
+        id: r.get(0)?,
+        name: r.get(1)?,
+        system_prompt: r.get::<_, Option<String>>(2)?.unwrap_or_default(),
+        model: r.get::<_, Option<String>>(3)?.unwrap_or_default(),
+        temperature: r.get(4)?,
+        top_p: r.get(5)?,
+        max_tokens: r.get::<_, Option<i64>>(6)?.map(clamp_to_i32),
+        color: r.get::<_, Option<String>>(7)?.unwrap_or_default(),
+        avatar: r.get::<_, Option<String>>(8)?.unwrap_or_default(),
+    })
+}
+
+pub fn list_personas(conn: &Connection) -> Result<Vec<Persona>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, system_prompt, model, temperature, top_p, max_tokens, color, avatar
+         FROM personas ORDER BY name",
+    )?;
+    let rows = stmt.query_map([], persona_from_row)?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+// insert (id 0) or update a persona; returns its id
+pub fn save_persona(conn: &Connection, p: &Persona) -> Result<i64, StorageError> {
+    let name = p.name.trim();
+    let max_tokens = p.max_tokens.map(i64::from);
+    if p.id == 0 {
+        conn.execute(
+            "INSERT INTO personas (name, system_prompt, model, temperature, top_p, max_tokens, color, avatar)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![name, p.system_prompt, p.model, p.temperature, p.top_p, max_tokens, p.color, p.avatar],
+        )?;
+        Ok(conn.last_insert_rowid())
+    } else {
+        conn.execute(
+            "UPDATE personas SET name = ?1, system_prompt = ?2, model = ?3, temperature = ?4, top_p = ?5,
+             max_tokens = ?6, color = ?7, avatar = ?8 WHERE id = ?9",
+            params![name, p.system_prompt, p.model, p.temperature, p.top_p, max_tokens, p.color, p.avatar, p.id],
+        )?;
+        Ok(p.id)
+    }
+}
+
+// chats using the persona go back to the plain settings
+pub fn delete_persona(conn: &Connection, id: i64) -> Result<(), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("UPDATE chats SET persona_id = NULL WHERE persona_id = ?1", params![id])?;
+    tx.execute("DELETE FROM personas WHERE id = ?1", params![id])?;
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn chat_persona(conn: &Connection, chat_id: &str) -> Result<Option<Persona>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT p.id, p.name, p.system_prompt, p.model, p.temperature, p.top_p, p.max_tokens, p.color, p.avatar
+         FROM chats c JOIN personas p ON p.id = c.persona_id WHERE c.id = ?1",
+    )?;
+    let mut rows = stmt.query(params![chat_id])?;
+    Ok(match rows.next()? {
+        Some(row) => Some(persona_from_row(row)?),
+        None => None,
+    })
+}
+
+pub fn set_chat_persona(conn: &Connection, chat_id: &str, persona_id: Option<i64>) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE chats SET persona_id = ?1 WHERE id = ?2",
+        params![persona_id, chat_id],
+    )?;
+    Ok(())
+}
+
+/* ================= MODEL COMPARISON ================= */
+
+// a column's reply, streamed in live and stored in comparison_replies
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompareColumn {
+    pub reply_id: i64,
+    pub model: String,
+    pub options: String,
+    pub content: String,
+    pub done: bool,
+    pub error: Option<String>,
+    pub first_token_ms: Option<i64>,
+    pub total_ms: Option<i64>,
+    pub eval_count: Option<i64>,
+    pub tokens_per_sec: Option<f64>,
+}
+
+impl CompareColumn {
+    // "first token 320 ms · 4.1 s · 212 tokens · 51.7 tok/s"
+    pub fn metrics(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(ms) = self.first_token_ms {
+            parts.push(format!("first token {} ms", ms));
+        }
+        if let Some(ms) = self.total_ms {
+            parts.push(format!("{:.1} s", ms as f64 / 1000.0));
+        }
+        if let Some(n) = self.eval_count {
+            parts.push(format!("{} tokens", n));
+        }
+        if let Some(tps) = self.tokens_per_sec {
+            parts.push(format!("{:.1} tok/s", tps));
+        }
+        parts.join(" · ")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredComparison {
+    pub id: i64,
+    pub prompt: String,
+    // column continued with, if any
+    pub chosen: Option<i64>,
+    pub created_at: String,
+    pub columns: Vec<CompareColumn>,
+}
+
+// store the comparison up front, one empty reply per (model, options) column; each reply is
+// filled in as its column finishes. Returns the comparison's id and the reply ids in column order
+pub fn create_comparison(
+    conn: &Connection,
+    chat_id: &str,
+    prompt: &str,
+    columns: &[(String, String)],
+) -> Result<(i64, Vec<i64>), StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO comparisons (chat_id, prompt) VALUES (?1, ?2)",
+        params![chat_id, prompt],
+    )?;
+    let comparison_id = tx.last_insert_rowid();
+    let mut reply_ids = Vec::new();
+    for (i, (model, options)) in columns.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO comparison_replies (comparison_id, column_index, model, options, content)
+             VALUES (?1, ?2, ?3, ?4, '')",
+            params![comparison_id, i as i64, model, options],
+        )?;
+        reply_ids.push(tx.last_insert_rowid());
+    }
+    tx.commit()?;
+    Ok((comparison_id, reply_ids))
+}
+
+pub fn finish_compare_reply(conn: &Connection, column: &CompareColumn) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE comparison_replies SET content = ?1, error = ?2, first_token_ms = ?3, total_ms = ?4,
+         eval_count = ?5, tokens_per_sec = ?6 WHERE id = ?7",
+        params![
+            column.content,
+            column.error,
+            column.first_token_ms,
+            column.total_ms,
+            column.eval_count,
+            column.tokens_per_sec,
+            column.reply_id
+        ],
+    )?;
+    Ok(())
+}
+
+// remember which column the chat was continued with
+pub fn choose_compare_reply(conn: &Connection, comparison_id: i64, column: usize) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE comparisons SET chosen = ?1 WHERE id = ?2",
+        params![column as i64, comparison_id],
+    )?;
+    Ok(())
+}
+
+// most recent comparisons of a chat, newest first
+pub fn list_comparisons(conn: &Connection, chat_id: &str) -> Result<Vec<StoredComparison>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, prompt, chosen, created_at FROM comparisons
+         WHERE chat_id = ?1 ORDER BY id DESC LIMIT 10",
+    )?;
+    let mut comparisons: Vec<StoredComparison> = stmt
+        .query_map(params![chat_id], |r| {
+            Ok(StoredComparison {
+                id: r.get(0)?,
+                prompt: r.get(1)?,
+                chosen: r.get(2)?,
+                created_at: r.get(3)?,
+                columns: vec![],
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut replies = conn.prepare(
+        "SELECT id, model, options, content, error, first_token_ms, total_ms, eval_count, tokens_per_sec
+         FROM comparison_replies WHERE comparison_id = ?1 ORDER BY column_index",
+    )?;
+    for comparison in comparisons.iter_mut() {
+        comparison.columns = replies
+            .query_map(params![comparison.id], |r| {
+                Ok(CompareColumn {
+                    reply_id: r.get(0)?,
+                    model: r.get(1)?,
+                    options: r.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    content: r.get(3)?,
+                    done: true,
+                    error: r.get(4)?,
+                    first_token_ms: r.get(5)?,
+                    total_ms: r.get(6)?,
+                    eval_count: r.get(7)?,
+                    tokens_per_sec: r.get(8)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+    }
+    Ok(comparisons)
+}
+
+/* ================= TOOLS ================= */
+
+// a user tool nobody decided about yet asks first
+pub fn load_tool_policy(conn: &Connection, name: &str) -> ToolPolicy {
+    conn.query_row(
+        "SELECT policy FROM tool_policies WHERE name = ?1",
+        params![name],
+        |r| r.get::<_, String>(0),
+    )
+    .map(|p| ToolPolicy::parse(&p))
+    .unwrap_or(ToolPolicy::Ask)
+}
+
+pub fn save_tool_policy(conn: &Connection, name: &str, policy: ToolPolicy) -> Result<(), StorageError> {
+    conn.execute(
+        "INSERT INTO tool_policies (name, policy) VALUES (?1, ?2)
+         ON CONFLICT(name) DO UPDATE SET policy = excluded.policy",
+        params![name, policy.as_str()],
+    )?;
+    Ok(())
+}
+
+// one entry of the tool_runs audit log
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToolRun<'a> {
+    pub chat_id: &'a str,
+    pub tool: &'a str,
+    pub arguments: &'a str,
+    // the rendered command, for user tools
+    pub command: Option<&'a str>,
+    // "builtin", "auto", "approved" or "denied"
+    pub decision: &'a str,
+    pub exit_code: Option<i32>,
+    pub output: &'a str,
+    pub duration_ms: i64,
+}
+
+pub fn log_tool_run(conn: &Connection, run: &ToolRun) -> Result<(), StorageError> {
+    conn.execute(
+        "INSERT INTO tool_runs (chat_id, tool_name, arguments, command, decision, exit_code, output, duration_ms)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            run.chat_id,
+            run.tool,
+            run.arguments,
+            run.command,
+            run.decision,
+            run.exit_code,
+            run.output,
+            run.duration_ms
+        ],
+    )?;
+    Ok(())
+}
+
+// what the history query tool can see: chats and messages outside the Trash, without settings,
+// tool logs or anything else in the database
+const HISTORY_TABLES: [(&str, &str); 2] = [
+    (
+        "chats",
+        "SELECT id, title, created_at, updated_at, pinned, archived, folder_id FROM chats WHERE deleted_at IS NULL",
+    ),
+    (
+        "messages",
+        "SELECT m.id, m.chat_id, m.role, m.content, m.timestamp, m.model, m.persona, m.tool_name, m.tool_calls
+         FROM messages m JOIN chats c ON c.id = m.chat_id WHERE c.deleted_at IS NULL",
+    ),
+];
+
+// run `sql` over a copy of the visible history (HISTORY_TABLES) taken from the database at `path`.
+// An authorizer refuses every other table, ATTACH and PRAGMA, statements SQLite says would write
+// are rejected, and the whole call is interrupted after `timeout`. Returns the first `max_rows`
+// rows as JSON objects, and whether there were more
+pub fn query_history(
+    path: &str,
+    sql: &str,
+    max_rows: usize,
+    timeout: Duration,
+) -> Result<(Vec<Value>, bool), StorageError> {
+    let deadline = std::time::Instant::now() + timeout;
+    let timed_out = move |e: rusqlite::Error| {
+        if std::time::Instant::now() >= deadline {
+            StorageError::Query(format!("the query took longer than {}s", timeout.as_secs()))
+        } else {
+            StorageError::from(e)
+        }
+    };
+
+    let source = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|e| StorageError::Open {
+        path: path.to_string(),
+        reason: e.to_string(),
+    })?;
+    source.progress_handler(1000, Some(move || std::time::Instant::now() >= deadline));
+    let conn = Connection::open_in_memory()?;
+    conn.progress_handler(1000, Some(move || std::time::Instant::now() >= deadline));
+
+    let tx = conn.unchecked_transaction()?;
+    for (table, select) in HISTORY_TABLES {
+        let mut stmt = source.prepare(select)?;
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        tx.execute(&format!("CREATE TABLE {} ({})", table, columns.join(", ")), [])?;
+        let mut insert = tx.prepare(&format!(
+            "INSERT INTO {} VALUES ({})",
+            table,
+            vec!["?"; columns.len()].join(", ")
+        ))?;
+        let mut rows = stmt.query([]).map_err(timed_out)?;
+        while let Some(row) = rows.next().map_err(timed_out)? {
+            let values = (0..columns.len())
+                .map(|i| row.get::<_, rusqlite::types::Value>(i))
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            insert.execute(rusqlite::params_from_iter(values)).map_err(timed_out)?;
+        }
+    }
+    tx.commit()?;
+    drop(source);
+
+    conn.authorizer(Some(|ctx: AuthContext<'_>| match ctx.action {
+        AuthAction::Select | AuthAction::Recursive | AuthAction::Function { .. } => Authorization::Allow,
+        // no database means a CTE or a bare table reference as in COUNT(*): no stored columns are read
+        AuthAction::Read { .. } if ctx.database_name.is_none() => Authorization::Allow,
+        AuthAction::Read { table_name, .. }
+            if ctx.database_name == Some("main") && HISTORY_TABLES.iter().any(|(t, _)| *t == table_name) =>
+        {
+            Authorization::Allow
+        }
+        _ => Authorization::Deny,
+    }));
+    let mut stmt = conn
+        .prepare(sql)
+        .map_err(|e| StorageError::Query(format!("{} (only SELECTs over chats and messages are allowed)", e)))?;
+    if !stmt.readonly() {
+        return Err(StorageError::Query("only read-only queries are allowed".to_string()));
+    }
+
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows = stmt.query([]).map_err(timed_out)?;
+    let mut out: Vec<Value> = Vec::new();
+    while let Some(row) = rows.next().map_err(timed_out)? {
+        if out.len() >= max_rows {
+            return Ok((out, true));
+        }
+        let mut obj = serde_json::Map::new();
+        for (i, name) in columns.iter().enumerate() {
+            let value = match row.get_ref(i)? {
+                ValueRef::Null => Value::Null,
+                ValueRef::Integer(n) => Value::from(n),
+                ValueRef::Real(f) => Value::from(f),
+                ValueRef::Text(t) => Value::from(String::from_utf8_lossy(t).to_string()),
+                ValueRef::Blob(b) => Value::from(format!("<{} bytes>", b.len())),
+            };
+            obj.insert(name.clone(), value);
+        }
+        out.push(Value::Object(obj));
+    }
+    Ok((out, false))
+}
+
+// (local date and time, UTC date and time, weekday with 0 = Sunday); SQLite already knows the
+// local time zone, so it is asked rather than pulling in a date crate
+pub fn current_time() -> Result<(String, String, i64), StorageError> {
+    let conn = Connection::open_in_memory()?;
+    Ok(conn.query_row(
+        "SELECT datetime('now', 'localtime'), datetime('now'), CAST(strftime('%w', 'now', 'localtime') AS INTEGER)",
+        [],
+        |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+    )?)
+}
+
+/* ================= CHAT ERRORS ================= */
+
+// a failed request, kept with its chat until dismissed or superseded by a new turn
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatErrorRecord {
+    pub id: i64,
+    pub kind: String,
+    pub message: String,
+    // the user message whose reply failed; lets Retry send it again
+    pub user_message: Option<String>,
+}
+
+pub fn record_chat_error(
+    conn: &Connection,
+    chat_id: &str,
+    err: &ChatError,
+    user_message: &str,
+) -> Result<ChatErrorRecord, StorageError> {
+    let message = err.to_string();
+    conn.execute(
+        "INSERT INTO chat_errors (chat_id, kind, message, user_message) VALUES (?1, ?2, ?3, ?4)",
+        params![chat_id, err.kind(), message, user_message],
+    )?;
+    Ok(ChatErrorRecord {
+        id: conn.last_insert_rowid(),
+        kind: err.kind().to_string(),
+        message,
+        user_message: Some(user_message.to_string()),
+    })
+}
+
+pub fn list_chat_errors(conn: &Connection, chat_id: &str) -> Result<Vec<ChatErrorRecord>, StorageError> {
+    let mut stmt =
+        conn.prepare("SELECT id, kind, message, user_message FROM chat_errors WHERE chat_id = ?1 ORDER BY id")?;
+    let rows = stmt.query_map(params![chat_id], |r| {
+        Ok(ChatErrorRecord {
+            id: r.get(0)?,
+            kind: r.get(1)?,
+            message: r.get(2)?,
+            user_message: r.get(3)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+pub fn dismiss_chat_error(conn: &Connection, id: i64) -> Result<(), StorageError> {
+    conn.execute("DELETE FROM chat_errors WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+pub fn clear_chat_errors(conn: &Connection, chat_id: &str) -> Result<(), StorageError> {
+    conn.execute("DELETE FROM chat_errors WHERE chat_id = ?1", params![chat_id])?;
+    Ok(())
+}
+
+/* ================= MESSAGE QUEUE ================= */
+
+// a follow-up typed while the chat was busy, sent when it's free
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueuedMessage {
+    pub id: i64,
+    pub content: String,
+}
+
+pub fn queue_message(conn: &Connection, chat_id: &str, content: &str) -> Result<(), StorageError> {
+    conn.execute(
+        "INSERT INTO message_queue (chat_id, content) VALUES (?1, ?2)",
+        params![chat_id, content],
+    )?;
+    Ok(())
+}
+
+pub fn list_queued_messages(conn: &Connection, chat_id: &str) -> Result<Vec<QueuedMessage>, StorageError> {
+    let mut stmt = conn.prepare("SELECT id, content FROM message_queue WHERE chat_id = ?1 ORDER BY id")?;
+    let rows = stmt.query_map(params![chat_id], |r| {
+        Ok(QueuedMessage {
+            id: r.get(0)?,
+            content: r.get(1)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+// chats that have something waiting in the queue; chats in the Trash keep theirs but don't send it
+pub fn chats_with_queue(conn: &Connection) -> Result<HashSet<String>, StorageError> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT q.chat_id FROM message_queue q JOIN chats c ON c.id = q.chat_id
+         WHERE c.deleted_at IS NULL",
+    )?;
+    let rows = stmt.query_map([], |r| r.get::<_, String>(0))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+pub fn update_queued_message(conn: &Connection, id: i64, content: &str) -> Result<(), StorageError> {
+    conn.execute(
+        "UPDATE message_queue SET content = ?1 WHERE id = ?2",
+        params![content, id],
+    )?;
+    Ok(())
+}
+
+pub fn remove_queued_message(conn: &Connection, id: i64) -> Result<(), StorageError> {
+    conn.execute("DELETE FROM message_queue WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+/* ================= BACKUPS ================= */
+
+// backups are named `chat-<local time>.db`, so sorting the names sorts them by age
+const BACKUP_PREFIX: &str = "chat-";
+const BACKUP_SUFFIX: &str = ".db";
+// pages copied per step of an online backup; the database stays writable between steps
+const BACKUP_PAGES_PER_STEP: std::os::raw::c_int = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    // local time the backup was taken, "YYYY-MM-DD HH:MM:SS"
+    pub taken_at: String,
+    pub chats: i64,
+    pub messages: i64,
+}
+
+// a chat stored in a backup, offered for restoring on its own
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupChat {
+    pub id: String,
+    pub title: String,
+    pub messages: i64,
+}
+
+// copy the whole database into the file `dest` with SQLite's online backup API
+pub fn backup_to(conn: &Connection, dest: &Path) -> Result<(), StorageError> {
+    let mut target = Connection::open(dest)?;
+    let backup = Backup::new(conn, &mut target)?;
+    backup.run_to_completion(BACKUP_PAGES_PER_STEP, Duration::from_millis(5), None)?;
+    Ok(())
+}
+
+// take a timestamped backup in `dir` and delete the oldest ones beyond `keep`; returns its path
+pub fn create_backup(conn: &Connection, dir: &Path, keep: usize) -> Result<PathBuf, StorageError> {
+    let io_error = |e: std::io::Error| StorageError::Query(format!("{}: {}", dir.display(), e));
+    std::fs::create_dir_all(dir).map_err(io_error)?;
+    let stamp: String = conn.query_row("SELECT strftime('%Y-%m-%d_%H-%M-%f', 'now', 'localtime')", [], |r| r.get(0))?;
+    let path = dir.join(format!("{}{}{}", BACKUP_PREFIX, stamp, BACKUP_SUFFIX));
+    // written under another name first so a half-finished copy is never listed
+    let partial = path.with_extension("partial");
+    if let Err(e) = backup_to(conn, &partial) {
+        std::fs::remove_file(&partial).ok();
+        return Err(e);
+    }
+    std::fs::rename(&partial, &path).map_err(io_error)?;
+
+    let files = backup_files(dir);
+    for old in files.iter().take(files.len().saturating_sub(keep.max(1))) {
+        std::fs::remove_file(old).map_err(io_error)?;
+    }
+    Ok(path)
+}
+
+// backup files in `dir`, oldest first
+fn backup_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.file_name().and_then(|n| n.to_str()).and_then(backup_time).is_some())
+        .collect();
+    files.sort();
+    files
+}
+
+// "chat-2024-05-01_09-30-12.345.db" -> "2024-05-01 09:30:12"
+fn backup_time(file_name: &str) -> Option<String> {
+    let stamp = file_name.strip_prefix(BACKUP_PREFIX)?.strip_suffix(BACKUP_SUFFIX)?;
+    let (date, time) = stamp.split_once('_')?;
+    let time = time.split('.').next()?.replace('-', ":");
+    (date.len() == 10 && time.len() == 8).then(|| format!("{} {}", date, time))
+}
+
+// how long ago the newest backup in `dir` was written (None = no backups yet)
+pub fn newest_backup_age(dir: &Path) -> Option<Duration> {
+    let newest = backup_files(dir).pop()?;
+    let modified = std::fs::metadata(newest).and_then(|m| m.modified()).ok()?;
+    Some(modified.elapsed().unwrap_or_default())
+}
+
+fn open_backup(path: &Path) -> Result<Connection, StorageError> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|e| StorageError::Open {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    })
+}
+
+// the backups in `dir`, newest first, with how many chats and messages each holds
+// (chats in the Trash aside); files that can't be read are left out
+pub fn list_backups(dir: &Path) -> Vec<BackupInfo> {
+    let mut backups: Vec<BackupInfo> = backup_files(dir)
+        .into_iter()
+        .filter_map(|path| {
+            let taken_at = backup_time(path.file_name()?.to_str()?)?;
+            let (chats, messages) = open_backup(&path)
+                .ok()?
+                .query_row(
+                    "SELECT (SELECT COUNT(*) FROM chats WHERE deleted_at IS NULL),
+                            (SELECT COUNT(*) FROM messages m JOIN chats c ON c.id = m.chat_id WHERE c.deleted_at IS NULL)",
+                    [],
+                    |r| Ok((r.get(0)?, r.get(1)?)),
+                )
+                .ok()?;
+            Some(BackupInfo { path, taken_at, chats, messages })
+        })
+        .collect();
+    backups.reverse();
+    backups
+}
+
+// chats stored in the backup at `path`, most recently active first
+pub fn backup_chats(path: &Path) -> Result<Vec<BackupChat>, StorageError> {
+    let conn = open_backup(path)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, title, (SELECT COUNT(*) FROM messages WHERE chat_id = chats.id) FROM chats
+         WHERE deleted_at IS NULL ORDER BY updated_at DESC, rowid DESC",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(BackupChat {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            messages: row.get(2)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+// replace everything in the database with the backup at `path` (online, like taking one)
+pub fn restore_backup(conn: &mut Connection, path: &Path) -> Result<(), StorageError> {
+    let source = open_backup(path)?;
+    Backup::new(&source, conn)?.run_to_completion(BACKUP_PAGES_PER_STEP, Duration::from_millis(5), None)?;
+    migrate(conn)
+}
+
+// put the chats `ids` back as they are in the backup at `path`, replacing their current
+// state; returns how many of them the backup had
+pub fn restore_backup_chats(conn: &Connection, path: &Path, ids: &[String]) -> Result<usize, StorageError> {
+    conn.execute("ATTACH DATABASE ?1 AS backup", params![path.to_string_lossy()])?;
+    let restored = copy_backup_chats(conn, ids);
+    conn.execute("DETACH DATABASE backup", [])?;
+    restored
+}
+
+fn copy_backup_chats(conn: &Connection, ids: &[String]) -> Result<usize, StorageError> {
+    let tx = conn.unchecked_transaction()?;
+    let mut restored = 0;
+    for id in ids {
+        let in_backup: bool = tx.query_row("SELECT EXISTS(SELECT 1 FROM backup.chats WHERE id = ?1)", params![id], |r| r.get(0))?;
+        if !in_backup {
+            continue;
+        }
+        delete_chat_rows(&tx, id)?;
+        copy_backup_rows(&tx, "chats", "id", id)?;
+        // messages and errors get new ids; embeddings are computed again in the background
+        copy_backup_rows(&tx, "messages", "chat_id", id)?;
+        copy_backup_rows(&tx, "chat_errors", "chat_id", id)?;
+        copy_backup_rows(&tx, "chat_tags", "chat_id", id)?;
+        // the folder it was filed in may have been deleted since
+        tx.execute(
+            "UPDATE chats SET folder_id = NULL WHERE id = ?1 AND folder_id NOT IN (SELECT id FROM folders)",
+            params![id],
+        )?;
+        restored += 1;
+    }
+    tx.commit()?;
+    Ok(restored)
+}
+
+// copy the rows of `table` whose `key` is `value` from the attached backup, using the columns
+// both schemas have (a backup can be older than the database) and leaving out row ids
+fn copy_backup_rows(conn: &Connection, table: &str, key: &str, value: &str) -> rusqlite::Result<()> {
+    let columns = |schema: &str| -> rusqlite::Result<Vec<String>> {
+        conn.prepare(&format!("PRAGMA {}.table_info({})", schema, table))?
+            .query_map([], |r| r.get::<_, String>(1))?
+            .collect()
+    };
+    let in_backup = columns("backup")?;
+    let shared: Vec<String> = columns("main")?
+        .into_iter()
+        .filter(|c| c != "id" || table == "chats")
+        .filter(|c| in_backup.contains(c))
+        .collect();
+    if shared.is_empty() {
+        return Ok(());
+    }
+    let list = shared.join(", ");
+    // rows get new ids, so copy them in their original order (rowid is `id` where there is one)
+    conn.execute(
+        &format!(
+            "INSERT INTO main.{t} ({list}) SELECT {list} FROM backup.{t} WHERE {key} = ?1 ORDER BY rowid",
+            t = table,
+            list = list,
+            key = key
+        ),
+        params![value],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        conn
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |r| r.get(0)).unwrap()
+    }
+
+    #[test]
+    fn new_database_gets_default_settings() {
+        let conn = memory_db();
+        let s = load_settings(&conn).unwrap();
+        assert_eq!(s.model, "");
+        assert_eq!(s.temperature, 0.7);
+        assert_eq!(s.max_tokens, 512);
+        assert_eq!(s.zoom, 100);
+        assert_eq!(s.kb_top_k, 4);
+        assert!(!s.tools_enabled);
+        assert_eq!(s.max_generations, 0);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let conn = memory_db();
+        let mut s = load_settings(&conn).unwrap();
+        s.model = "llama3".to_string();
+        s.system_prompt = "be brief".to_string();
+        s.top_p = 0.5;
+        s.tools_enabled = true;
+        s.embed_model = "nomic-embed-text".to_string();
+        s.max_generations = 2;
+        s.notify_background = true;
+        s.api_enabled = true;
+        s.api_port = 9000;
+        s.api_token = "secret".to_string();
+        s.trash_days = 7;
+        s.backup_hours = 6;
+        s.backup_keep = 3;
+        save_settings(&conn, &s).unwrap();
+
+        let loaded = load_settings(&conn).unwrap();
+        assert_eq!(loaded.model, "llama3");
+        assert_eq!(loaded.system_prompt, "be brief");
+        assert_eq!(loaded.top_p, 0.5);
+        assert!(loaded.tools_enabled);
+        assert_eq!(loaded.embed_model, "nomic-embed-text");
+        assert_eq!(loaded.max_generations, 2);
+        assert!(loaded.notify_background);
+        assert!(loaded.api_enabled);
+        assert_eq!(loaded.api_port, 9000);
+        assert_eq!(loaded.api_token, "secret");
+        assert_eq!(loaded.trash_days, 7);
+        assert_eq!((loaded.backup_hours, loaded.backup_keep), (6, 3));
+    }
+
+    #[test]
+    fn migrate_upgrades_an_old_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE chats (id TEXT PRIMARY KEY, title TEXT NOT NULL);
+             CREATE TABLE messages (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 chat_id TEXT NOT NULL,
+                 role TEXT NOT NULL,
+                 content TEXT NOT NULL,
+                 timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+             );
+             CREATE TABLE settings (
+                 id INTEGER PRIMARY KEY CHECK (id = 1),
+                 model TEXT NOT NULL, system_prompt TEXT, temperature REAL, top_p REAL,
+                 max_tokens INTEGER, zoom INTEGER, maximized INTEGER,
+                 window_width INTEGER, window_height INTEGER
+             );
+             INSERT INTO settings VALUES (1, 'mistral', '', 0.3, 0.9, 256, 110, 1, 800, 600);
+             INSERT INTO chats VALUES ('c1', 'Old chat');
+             INSERT INTO messages (chat_id, role, content) VALUES ('c1', 'user', 'hello');
+             INSERT INTO messages (chat_id, role, content) VALUES ('c1', 'assistant', 'Error: Could not connect to Ollama. Make sure Ollama is running at http://localhost:11434');
+             INSERT INTO messages (chat_id, role, content) VALUES ('c1', 'assistant', 'Error: Ollama API returned status 404 Not Found');
+             INSERT INTO messages (chat_id, role, content) VALUES ('c1', 'assistant', 'Error: means the build failed; check the log.');",
+        )
+        .unwrap();
+
+        migrate(&conn).unwrap();
+        // a second run finds nothing to do
+        migrate(&conn).unwrap();
+
+        let s = load_settings(&conn).unwrap();
+        assert_eq!(s.model, "mistral");
+        assert_eq!(s.max_tokens, 256);
+        assert!(!s.embed_messages);
+
+        // errors stored as replies by older versions are moved out of the history
+        let history = load_chat_messages(&conn, "c1").unwrap();
+        // (a real reply that merely starts with "Error:" stays)
+        assert_eq!(
+            history,
+            vec![
+                ChatMessage::new("user", "hello"),
+                ChatMessage::new("assistant", "Error: means the build failed; check the log.")
+            ]
+        );
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM chat_errors WHERE kind = 'legacy'"), 2);
+
+        // new columns are usable
+        insert_message(
+            &conn,
+            "c1",
+            &ChatMessage {
+                model: Some("mistral".into()),
+                persona: Some("Reviewer".into()),
+                ..ChatMessage::new("assistant", "hi")
+            },
+            None,
+        )
+        .unwrap();
+        assert_eq!(list_used_models(&conn).unwrap(), vec!["mistral"]);
+
+        // the old chat is dated by its messages
+        let activity = &chat_activity(&conn).unwrap()["c1"];
+        assert!(!activity.created_at.is_empty());
+        assert!(activity.updated_at >= activity.created_at);
+        assert!(!activity.pinned && !activity.archived);
+    }
+
+    #[test]
+    fn chats_can_be_created_renamed_and_deleted() {
+        let conn = memory_db();
+        create_chat(&conn, "a", "New Chat", None).unwrap();
+        create_chat(&conn, "b", "New Chat", None).unwrap();
+
+        let long = "é".repeat(200);
+        let stored = rename_chat(&conn, "a", &long).unwrap();
+        assert!(stored.len() <= MAX_TITLE_LEN);
+        assert!(long.starts_with(&stored));
+
+        insert_message(&conn, "a", &ChatMessage::new("user", "hi"), None).unwrap();
+        conn.execute(
+            "INSERT INTO message_queue (chat_id, content) VALUES ('a', 'later')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO chat_errors (chat_id, kind, message) VALUES ('a', 'connect', 'down')",
+            [],
+        )
+        .unwrap();
+        insert_message(&conn, "b", &ChatMessage::new("user", "kept"), None).unwrap();
+
+        assert!(chat_exists(&conn, "a").unwrap());
+        delete_chat(&conn, "a").unwrap();
+        assert!(!chat_exists(&conn, "a").unwrap());
+        assert_eq!(list_chats(&conn).unwrap(), vec![("b".to_string(), "New Chat".to_string())]);
+        assert!(load_chat_messages(&conn, "a").unwrap().is_empty());
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM message_queue"), 0);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM chat_errors"), 0);
+        assert_eq!(load_chat_messages(&conn, "b").unwrap().len(), 1);
+
+        delete_all_chats(&conn).unwrap();
+        assert!(list_chats(&conn).unwrap().is_empty());
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM messages"), 0);
+    }
+
+    #[test]
+    fn messages_keep_order_and_generation_metadata() {
+        let conn = memory_db();
+        create_chat(&conn, "c", "New Chat", None).unwrap();
+        insert_message(&conn, "c", &ChatMessage::new("user", "question"), None).unwrap();
+        let reply = ChatMessage {
+            model: Some("llama3".into()),
+            sources: Some("[]".into()),
+            ..ChatMessage::new("assistant", "answer")
+        };
+        let meta = GenerationMeta {
+            endpoint: "http://localhost:11434".into(),
+            system_prompt_hash: hash_system_prompt("be brief"),
+            options: Some(r#"{"temperature":0.7}"#.into()),
+        };
+        let id = insert_message(&conn, "c", &reply, Some(&meta)).unwrap();
+
+        assert_eq!(
+            load_chat_messages(&conn, "c").unwrap(),
+            vec![ChatMessage::new("user", "question"), reply]
+        );
+        let (endpoint, hash, options): (String, String, String) = conn
+            .query_row(
+                "SELECT endpoint, system_prompt_hash, options FROM messages WHERE id = ?1",
+                params![id],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(endpoint, meta.endpoint);
+        assert_eq!(Some(hash), meta.system_prompt_hash);
+        assert_eq!(Some(options), meta.options);
+        assert_eq!(chats_with_model(&conn, "llama3"), HashSet::from(["c".to_string()]));
+    }
+
+    #[test]
+    fn history_is_trimmed_to_the_limit() {
+        let conn = memory_db();
+        let tx = conn.unchecked_transaction().unwrap();
+        for i in 0..MAX_HISTORY_MESSAGES + 5 {
+            insert_message(&tx, "c", &ChatMessage::new("user", format!("m{}", i)), None).unwrap();
+        }
+        tx.commit().unwrap();
+
+        enforce_history_limit(&conn, "c");
+        let history = load_chat_messages(&conn, "c").unwrap();
+        assert_eq!(history.len() as i64, MAX_HISTORY_MESSAGES);
+        assert_eq!(history[0].content, "m5");
+        assert_eq!(
+            history.last().unwrap().content,
+            format!("m{}", MAX_HISTORY_MESSAGES + 4)
+        );
+    }
+
+    #[test]
+    fn unread_flags_follow_the_chat() {
+        let conn = memory_db();
+        create_chat(&conn, "a", "New Chat", None).unwrap();
+        create_chat(&conn, "b", "New Chat", None).unwrap();
+        mark_chat_unread(&conn, "a", true).unwrap();
+        mark_chat_unread(&conn, "b", true).unwrap();
+        mark_chat_unread(&conn, "b", false).unwrap();
+        assert_eq!(list_unread_chats(&conn), HashSet::from(["a".to_string()]));
+    }
+
+    #[test]
+    fn proxy_chats_are_found_by_client_and_fingerprint() {
+        let conn = memory_db();
+        create_chat(&conn, "p", "Hello", None).unwrap();
+        create_chat(&conn, "gui", "New Chat", None).unwrap();
+        set_proxy_chat(&conn, "p", "editor", "abc").unwrap();
+
+        assert_eq!(find_proxy_chat(&conn, "editor", "abc").unwrap().as_deref(), Some("p"));
+        assert_eq!(find_proxy_chat(&conn, "other-tool", "abc").unwrap(), None);
+        assert_eq!(find_proxy_chat(&conn, "editor", "def").unwrap(), None);
+        assert_eq!(
+            chat_clients(&conn),
+            HashMap::from([("p".to_string(), "editor".to_string())])
+        );
+    }
+
+    #[test]
+    fn chats_are_listed_pinned_first_then_by_last_activity() {
+        let conn = memory_db();
+        for id in ["a", "b", "c"] {
+            create_chat(&conn, id, id, None).unwrap();
+        }
+        let ids = |conn: &Connection| list_chats(conn).unwrap().into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(ids(&conn), vec!["c", "b", "a"]);
+
+        conn.execute("UPDATE chats SET updated_at = '2020-01-01 10:00:00'", []).unwrap();
+        insert_message(&conn, "a", &ChatMessage::new("user", "bump"), None).unwrap();
+        assert_eq!(ids(&conn), vec!["a", "c", "b"]);
+
+        set_chat_pinned(&conn, "b", true).unwrap();
+        assert_eq!(ids(&conn), vec!["b", "a", "c"]);
+
+        set_chat_archived(&conn, "c", true).unwrap();
+        let activity = chat_activity(&conn).unwrap();
+        assert!(activity["c"].archived && activity["b"].pinned);
+        assert_eq!(activity["a"].days_ago, 0);
+        assert!(activity["c"].days_ago > 365);
+        // archived chats are still listed; the sidebar decides where they show
+        assert_eq!(ids(&conn).len(), 3);
+    }
+
+    #[test]
+    fn trashed_chats_can_be_restored_until_purged() {
+        let conn = memory_db();
+        for id in ["a", "b", "c"] {
+            create_chat(&conn, id, id, None).unwrap();
+            insert_message(&conn, id, &ChatMessage::new("user", "hi"), None).unwrap();
+        }
+        conn.execute_batch(
+            "INSERT INTO comparisons (id, chat_id, prompt) VALUES (1, 'b', 'which?');
+             INSERT INTO comparison_replies (comparison_id, column_index, model, content) VALUES (1, 0, 'm', 'this');",
+        )
+        .unwrap();
+        trash_chats(&conn, &["a".to_string(), "b".to_string()]).unwrap();
+        let ids = |conn: &Connection| list_chats(conn).unwrap().into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(ids(&conn), vec!["c"]);
+        assert!(!chat_exists(&conn, "a").unwrap());
+        assert_eq!(list_trash(&conn).unwrap().len(), 2);
+        // the messages are kept while the chat is in the Trash
+        assert_eq!(load_chat_messages(&conn, "a").unwrap().len(), 1);
+
+        restore_chats(&conn, &["a".to_string()]).unwrap();
+        assert_eq!(ids(&conn), vec!["c", "a"]);
+
+        // "b" was deleted 40 days ago: a 30-day limit purges it, nothing else
+        conn.execute("UPDATE chats SET deleted_at = datetime('now', '-40 days') WHERE id = 'b'", [])
+            .unwrap();
+        trash_chats(&conn, &["c".to_string()]).unwrap();
+        assert_eq!(purge_trash(&conn, 30).unwrap(), 1);
+        assert_eq!(
+            list_trash(&conn).unwrap().into_iter().map(|t| t.id).collect::<Vec<_>>(),
+            vec!["c"]
+        );
+        assert!(load_chat_messages(&conn, "b").unwrap().is_empty());
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM comparisons"), 0);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM comparison_replies"), 0);
+
+        assert_eq!(purge_trash(&conn, 0).unwrap(), 1);
+        assert!(list_trash(&conn).unwrap().is_empty());
+        assert_eq!(ids(&conn), vec!["a"]);
+    }
+
+    #[test]
+    fn folders_keep_their_order_and_release_chats_when_deleted() {
+        let conn = memory_db();
+        create_chat(&conn, "a", "New Chat", None).unwrap();
+        create_chat(&conn, "b", "New Chat", None).unwrap();
+        let work = create_folder(&conn, " Work ").unwrap();
+        let home = create_folder(&conn, "Home").unwrap();
+        let misc = create_folder(&conn, "Misc").unwrap();
+        let names = |conn: &Connection| list_folders(conn).unwrap().into_iter().map(|f| f.name).collect::<Vec<_>>();
+        assert_eq!(names(&conn), vec!["Work", "Home", "Misc"]);
+
+        move_folder(&conn, misc, Some(work)).unwrap();
+        assert_eq!(names(&conn), vec!["Misc", "Work", "Home"]);
+        move_folder(&conn, misc, None).unwrap();
+        assert_eq!(names(&conn), vec!["Work", "Home", "Misc"]);
+
+        set_folder_collapsed(&conn, home, true).unwrap();
+        assert!(list_folders(&conn).unwrap()[1].collapsed);
+
+        set_chats_folder(&conn, &["a".to_string(), "b".to_string()], Some(work)).unwrap();
+        set_chats_folder(&conn, &["b".to_string()], Some(home)).unwrap();
+        assert_eq!(chat_folders(&conn), HashMap::from([("a".to_string(), work), ("b".to_string(), home)]));
+
+        delete_folder(&conn, work).unwrap();
+        assert_eq!(names(&conn), vec!["Home", "Misc"]);
+        assert_eq!(chat_folders(&conn), HashMap::from([("b".to_string(), home)]));
+        assert_eq!(list_chats(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn tags_are_added_in_bulk_and_merged_regardless_of_case() {
+        let conn = memory_db();
+        let both = vec!["a".to_string(), "b".to_string()];
+        add_tag(&conn, &both, "Work").unwrap();
+        add_tag(&conn, &["b".to_string()], " #work ").unwrap();
+        add_tag(&conn, &["b".to_string()], "rust").unwrap();
+        add_tag(&conn, &both, "  ").unwrap();
+        assert_eq!(list_tags(&conn), vec!["rust", "Work"]);
+        assert_eq!(chat_tags(&conn).unwrap()["b"], vec!["rust", "Work"]);
+
+        remove_tag(&conn, &both, "WORK").unwrap();
+        assert_eq!(list_tags(&conn), vec!["rust"]);
+        assert!(!chat_tags(&conn).unwrap().contains_key("a"));
+
+        delete_chat(&conn, "b").unwrap();
+        assert!(list_tags(&conn).is_empty());
+    }
+
+    #[test]
+    fn system_prompt_hash_is_stable() {
+        assert_eq!(hash_system_prompt(""), None);
+        assert_eq!(hash_system_prompt("be brief"), hash_system_prompt("be brief"));
+        assert_ne!(hash_system_prompt("be brief"), hash_system_prompt("be verbose"));
+        assert_eq!(hash_system_prompt("a").unwrap().len(), 16);
+    }
+
+    #[test]
+    fn default_settings_match_a_new_database() {
+        assert_eq!(load_settings(&memory_db()).unwrap(), Settings::default());
+    }
+
+    #[test]
+    fn opening_a_file_that_is_not_a_database_fails() {
+        let path = std::env::temp_dir().join(format!("rusty-chat-test-{}.db", std::process::id()));
+        std::fs::write(&path, b"definitely not an sqlite file, just some text that is long enough").unwrap();
+        let result = open(path.to_str().unwrap());
+        std::fs::remove_file(&path).ok();
+        match result {
+            Err(StorageError::Open { path: p, .. }) => assert_eq!(p, path.to_str().unwrap()),
+            other => panic!("expected an open error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn sqlite_store_behaves_like_the_key_value_store() {
+        let path = std::env::temp_dir().join(format!("rusty-chat-store-{}.db", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        crate::test_support::exercise_store(&SqliteStore::new(path.to_str().unwrap()));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn latest_reply_is_the_newest_assistant_message() {
+        let conn = memory_db();
+        create_chat(&conn, "a", "Rust", None).unwrap();
+        assert_eq!(latest_reply(&conn, "a").unwrap(), ("Rust".to_string(), String::new()));
+        insert_message(&conn, "a", &ChatMessage::new("assistant", "first"), None).unwrap();
+        insert_message(&conn, "a", &ChatMessage::new("assistant", "second"), None).unwrap();
+        insert_message(&conn, "a", &ChatMessage::new("user", "thanks"), None).unwrap();
+        assert_eq!(latest_reply(&conn, "a").unwrap(), ("Rust".to_string(), "second".to_string()));
+        assert!(latest_reply(&conn, "missing").is_err());
+    }
+
+    #[test]
+    fn chat_formats_and_saved_schemas() {
+        let conn = memory_db();
+        create_chat(&conn, "a", "Chat", None).unwrap();
+        assert_eq!(load_chat_format(&conn, "a").unwrap(), None);
+        save_chat_format(&conn, "a", Some("json")).unwrap();
+        assert_eq!(load_chat_format(&conn, "a").unwrap().as_deref(), Some("json"));
+        save_chat_format(&conn, "a", None).unwrap();
+        assert_eq!(load_chat_format(&conn, "a").unwrap(), None);
+
+        save_schema(&conn, "person", r#"{"type":"object"}"#).unwrap();
+        save_schema(&conn, "list", r#"{"type":"array"}"#).unwrap();
+        save_schema(&conn, "person", r#"{"type":"string"}"#).unwrap();
+        assert_eq!(
+            list_saved_schemas(&conn),
+            vec![
+                ("list".to_string(), r#"{"type":"array"}"#.to_string()),
+                ("person".to_string(), r#"{"type":"string"}"#.to_string()),
+            ]
+        );
+        delete_schema(&conn, "list").unwrap();
+        assert_eq!(list_saved_schemas(&conn).len(), 1);
+    }
+
+    #[test]
+    fn knowledge_base_sources_are_replaced_whole() {
+        let conn = memory_db();
+        let chunk = |file: &str, i: i64, embedding: Vec<f32>| KbChunk {
+            file_path: file.to_string(),
+            chunk_index: i,
+            content: format!("{file} #{i}"),
+            embedding,
+        };
+        replace_kb_source(&conn, "/docs", "nomic", &[chunk("a.md", 0, vec![1.0, 0.0]), chunk("a.md", 1, vec![0.0, 1.0])])
+            .unwrap();
+        replace_kb_source(&conn, "/notes", "other", &[chunk("b.md", 0, vec![0.5])]).unwrap();
+        assert_eq!(kb_chunks(&conn, "nomic").unwrap(), vec![chunk("a.md", 0, vec![1.0, 0.0]), chunk("a.md", 1, vec![0.0, 1.0])]);
+
+        replace_kb_source(&conn, "/docs", "nomic", &[chunk("c.md", 0, vec![0.25, 0.75])]).unwrap();
+        assert_eq!(kb_chunks(&conn, "nomic").unwrap(), vec![chunk("c.md", 0, vec![0.25, 0.75])]);
+        let sources = list_kb_sources(&conn);
+        let docs = sources.iter().find(|s| s.path == "/docs").unwrap();
+        assert_eq!((docs.embed_model.as_str(), docs.chunks), ("nomic", 1));
+
+        remove_kb_source(&conn, docs.id).unwrap();
+        assert!(kb_chunks(&conn, "nomic").unwrap().is_empty());
+        assert_eq!(list_kb_sources(&conn).len(), 1);
+
+        create_chat(&conn, "a", "Chat", None).unwrap();
+        assert!(!chat_uses_kb(&conn, "a"));
+        set_chat_uses_kb(&conn, "a", true).unwrap();
+        assert!(chat_uses_kb(&conn, "a"));
+    }
+
+    #[test]
+    fn message_embeddings_track_progress_and_skip_the_trash() {
+        let conn = memory_db();
+        create_chat(&conn, "a", "Kept", None).unwrap();
+        create_chat(&conn, "b", "Trashed", None).unwrap();
+        let hi = insert_message(&conn, "a", &ChatMessage::new("user", "hi"), None).unwrap();
+        // errors live in chat_errors, so a reply starting with "Error:" is a real answer
+        let answer = insert_message(&conn, "a", &ChatMessage::new("assistant", "Error: means it failed"), None).unwrap();
+        insert_message(&conn, "a", &ChatMessage::new("system", "be brief"), None).unwrap();
+        let other = insert_message(&conn, "b", &ChatMessage::new("assistant", "hello"), None).unwrap();
+
+        assert_eq!(
+            pending_message_embeddings(&conn, "nomic", 10).unwrap(),
+            vec![
+                (hi, "hi".to_string()),
+                (answer, "Error: means it failed".to_string()),
+                (other, "hello".to_string())
+            ]
+        );
+        assert_eq!(message_embedding_progress(&conn, "nomic"), (0, 3));
+
+        store_message_embeddings(&conn, "nomic", &[(hi, vec![1.0, 2.0]), (answer, vec![0.5]), (other, vec![3.0])])
+            .unwrap();
+        assert!(pending_message_embeddings(&conn, "nomic", 10).unwrap().is_empty());
+        assert_eq!(message_embedding_progress(&conn, "nomic"), (3, 3));
+        assert_eq!(message_embedding_progress(&conn, "other"), (0, 3));
+
+        trash_chats(&conn, &["b".to_string()]).unwrap();
+        let embedded = embedded_messages(&conn, "nomic").unwrap();
+        assert_eq!(
+            embedded,
+            vec![
+                EmbeddedMessage {
+                    chat_id: "a".to_string(),
+                    chat_title: "Kept".to_string(),
+                    role: "user".to_string(),
+                    content: "hi".to_string(),
+                    embedding: vec![1.0, 2.0],
+                },
+                EmbeddedMessage {
+                    chat_id: "a".to_string(),
+                    chat_title: "Kept".to_string(),
+                    role: "assistant".to_string(),
+                    content: "Error: means it failed".to_string(),
+                    embedding: vec![0.5],
+                }
+            ]
+        );
+
+        delete_chat(&conn, "a").unwrap();
+        prune_message_embeddings(&conn).unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM message_embeddings"), 1);
+    }
+
+    #[test]
+    fn prompt_templates_round_trip() {
+        let conn = memory_db();
+        let template = |name: &str, body: &str| PromptTemplate {
+            name: name.to_string(),
+            description: String::new(),
+            body: body.to_string(),
+        };
+        save_prompt_template(&conn, &template("review", "Review {{code}}")).unwrap();
+        save_prompt_templates(&conn, &[template("explain", "Explain {{topic}}"), template("review", "Check {{code}}")])
+            .unwrap();
+        assert_eq!(
+            list_prompt_templates(&conn).unwrap(),
+            vec![template("explain", "Explain {{topic}}"), template("review", "Check {{code}}")]
+        );
+        delete_prompt_template(&conn, "explain").unwrap();
+        assert_eq!(list_prompt_templates(&conn).unwrap(), vec![template("review", "Check {{code}}")]);
+    }
+
+    #[test]
+    fn personas_are_saved_applied_and_unassigned_on_delete() {
+        let conn = memory_db();
+        let mut persona = Persona {
+            name: "  Reviewer ".to_string(),
+            model: "codellama".to_string(),
+            temperature: Some(0.1),
+            avatar: "🦀".to_string(),
+            ..Persona::default()
+        };
+        persona.id = save_persona(&conn, &persona).unwrap();
+        persona.name = "Reviewer".to_string();
+        assert_eq!(list_personas(&conn).unwrap(), vec![persona.clone()]);
+        assert_eq!(persona.label(), "🦀 Reviewer");
+
+        persona.max_tokens = Some(64);
+        assert_eq!(save_persona(&conn, &persona).unwrap(), persona.id);
+        create_chat(&conn, "a", "Chat", None).unwrap();
+        set_chat_persona(&conn, "a", Some(persona.id)).unwrap();
+        assert_eq!(chat_persona(&conn, "a").unwrap(), Some(persona.clone()));
+
+        let settings = load_settings(&conn).unwrap();
+        let applied = apply_persona(&settings, &persona);
+        assert_eq!((applied.model.as_str(), applied.temperature, applied.max_tokens), ("codellama", 0.1, 64));
+        assert_eq!((applied.system_prompt, applied.top_p), (settings.system_prompt, settings.top_p));
+
+        delete_persona(&conn, persona.id).unwrap();
+        assert_eq!(chat_persona(&conn, "a").unwrap(), None);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM chats WHERE persona_id IS NOT NULL"), 0);
+    }
+
+    #[test]
+    fn comparisons_keep_their_columns_in_order() {
+        let conn = memory_db();
+        create_chat(&conn, "a", "Chat", None).unwrap();
+        let columns = vec![("llama3".to_string(), "t=0.7".to_string()), ("mistral".to_string(), "t=0.2".to_string())];
+        let (id, replies) = create_comparison(&conn, "a", "why?", &columns).unwrap();
+        assert_eq!(replies.len(), 2);
+
+        let finished = CompareColumn {
+            reply_id: replies[1],
+            model: "mistral".to_string(),
+            options: "t=0.2".to_string(),
+            content: "because".to_string(),
+            done: true,
+            error: None,
+            first_token_ms: Some(120),
+            total_ms: Some(900),
+            eval_count: Some(42),
+            tokens_per_sec: Some(46.5),
+        };
+        finish_compare_reply(&conn, &finished).unwrap();
+        choose_compare_reply(&conn, id, 1).unwrap();
+
+        let stored = list_comparisons(&conn, "a").unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!((stored[0].prompt.as_str(), stored[0].chosen), ("why?", Some(1)));
+        assert_eq!(stored[0].columns[0].model, "llama3");
+        assert_eq!(stored[0].columns[0].content, "");
+        assert_eq!(stored[0].columns[1], finished);
+    }
+
+    #[test]
+    fn tool_policies_default_to_ask_and_runs_are_logged() {
+        let conn = memory_db();
+        assert_eq!(load_tool_policy(&conn, "grep"), ToolPolicy::Ask);
+        save_tool_policy(&conn, "grep", ToolPolicy::Allow).unwrap();
+        assert_eq!(load_tool_policy(&conn, "grep"), ToolPolicy::Allow);
+
+        create_chat(&conn, "a", "Chat", None).unwrap();
+        let run = ToolRun {
+            chat_id: "a",
+            tool: "grep",
+            arguments: r#"{"pattern":"fn"}"#,
+            command: Some("grep 'fn'"),
+            decision: "auto",
+            exit_code: Some(0),
+            output: "fn main",
+            duration_ms: 12,
+        };
+        log_tool_run(&conn, &run).unwrap();
+        let logged: (String, String, i64) = conn
+            .query_row("SELECT command, decision, exit_code FROM tool_runs", [], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(logged, ("grep 'fn'".to_string(), "auto".to_string(), 0));
+    }
+
+    #[test]
+    fn history_queries_refuse_writes_and_cap_rows() {
+        let dir = std::env::temp_dir().join(format!("rusty-chat-query-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+        let path = path.to_str().unwrap();
+        let conn = open(path).unwrap();
+        for id in ["a", "b", "c"] {
+            create_chat(&conn, id, &format!("Chat {id}"), None).unwrap();
+        }
+
+        let query = |sql: &str| query_history(path, sql, 2, Duration::from_secs(5));
+
+        let (rows, more) = query("SELECT id, title FROM chats ORDER BY id").unwrap();
+        assert_eq!(rows, vec![serde_json::json!({"id": "a", "title": "Chat a"}), serde_json::json!({"id": "b", "title": "Chat b"})]);
+        assert!(more);
+        let (rows, more) = query("SELECT COUNT(*) AS n FROM chats").unwrap();
+        assert_eq!((rows, more), (vec![serde_json::json!({"n": 3})], false));
+
+        assert!(matches!(query("DELETE FROM chats"), Err(StorageError::Query(_))));
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM chats"), 3);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn history_queries_only_see_visible_chats_and_messages() {
+        let dir = std::env::temp_dir().join(format!("rusty-chat-query-scope-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+        let path = path.to_str().unwrap();
+        let conn = open(path).unwrap();
+        let settings = Settings {
+            api_token: "secret".to_string(),
+            ..Settings::default()
+        };
+        save_settings(&conn, &settings).unwrap();
+        create_chat(&conn, "a", "Kept", None).unwrap();
+        create_chat(&conn, "b", "Trashed", None).unwrap();
+        insert_message(&conn, "a", &ChatMessage::new("user", "hello"), None).unwrap();
+        insert_message(&conn, "b", &ChatMessage::new("user", "hidden"), None).unwrap();
+        trash_chats(&conn, &["b".to_string()]).unwrap();
+        let query = |sql: &str| query_history(path, sql, 10, Duration::from_secs(5));
+
+        let refused = query("SELECT api_token FROM settings").unwrap_err();
+        assert!(matches!(refused, StorageError::Query(_)));
+        assert!(!refused.to_string().contains("secret"));
+        assert!(query("SELECT output FROM tool_runs").is_err());
+        assert!(query("SELECT sql FROM sqlite_master").is_err());
+        assert!(query(&format!("ATTACH '{}' AS original", path)).is_err());
+        assert!(query("PRAGMA table_info(messages)").is_err());
+
+        let (rows, _) = query("SELECT c.title, m.content FROM messages m JOIN chats c ON c.id = m.chat_id").unwrap();
+        assert_eq!(rows, vec![serde_json::json!({"title": "Kept", "content": "hello"})]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn history_queries_stop_at_the_timeout() {
+        let dir = std::env::temp_dir().join(format!("rusty-chat-query-slow-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+        let path = path.to_str().unwrap();
+        open(path).unwrap();
+        let endless = "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n) SELECT COUNT(*) FROM n";
+        let err = query_history(path, endless, 1, Duration::from_millis(200)).unwrap_err();
+        assert!(err.to_string().contains("took longer"), "{}", err);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn chat_errors_are_kept_until_dismissed() {
+        let conn = memory_db();
+        create_chat(&conn, "a", "Chat", None).unwrap();
+        let first = record_chat_error(&conn, "a", &ChatError::Connect("refused".to_string()), "hi").unwrap();
+        let second = record_chat_error(&conn, "a", &ChatError::NoModel, "again").unwrap();
+        assert_eq!(list_chat_errors(&conn, "a").unwrap(), vec![first.clone(), second.clone()]);
+        assert_eq!(first.kind, "connect");
+
+        dismiss_chat_error(&conn, first.id).unwrap();
+        assert_eq!(list_chat_errors(&conn, "a").unwrap(), vec![second]);
+        clear_chat_errors(&conn, "a").unwrap();
+        assert!(list_chat_errors(&conn, "a").unwrap().is_empty());
+    }
+
+    #[test]
+    fn queued_messages_wait_with_their_chat() {
+        let conn = memory_db();
+        create_chat(&conn, "a", "Busy", None).unwrap();
+        create_chat(&conn, "b", "Trashed", None).unwrap();
+        queue_message(&conn, "a", "first").unwrap();
+        queue_message(&conn, "a", "second").unwrap();
+        queue_message(&conn, "b", "later").unwrap();
+        trash_chats(&conn, &["b".to_string()]).unwrap();
+        assert_eq!(chats_with_queue(&conn).unwrap(), HashSet::from(["a".to_string()]));
+
+        let queued = list_queued_messages(&conn, "a").unwrap();
+        assert_eq!(queued.iter().map(|q| q.content.as_str()).collect::<Vec<_>>(), vec!["first", "second"]);
+        update_queued_message(&conn, queued[0].id, "edited").unwrap();
+        remove_queued_message(&conn, queued[1].id).unwrap();
+        assert_eq!(list_queued_messages(&conn, "a").unwrap(), vec![QueuedMessage { id: queued[0].id, content: "edited".to_string() }]);
+        assert_eq!(list_queued_messages(&conn, "b").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn opening_in_a_missing_folder_fails() {
+        let path = std::env::temp_dir().join("rusty-chat-missing-folder").join("chat.db");
+        assert!(matches!(open(path.to_str().unwrap()), Err(StorageError::Open { .. })));
+    }
+
+    #[test]
+    fn backups_are_rotated_and_listed_newest_first() {
+        let dir = std::env::temp_dir().join(format!("rusty-chat-backups-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let conn = memory_db();
+        create_chat(&conn, "a", "First", None).unwrap();
+        insert_message(&conn, "a", &ChatMessage::new("user", "hi"), None).unwrap();
+
+        let mut taken = vec![];
+        for _ in 0..3 {
+            taken.push(create_backup(&conn, &dir, 2).unwrap());
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        create_chat(&conn, "b", "Second", None).unwrap();
+        taken.push(create_backup(&conn, &dir, 2).unwrap());
+
+        let listed = list_backups(&dir);
+        assert_eq!(listed.iter().map(|b| b.path.clone()).collect::<Vec<_>>(), vec![taken[3].clone(), taken[2].clone()]);
+        assert_eq!((listed[0].chats, listed[0].messages), (2, 1));
+        assert_eq!((listed[1].chats, listed[1].messages), (1, 1));
+        assert!(newest_backup_age(&dir).unwrap() < Duration::from_secs(60));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn backups_restore_whole_or_chat_by_chat() {
+        let dir = std::env::temp_dir().join(format!("rusty-chat-restore-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let mut conn = memory_db();
+        create_chat(&conn, "a", "First", None).unwrap();
+        insert_message(&conn, "a", &ChatMessage::new("user", "kept"), None).unwrap();
+        insert_message(&conn, "a", &ChatMessage::new("assistant", "in order"), None).unwrap();
+        create_chat(&conn, "b", "Second", None).unwrap();
+        add_tag(&conn, &["a".to_string()], "work").unwrap();
+        let backup = create_backup(&conn, &dir, 5).unwrap();
+
+        insert_message(&conn, "a", &ChatMessage::new("user", "later"), None).unwrap();
+        delete_chat(&conn, "b").unwrap();
+        create_chat(&conn, "c", "Third", None).unwrap();
+
+        let in_backup = backup_chats(&backup).unwrap();
+        assert_eq!(in_backup.iter().map(|c| (c.id.as_str(), c.messages)).collect::<Vec<_>>(), vec![("b", 0), ("a", 2)]);
+
+        let ids = vec!["a".to_string(), "c".to_string()];
+        assert_eq!(restore_backup_chats(&conn, &backup, &ids).unwrap(), 1);
+        let contents: Vec<String> = load_chat_messages(&conn, "a").unwrap().into_iter().map(|m| m.content).collect();
+        assert_eq!(contents, vec!["kept", "in order"]);
+        assert_eq!(chat_tags(&conn).unwrap()["a"], vec!["work"]);
+        assert!(chat_exists(&conn, "c").unwrap());
+        assert!(!chat_exists(&conn, "b").unwrap());
+
+        restore_backup(&mut conn, &backup).unwrap();
+        let ids: Vec<String> = list_chats(&conn).unwrap().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec!["b", "a"]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn query_errors_are_reported_instead_of_panicking() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(matches!(load_settings(&conn), Err(StorageError::Query(_))));
+        assert!(create_chat(&conn, "c1", "Chat", None).is_err());
+        assert!(matches!(load_chat_messages(&conn, "c1"), Err(StorageError::Query(_))));
+        assert!(chat_persona(&conn, "c1").is_err());
+    }
+}
@@ -0,0 +1,462 @@
+// what gets stored (settings, chats, messages) independent of where: SQLite on the desktop
+// (`storage`), localStorage in the browser (`KvStore`)
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+// Maximum number of messages to keep / load per chat (history limit)
+pub const MAX_HISTORY_MESSAGES: i64 = 10000;
+// Maximum title length for chat rename
+pub const MAX_TITLE_LEN: usize = 255;
+// port of the local API server until one is chosen in Settings
+pub const DEFAULT_API_PORT: i32 = 11435;
+
+#[derive(Debug)]
+pub enum StorageError {
+    // the store can't be opened or isn't usable (corrupt, not SQLite, no permission, no localStorage)
+    Open { path: String, reason: String },
+    // a read or write failed (locked, read-only, disk or quota full, ...)
+    Query(String),
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::Open { path, reason } => write!(f, "Could not open {}: {}", path, reason),
+            StorageError::Query(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+// stable FNV-1a hash of a system prompt (hex), so replies made under different prompts can be told apart
+pub fn hash_system_prompt(prompt: &str) -> Option<String> {
+    if prompt.is_empty() {
+        return None;
+    }
+    Some(fnv1a_hex(prompt))
+}
+
+// 64-bit FNV-1a as 16 hex digits; stable across versions, unlike std's hasher
+pub fn fnv1a_hex(text: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+// clamp helper to ensure DB integer values respect Rust i32 bounds
+pub fn clamp_to_i32(v: i64) -> i32 {
+    if v > i32::MAX as i64 {
+        i32::MAX
+    } else if v < i32::MIN as i64 {
+        i32::MIN
+    } else {
+        v as i32
+    }
+}
+
+// a chat title cut to MAX_TITLE_LEN bytes at a character boundary
+pub fn clamp_title(title: &str) -> String {
+    let mut title = title.to_string();
+    if title.len() > MAX_TITLE_LEN {
+        let mut end = MAX_TITLE_LEN;
+        while !title.is_char_boundary(end) {
+            end -= 1;
+        }
+        title.truncate(end);
+    }
+    title
+}
+
+// missing fields (settings saved by an older version) take their default
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub model: String,
+    pub system_prompt: String,
+    pub temperature: f64,
+    pub top_p: f64,
+    pub max_tokens: i32,
+    pub zoom: i32,
+    pub maximized: bool,
+    pub window_width: i32,
+    pub window_height: i32,
+    // let the model call the built-in tools
+    pub tools_enabled: bool,
+    // the only folder the read_file tool may read from ("" = file access off)
+    pub tool_folder: String,
+    // Ollama model used for /api/embed (knowledge base)
+    pub embed_model: String,
+    // number of knowledge base chunks added to each prompt
+    pub kb_top_k: i32,
+    // embed chat messages in the background for semantic search
+    pub embed_messages: bool,
+    // chats generating at the same time (0 = no limit)
+    pub max_generations: i32,
+    // desktop notification when a reply finishes in a chat that isn't open
+    pub notify_background: bool,
+    // local HTTP API for other tools (see `api`), its port on 127.0.0.1 and the bearer token
+    pub api_enabled: bool,
+    pub api_port: i32,
+    pub api_token: String,
+    // days a deleted chat stays in the Trash before it's removed for good (0 = until emptied)
+    pub trash_days: i32,
+    // hours between automatic backups of the database (0 = off) and how many backups are kept
+    pub backup_hours: i32,
+    pub backup_keep: i32,
+}
+
+// what a new database starts with, also used while the stored settings can't be read
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            // no default model — user must pick one
+            model: String::new(),
+            system_prompt: String::new(),
+            temperature: 0.7,
+            top_p: 0.95,
+            max_tokens: 512,
+            zoom: 100,
+            maximized: true,
+            window_width: 1024,
+            window_height: 768,
+            tools_enabled: false,
+            tool_folder: String::new(),
+            embed_model: String::new(),
+            kb_top_k: 4,
+            embed_messages: false,
+            max_generations: 0,
+            notify_background: false,
+            api_enabled: false,
+            api_port: DEFAULT_API_PORT,
+            api_token: String::new(),
+            trash_days: 30,
+            backup_hours: 24,
+            backup_keep: 10,
+        }
+    }
+}
+
+// a message as held in the visible chat buffer; `model` is only set on assistant replies,
+// `tool_calls` (JSON) on assistant turns that called tools, `tool_name` on 'tool' results and
+// `sources` (JSON) on replies grounded on knowledge base excerpts; `persona` names the persona
+// that wrote an assistant reply
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.into(),
+            model: None,
+            tool_calls: None,
+            tool_name: None,
+            sources: None,
+            persona: None,
+        }
+    }
+}
+
+// how an assistant reply was produced, stored next to it
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationMeta {
+    pub endpoint: String,
+    pub system_prompt_hash: Option<String>,
+    // request options as JSON
+    pub options: Option<String>,
+}
+
+/* ================= STORE ================= */
+
+// the part of storage both the desktop and the browser UI need; the desktop app has many more
+// queries (queue, knowledge base, personas, ...) that only exist on SQLite
+pub trait ChatStore {
+    fn load_settings(&self) -> Result<Settings, StorageError>;
+    fn save_settings(&self, settings: &Settings) -> Result<(), StorageError>;
+    // all chats as (id, title), most recently active first
+    fn list_chats(&self) -> Result<Vec<(String, String)>, StorageError>;
+    fn create_chat(&self, id: &str, title: &str) -> Result<(), StorageError>;
+    // titles longer than MAX_TITLE_LEN are cut; returns the stored title
+    fn rename_chat(&self, id: &str, title: &str) -> Result<String, StorageError>;
+    fn delete_chat(&self, id: &str) -> Result<(), StorageError>;
+    // the newest MAX_HISTORY_MESSAGES messages, oldest first
+    fn load_messages(&self, chat_id: &str) -> Result<Vec<ChatMessage>, StorageError>;
+    fn insert_message(
+        &self,
+        chat_id: &str,
+        msg: &ChatMessage,
+        meta: Option<&GenerationMeta>,
+    ) -> Result<(), StorageError>;
+}
+
+// string values by key, like the browser's localStorage
+pub trait KeyValue {
+    fn get(&self, key: &str) -> Result<Option<String>, StorageError>;
+    fn set(&self, key: &str, value: &str) -> Result<(), StorageError>;
+    fn remove(&self, key: &str) -> Result<(), StorageError>;
+}
+
+const SETTINGS_KEY: &str = "rusty_chat/settings";
+const CHATS_KEY: &str = "rusty_chat/chats";
+
+fn messages_key(chat_id: &str) -> String {
+    format!("rusty_chat/messages/{}", chat_id)
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredChat {
+    id: String,
+    title: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredMessage {
+    #[serde(flatten)]
+    message: ChatMessage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    meta: Option<GenerationMeta>,
+}
+
+// a ChatStore over a key-value store: settings, the chat list and each chat's messages are
+// one JSON value each
+pub struct KvStore<K> {
+    kv: K,
+}
+
+impl<K: KeyValue> KvStore<K> {
+    pub fn new(kv: K) -> Self {
+        KvStore { kv }
+    }
+
+    fn read<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Result<Option<T>, StorageError> {
+        match self.kv.get(key)? {
+            Some(json) => serde_json::from_str(&json)
+                .map(Some)
+                .map_err(|e| StorageError::Query(format!("{} is damaged: {}", key, e))),
+            None => Ok(None),
+        }
+    }
+
+    fn write<T: Serialize>(&self, key: &str, value: &T) -> Result<(), StorageError> {
+        let json = serde_json::to_string(value).map_err(|e| StorageError::Query(e.to_string()))?;
+        self.kv.set(key, &json)
+    }
+
+    fn chats(&self) -> Result<Vec<StoredChat>, StorageError> {
+        Ok(self.read(CHATS_KEY)?.unwrap_or_default())
+    }
+
+    fn messages(&self, chat_id: &str) -> Result<Vec<StoredMessage>, StorageError> {
+        Ok(self.read(&messages_key(chat_id))?.unwrap_or_default())
+    }
+}
+
+impl<K: KeyValue> ChatStore for KvStore<K> {
+    fn load_settings(&self) -> Result<Settings, StorageError> {
+        Ok(self.read(SETTINGS_KEY)?.unwrap_or_default())
+    }
+
+    fn save_settings(&self, settings: &Settings) -> Result<(), StorageError> {
+        self.write(SETTINGS_KEY, settings)
+    }
+
+    fn list_chats(&self) -> Result<Vec<(String, String)>, StorageError> {
+        Ok(self.chats()?.into_iter().map(|c| (c.id, c.title)).collect())
+    }
+
+    fn create_chat(&self, id: &str, title: &str) -> Result<(), StorageError> {
+        let mut chats = self.chats()?;
+        if chats.iter().any(|c| c.id == id) {
+            return Err(StorageError::Query(format!("chat {} already exists", id)));
+        }
+        chats.insert(
+            0,
+            StoredChat {
+                id: id.to_string(),
+                title: title.to_string(),
+            },
+        );
+        self.write(CHATS_KEY, &chats)
+    }
+
+    fn rename_chat(&self, id: &str, title: &str) -> Result<String, StorageError> {
+        let title = clamp_title(title);
+        let mut chats = self.chats()?;
+        if let Some(chat) = chats.iter_mut().find(|c| c.id == id) {
+            chat.title = title.clone();
+            self.write(CHATS_KEY, &chats)?;
+        }
+        Ok(title)
+    }
+
+    fn delete_chat(&self, id: &str) -> Result<(), StorageError> {
+        let mut chats = self.chats()?;
+        chats.retain(|c| c.id != id);
+        self.write(CHATS_KEY, &chats)?;
+        self.kv.remove(&messages_key(id))
+    }
+
+    fn load_messages(&self, chat_id: &str) -> Result<Vec<ChatMessage>, StorageError> {
+        Ok(self.messages(chat_id)?.into_iter().map(|m| m.message).collect())
+    }
+
+    fn insert_message(
+        &self,
+        chat_id: &str,
+        msg: &ChatMessage,
+        meta: Option<&GenerationMeta>,
+    ) -> Result<(), StorageError> {
+        let mut messages = self.messages(chat_id)?;
+        messages.push(StoredMessage {
+            message: msg.clone(),
+            meta: meta.cloned(),
+        });
+        // same history limit as the SQLite store, which also keeps localStorage from filling up
+        let excess = messages.len().saturating_sub(MAX_HISTORY_MESSAGES as usize);
+        messages.drain(..excess);
+        self.write(&messages_key(chat_id), &messages)?;
+        // the chat list is kept in order of activity
+        let mut chats = self.chats()?;
+        if let Some(at) = chats.iter().position(|c| c.id == chat_id).filter(|at| *at > 0) {
+            let chat = chats.remove(at);
+            chats.insert(0, chat);
+            self.write(CHATS_KEY, &chats)?;
+        }
+        Ok(())
+    }
+}
+
+// the page's localStorage; about 5 MB per origin in most browsers, so writes can fail when full
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStorage(web_sys::Storage);
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorage {
+    pub fn open() -> Result<Self, StorageError> {
+        web_sys::window()
+            .and_then(|w| w.local_storage().ok().flatten())
+            .map(LocalStorage)
+            .ok_or_else(|| StorageError::Open {
+                path: "localStorage".to_string(),
+                reason: "this browser doesn't allow the page to store data".to_string(),
+            })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl KeyValue for LocalStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, StorageError> {
+        self.0
+            .get_item(key)
+            .map_err(|e| StorageError::Query(format!("{:?}", e)))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), StorageError> {
+        self.0
+            .set_item(key, value)
+            .map_err(|_| StorageError::Query("the browser's storage for this page is full".to_string()))
+    }
+
+    fn remove(&self, key: &str) -> Result<(), StorageError> {
+        self.0
+            .remove_item(key)
+            .map_err(|e| StorageError::Query(format!("{:?}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::exercise_store;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MemoryKv(RefCell<HashMap<String, String>>);
+
+    impl KeyValue for MemoryKv {
+        fn get(&self, key: &str) -> Result<Option<String>, StorageError> {
+            Ok(self.0.borrow().get(key).cloned())
+        }
+
+        fn set(&self, key: &str, value: &str) -> Result<(), StorageError> {
+            self.0.borrow_mut().insert(key.to_string(), value.to_string());
+            Ok(())
+        }
+
+        fn remove(&self, key: &str) -> Result<(), StorageError> {
+            self.0.borrow_mut().remove(key);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn key_value_store_behaves_like_the_database() {
+        exercise_store(&KvStore::new(MemoryKv::default()));
+    }
+
+    #[test]
+    fn chats_move_to_the_top_when_they_get_a_message() {
+        let store = KvStore::new(MemoryKv::default());
+        for id in ["a", "b", "c"] {
+            store.create_chat(id, id).unwrap();
+        }
+        store.insert_message("a", &ChatMessage::new("user", "bump"), None).unwrap();
+        let ids: Vec<String> = store.list_chats().unwrap().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec!["a", "c", "b"]);
+    }
+
+    #[test]
+    fn settings_from_an_older_version_get_defaults() {
+        let kv = MemoryKv::default();
+        kv.set(SETTINGS_KEY, r#"{"model":"llama3"}"#).unwrap();
+        let settings = KvStore::new(kv).load_settings().unwrap();
+        assert_eq!(settings.model, "llama3");
+        assert_eq!(settings.max_tokens, Settings::default().max_tokens);
+    }
+
+    #[test]
+    fn damaged_values_are_reported() {
+        let kv = MemoryKv::default();
+        kv.set(CHATS_KEY, "not json").unwrap();
+        assert!(matches!(KvStore::new(kv).list_chats(), Err(StorageError::Query(_))));
+    }
+
+    #[test]
+    fn history_is_trimmed_to_the_limit() {
+        let store = KvStore::new(MemoryKv::default());
+        store.create_chat("c", "New Chat").unwrap();
+        let mut messages: Vec<StoredMessage> = (0..MAX_HISTORY_MESSAGES)
+            .map(|i| StoredMessage {
+                message: ChatMessage::new("user", format!("m{}", i)),
+                meta: None,
+            })
+            .collect();
+        messages[0].message.content = "oldest".to_string();
+        store.write(&messages_key("c"), &messages).unwrap();
+
+        store.insert_message("c", &ChatMessage::new("user", "newest"), None).unwrap();
+        let loaded = store.load_messages("c").unwrap();
+        assert_eq!(loaded.len() as i64, MAX_HISTORY_MESSAGES);
+        assert_eq!(loaded[0].content, "m1");
+        assert_eq!(loaded.last().unwrap().content, "newest");
+    }
+}
@@ -0,0 +1,129 @@
+// helpers shared by the test suites: a tiny HTTP server standing in for Ollama, and the checks
+// every ChatStore has to pass
+
+use crate::store::{ChatMessage, ChatStore, GenerationMeta, Settings, MAX_TITLE_LEN};
+use reqwest::Client;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::time::Duration;
+
+pub fn client() -> Client {
+    // the sandbox may set proxy variables; the mock server is always local
+    Client::builder().no_proxy().build().unwrap()
+}
+
+pub fn http_response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+// request head and body as text
+pub fn read_request(stream: &mut TcpStream) -> String {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&data).to_string();
+        if let Some(head_end) = text.find("\r\n\r\n") {
+            let length = text[..head_end]
+                .lines()
+                .find_map(|l| {
+                    let (name, value) = l.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if data.len() >= head_end + 4 + length {
+                return text;
+            }
+        }
+    }
+    String::from_utf8_lossy(&data).to_string()
+}
+
+// serve one connection, writing `parts` with short pauses so the client sees separate chunks;
+// returns the base URL and the request that was received
+pub fn mock_server(parts: Vec<String>) -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        // the test may not look at the request
+        let _ = tx.send(read_request(&mut stream));
+        for part in parts {
+            stream.write_all(part.as_bytes()).unwrap();
+            stream.flush().unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    });
+    (format!("http://{}", addr), rx)
+}
+
+// answer one connection per entry of `replies`, in order; returns the base URL and the requests
+pub fn mock_server_replies(replies: Vec<String>) -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for reply in replies {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = tx.send(read_request(&mut stream));
+            stream.write_all(reply.as_bytes()).unwrap();
+        }
+    });
+    (format!("http://{}", addr), rx)
+}
+
+// the behaviour the UI relies on, whichever storage is behind it; `store` must start empty
+pub fn exercise_store(store: &dyn ChatStore) {
+    assert_eq!(store.load_settings().unwrap(), Settings::default());
+    let settings = Settings {
+        model: "llama3".to_string(),
+        temperature: 0.2,
+        ..Settings::default()
+    };
+    store.save_settings(&settings).unwrap();
+    assert_eq!(store.load_settings().unwrap(), settings);
+
+    store.create_chat("a", "New Chat").unwrap();
+    store.create_chat("b", "New Chat").unwrap();
+    let long = "é".repeat(200);
+    let stored = store.rename_chat("a", &long).unwrap();
+    assert!(stored.len() <= MAX_TITLE_LEN);
+    assert_eq!(
+        store.list_chats().unwrap(),
+        vec![("b".to_string(), "New Chat".to_string()), ("a".to_string(), stored)]
+    );
+
+    let reply = ChatMessage {
+        model: Some("llama3".into()),
+        ..ChatMessage::new("assistant", "answer")
+    };
+    let meta = GenerationMeta {
+        endpoint: "http://localhost:11434".into(),
+        system_prompt_hash: None,
+        options: Some(r#"{"temperature":0.2}"#.into()),
+    };
+    store.insert_message("a", &ChatMessage::new("user", "question"), None).unwrap();
+    store.insert_message("a", &reply, Some(&meta)).unwrap();
+    store.insert_message("b", &ChatMessage::new("user", "kept"), None).unwrap();
+    assert_eq!(
+        store.load_messages("a").unwrap(),
+        vec![ChatMessage::new("user", "question"), reply]
+    );
+
+    store.delete_chat("a").unwrap();
+    assert_eq!(store.list_chats().unwrap().len(), 1);
+    assert!(store.load_messages("a").unwrap().is_empty());
+    assert_eq!(store.load_messages("b").unwrap().len(), 1);
+}
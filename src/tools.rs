@@ -0,0 +1,324 @@
+// the model's tools that don't depend on the UI: definitions, the calculator, command templates
+// and what the user allows to run
+
+use serde_json::Value;
+
+// tool output is cut to this many characters before it goes back to the model
+pub const MAX_TOOL_OUTPUT_CHARS: usize = 16_000;
+
+pub fn tool_definition(name: &str, description: &str, parameters: Value) -> Value {
+    serde_json::json!({
+        "type": "function",
+        "function": {
+            "name": name,
+            "description": description,
+            "parameters": parameters
+        }
+    })
+}
+
+pub fn truncate_tool_output(out: String) -> String {
+    if out.chars().count() > MAX_TOOL_OUTPUT_CHARS {
+        out.chars().take(MAX_TOOL_OUTPUT_CHARS).collect::<String>() + "\n[output truncated]"
+    } else {
+        out
+    }
+}
+
+// whether a command tool runs without asking, asks first or is never offered; stored per tool
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToolPolicy {
+    Allow,
+    Ask,
+    Deny,
+}
+
+impl ToolPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ToolPolicy::Allow => "allow",
+            ToolPolicy::Ask => "ask",
+            ToolPolicy::Deny => "deny",
+        }
+    }
+
+    // unknown values fall back to asking
+    pub fn parse(s: &str) -> ToolPolicy {
+        match s {
+            "allow" => ToolPolicy::Allow,
+            "deny" => ToolPolicy::Deny,
+            _ => ToolPolicy::Ask,
+        }
+    }
+}
+
+// quote a value so the shell passes it through as a single literal argument
+pub fn shell_quote(value: &str) -> String {
+    if cfg!(windows) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+// fill {{name}} placeholders from the call arguments, quoting every substituted value
+pub fn render_command(template: &str, args: &Value) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| "unterminated {{ in command template".to_string())?;
+        let key = after[..end].trim();
+        let value = match args.get(key) {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Null) | None => return Err(format!("missing argument '{}'", key)),
+            Some(other) => other.to_string(),
+        };
+        out.push_str(&shell_quote(&value));
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+// whole numbers print without a trailing ".0"
+pub fn format_number(v: f64) -> String {
+    if v.is_finite() && v.fract() == 0.0 && v.abs() < 1e15 {
+        format!("{}", v as i64)
+    } else {
+        format!("{}", v)
+    }
+}
+
+// small recursive-descent evaluator backing the calculator tool
+pub fn eval_expression(expr: &str) -> Result<f64, String> {
+    struct Parser {
+        chars: Vec<char>,
+        pos: usize,
+    }
+
+    impl Parser {
+        fn peek(&mut self) -> Option<char> {
+            while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+                self.pos += 1;
+            }
+            self.chars.get(self.pos).copied()
+        }
+
+        fn eat(&mut self, c: char) -> bool {
+            if self.peek() == Some(c) {
+                self.pos += 1;
+                true
+            } else {
+                false
+            }
+        }
+
+        // expr := term (('+' | '-') term)*
+        fn expr(&mut self) -> Result<f64, String> {
+            let mut v = self.term()?;
+            loop {
+                if self.eat('+') {
+                    v += self.term()?;
+                } else if self.eat('-') {
+                    v -= self.term()?;
+                } else {
+                    return Ok(v);
+                }
+            }
+        }
+
+        // term := unary (('*' | '/' | '%') unary)*
+        fn term(&mut self) -> Result<f64, String> {
+            let mut v = self.unary()?;
+            loop {
+                if self.eat('*') {
+                    v *= self.unary()?;
+                } else if self.eat('/') {
+                    let d = self.unary()?;
+                    if d == 0.0 {
+                        return Err("division by zero".to_string());
+                    }
+                    v /= d;
+                } else if self.eat('%') {
+                    let d = self.unary()?;
+                    if d == 0.0 {
+                        return Err("division by zero".to_string());
+                    }
+                    v %= d;
+                } else {
+                    return Ok(v);
+                }
+            }
+        }
+
+        // unary := ('-' | '+') unary | power
+        fn unary(&mut self) -> Result<f64, String> {
+            if self.eat('-') {
+                Ok(-self.unary()?)
+            } else if self.eat('+') {
+                self.unary()
+            } else {
+                self.power()
+            }
+        }
+
+        // power := primary ('^' unary)?   (right associative)
+        fn power(&mut self) -> Result<f64, String> {
+            let base = self.primary()?;
+            if self.eat('^') {
+                Ok(base.powf(self.unary()?))
+            } else {
+                Ok(base)
+            }
+        }
+
+        fn primary(&mut self) -> Result<f64, String> {
+            match self.peek() {
+                Some('(') => {
+                    self.pos += 1;
+                    let v = self.expr()?;
+                    if !self.eat(')') {
+                        return Err("missing ')'".to_string());
+                    }
+                    Ok(v)
+                }
+                Some(c) if c.is_ascii_digit() || c == '.' => {
+                    let start = self.pos;
+                    while self
+                        .chars
+                        .get(self.pos)
+                        .is_some_and(|c| c.is_ascii_digit() || *c == '.' || *c == '_')
+                    {
+                        self.pos += 1;
+                    }
+                    let text: String = self.chars[start..self.pos]
+                        .iter()
+                        .filter(|c| **c != '_')
+                        .collect();
+                    text.parse::<f64>()
+                        .map_err(|_| format!("invalid number '{}'", text))
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    let start = self.pos;
+                    while self
+                        .chars
+                        .get(self.pos)
+                        .is_some_and(|c| c.is_ascii_alphanumeric())
+                    {
+                        self.pos += 1;
+                    }
+                    let name: String = self.chars[start..self.pos].iter().collect();
+                    match name.to_ascii_lowercase().as_str() {
+                        "pi" => return Ok(std::f64::consts::PI),
+                        "e" => return Ok(std::f64::consts::E),
+                        _ => {}
+                    }
+                    if !self.eat('(') {
+                        return Err(format!("unknown name '{}'", name));
+                    }
+                    let arg = self.expr()?;
+                    if !self.eat(')') {
+                        return Err("missing ')'".to_string());
+                    }
+                    match name.to_ascii_lowercase().as_str() {
+                        "sqrt" => Ok(arg.sqrt()),
+                        "abs" => Ok(arg.abs()),
+                        "exp" => Ok(arg.exp()),
+                        "ln" => Ok(arg.ln()),
+                        "log" => Ok(arg.log10()),
+                        "sin" => Ok(arg.sin()),
+                        "cos" => Ok(arg.cos()),
+                        "tan" => Ok(arg.tan()),
+                        "floor" => Ok(arg.floor()),
+                        "ceil" => Ok(arg.ceil()),
+                        "round" => Ok(arg.round()),
+                        _ => Err(format!("unknown function '{}'", name)),
+                    }
+                }
+                Some(c) => Err(format!("unexpected '{}'", c)),
+                None => Err("unexpected end of expression".to_string()),
+            }
+        }
+    }
+
+    let mut parser = Parser {
+        chars: expr.chars().collect(),
+        pos: 0,
+    };
+    let v = parser.expr()?;
+    if let Some(c) = parser.peek() {
+        return Err(format!("unexpected '{}'", c));
+    }
+    if !v.is_finite() {
+        return Err("result is not a finite number".to_string());
+    }
+    Ok(v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calculator_follows_precedence() {
+        assert_eq!(eval_expression("1 + 2 * 3").unwrap(), 7.0);
+        assert_eq!(eval_expression("(1 + 2) * 3").unwrap(), 9.0);
+        assert_eq!(eval_expression("2 ^ 3 ^ 2").unwrap(), 512.0);
+        assert_eq!(eval_expression("-2 ^ 2").unwrap(), -4.0);
+        assert_eq!(eval_expression("10 % 4 + 1_000").unwrap(), 1002.0);
+        assert_eq!(eval_expression("sqrt(16) + abs(-1)").unwrap(), 5.0);
+        assert!((eval_expression("cos(pi)").unwrap() + 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn calculator_reports_bad_input() {
+        assert_eq!(eval_expression("1 / 0").unwrap_err(), "division by zero");
+        assert_eq!(eval_expression("(1 + 2").unwrap_err(), "missing ')'");
+        assert_eq!(eval_expression("1 2").unwrap_err(), "unexpected '2'");
+        assert_eq!(eval_expression("foo(1)").unwrap_err(), "unknown function 'foo'");
+        assert_eq!(eval_expression("x").unwrap_err(), "unknown name 'x'");
+        assert_eq!(eval_expression("").unwrap_err(), "unexpected end of expression");
+        assert_eq!(eval_expression("10 ^ 400").unwrap_err(), "result is not a finite number");
+    }
+
+    #[test]
+    fn whole_numbers_print_without_a_fraction() {
+        assert_eq!(format_number(4.0), "4");
+        assert_eq!(format_number(-0.5), "-0.5");
+        assert_eq!(format_number(1e20), "100000000000000000000");
+    }
+
+    #[test]
+    fn substituted_arguments_are_quoted() {
+        let args = serde_json::json!({ "file": "it's; rm -rf /", "count": 3 });
+        let command = render_command("head -n {{ count }} {{file}}", &args).unwrap();
+        if cfg!(windows) {
+            assert_eq!(command, "head -n \"3\" \"it's; rm -rf /\"");
+        } else {
+            assert_eq!(command, "head -n '3' 'it'\\''s; rm -rf /'");
+        }
+        assert_eq!(render_command("echo {{missing}}", &args).unwrap_err(), "missing argument 'missing'");
+        assert_eq!(render_command("echo {{file", &args).unwrap_err(), "unterminated {{ in command template");
+    }
+
+    #[test]
+    fn long_output_is_cut() {
+        let long = "x".repeat(MAX_TOOL_OUTPUT_CHARS + 1);
+        let cut = truncate_tool_output(long);
+        assert!(cut.ends_with("\n[output truncated]"));
+        assert_eq!(cut.chars().filter(|c| *c == 'x').count(), MAX_TOOL_OUTPUT_CHARS);
+        assert_eq!(truncate_tool_output("short".into()), "short");
+    }
+
+    #[test]
+    fn unknown_policies_ask() {
+        for policy in [ToolPolicy::Allow, ToolPolicy::Ask, ToolPolicy::Deny] {
+            assert_eq!(ToolPolicy::parse(policy.as_str()), policy);
+        }
+        assert_eq!(ToolPolicy::parse("sometimes"), ToolPolicy::Ask);
+    }
+}
@@ -0,0 +1,411 @@
+// UI pieces both the desktop app and the browser page are built from. Chats, messages and
+// settings go through `ChatStore`; only where that store lives differs per platform
+
+use dioxus::prelude::*;
+use serde_json::Value;
+
+use rusty_chat::backend::OllamaToolCall;
+use rusty_chat::conversation::json_type_name;
+use rusty_chat::knowledge::KbHit;
+#[cfg(not(target_arch = "wasm32"))]
+use rusty_chat::storage::SqliteStore;
+#[cfg(target_arch = "wasm32")]
+use rusty_chat::store::{KvStore, LocalStorage};
+use rusty_chat::store::{clamp_title, clamp_to_i32, ChatStore, StorageError};
+
+/* ================= STORE ================= */
+
+// SQLite next to the app on the desktop, the page's localStorage in the browser
+#[cfg(not(target_arch = "wasm32"))]
+pub type AppStore = SqliteStore;
+#[cfg(target_arch = "wasm32")]
+pub type AppStore = KvStore<LocalStorage>;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open_store() -> Result<AppStore, StorageError> {
+    Ok(SqliteStore::new(&crate::desktop::db_path()))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn open_store() -> Result<AppStore, StorageError> {
+    LocalStorage::open().map(KvStore::new)
+}
+
+/* ================= ERROR BANNER ================= */
+
+// storage errors shown at the top of the window until dismissed
+static APP_ERRORS: GlobalSignal<Vec<String>> = Signal::global(Vec::new);
+
+// show `err` in the banner (once, however often it happens)
+pub fn report_error(err: impl std::fmt::Display) {
+    let message = err.to_string();
+    eprintln!("{}", message);
+    if !APP_ERRORS.peek().contains(&message) {
+        APP_ERRORS.write().push(message);
+    }
+}
+
+// what a storage read returned, or nothing after the error went to the banner
+// (`what` completes "Could not …")
+pub fn or_report<T: Default>(result: Result<T, StorageError>, what: &str) -> T {
+    result.unwrap_or_else(|e| {
+        report_error(format!("Could not {}: {}", what, e));
+        T::default()
+    })
+}
+
+#[component]
+pub fn ErrorBanner() -> Element {
+    let errors = APP_ERRORS();
+    if errors.is_empty() {
+        return rsx! {};
+    }
+    rsx! {
+        div { class: "error-banner",
+            for (i, error) in errors.into_iter().enumerate() {
+                div { key: "{i}", class: "error-banner-row",
+                    span { class: "error-banner-text", "⚠️ {error}" }
+                    button {
+                        class: "error-banner-dismiss",
+                        title: "Dismiss",
+                        onclick: move |_| {
+                            APP_ERRORS.write().remove(i);
+                        },
+                        "✕"
+                    }
+                }
+            }
+        }
+    }
+}
+
+/* ================= CHAT LIST ================= */
+
+// inline title editor of a chat in the sidebar; the new title is stored, then shown in `chats`
+#[component]
+pub fn RenameRow(
+    chat_id: String,
+    chats: Signal<Vec<(String, String)>>,
+    editing_chat: Signal<Option<String>>,
+    edit_text: Signal<String>,
+) -> Element {
+    rsx! {
+        div { class: "rename-row",
+            input {
+                class: "rename-input",
+                value: "{edit_text}",
+                // clicks inside the row must not open the chat underneath
+                onclick: move |e| e.stop_propagation(),
+                // enforce the title length limit while typing
+                oninput: move |e| edit_text.set(clamp_title(&e.value())),
+            }
+            button {
+                class: "rename-save",
+                onclick: move |e| {
+                    e.stop_propagation();
+                    let title = match open_store().and_then(|s| s.rename_chat(&chat_id, &edit_text())) {
+                        Ok(t) => t,
+                        Err(e) => return report_error(format!("Could not rename the chat: {}", e)),
+                    };
+                    for chat in chats.write().iter_mut() {
+                        if chat.0 == chat_id {
+                            chat.1 = title.clone();
+                        }
+                    }
+                    editing_chat.set(None);
+                },
+                "Save"
+            }
+            button {
+                class: "rename-cancel",
+                onclick: move |e| {
+                    e.stop_propagation();
+                    editing_chat.set(None);
+                },
+                "Cancel"
+            }
+        }
+    }
+}
+
+/* ================= SETTINGS ================= */
+
+// the model and sampling fields of the Settings dialog; `models_note` explains an empty model list
+#[component]
+pub fn ModelSettings(
+    model: Signal<String>,
+    models: Vec<String>,
+    models_note: Option<String>,
+    system_prompt: Signal<String>,
+    temperature: Signal<f64>,
+    top_p: Signal<f64>,
+    max_tokens: Signal<i32>,
+) -> Element {
+    // the stored model stays selectable while Ollama doesn't list it (offline, removed)
+    let mut options = models;
+    if !model().is_empty() && !options.contains(&model()) {
+        options.insert(0, model());
+    }
+
+    rsx! {
+        label { "Model (choose one of the available Ollama models)" }
+        select {
+            class: "input",
+            value: "{model}",
+            onchange: move |e| model.set(e.value()),
+            // selected while no model is chosen
+            option { selected: model().is_empty(), value: "", "- Select a model -" }
+            // mark the selected option explicitly so the browser doesn't fall back to the first one
+            {options.iter().map(|m| rsx!( option { selected: m == &model(), value: "{m}", "{m}" } ))}
+        }
+
+        if let Some(note) = models_note {
+            p { class: "dim-text warning-text", "{note}" }
+        }
+
+        if model().is_empty() {
+            p { class: "dim-text warning-text", "No model selected - pick a model to allow sending messages." }
+        }
+
+        label { "System prompt (optional)" }
+        textarea {
+            class: "textarea",
+            value: "{system_prompt}",
+            oninput: move |e| system_prompt.set(e.value()),
+        }
+
+        label { "Temperature" }
+        input {
+            class: "input",
+            r#type: "number",
+            step: "0.05",
+            min: "0.0",
+            max: "2.0",
+            value: "{temperature}",
+            oninput: move |e| temperature.set(e.value().parse::<f64>().unwrap_or(0.7))
+        }
+
+        label { "Top-p" }
+        input {
+            class: "input",
+            r#type: "number",
+            step: "0.01",
+            min: "0.0",
+            max: "1.0",
+            value: "{top_p}",
+            oninput: move |e| top_p.set(e.value().parse::<f64>().unwrap_or(0.95))
+        }
+
+        label { "Max tokens (clamped to Rust/i32 limits)" }
+        input {
+            class: "input",
+            r#type: "number",
+            step: "1",
+            min: "1",
+            max: format!("{}", i32::MAX),
+            value: "{max_tokens}",
+            oninput: move |e| {
+                let parsed = e.value().parse::<i64>().unwrap_or(512);
+                max_tokens.set(clamp_to_i32(parsed));
+            }
+        }
+    }
+}
+
+/* ================= MESSAGES ================= */
+
+// collapsible tree view of a JSON value, used for structured replies
+#[component]
+fn JsonTree(value: Value, label: Option<String>) -> Element {
+    let key = label.map(|l| format!("{}: ", l)).unwrap_or_default();
+    match value {
+        Value::Object(map) => {
+            let count = map.len();
+            rsx! {
+                details { class: "json-node", open: true,
+                    summary { span { class: "json-key", "{key}" } "{{…}} {count} keys" }
+                    div { class: "json-children",
+                        {map.into_iter().map(|(k, v)| rsx! { JsonTree { value: v, label: Some(k) } })}
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            let count = items.len();
+            rsx! {
+                details { class: "json-node", open: true,
+                    summary { span { class: "json-key", "{key}" } "[…] {count} items" }
+                    div { class: "json-children",
+                        {items.into_iter().enumerate().map(|(i, v)| rsx! { JsonTree { value: v, label: Some(i.to_string()) } })}
+                    }
+                }
+            }
+        }
+        leaf => {
+            let class_name = format!("json-leaf json-{}", json_type_name(&leaf));
+            let text = leaf.to_string();
+            rsx! {
+                div { class: "json-row",
+                    span { class: "json-key", "{key}" }
+                    span { class: "{class_name}", "{text}" }
+                }
+            }
+        }
+    }
+}
+
+// one message of the history; `persona` is the (label, accent color) of the persona that wrote
+// a reply, and `children` are extra actions shown under the message
+#[component]
+pub fn Message(
+    role: String,
+    content: String,
+    model: Option<String>,
+    tool_calls: Option<String>,
+    tool_name: Option<String>,
+    sources: Option<String>,
+    persona: Option<(String, String)>,
+    children: Element,
+) -> Element {
+    // tool results get their own compact bubble
+    if role == "tool" {
+        let name = tool_name.unwrap_or_else(|| "tool".to_string());
+        return rsx! {
+            div { class: "message tool-message",
+                p { class: "tool-label", "🔧 {name} returned" }
+                pre { class: "tool-output", "{content}" }
+            }
+        };
+    }
+
+    let class_name = if role == "user" {
+        "message user-message"
+    } else {
+        "message assistant-message"
+    };
+
+    // one line per tool the assistant asked to run
+    let calls: Vec<OllamaToolCall> = tool_calls
+        .as_deref()
+        .and_then(|t| serde_json::from_str(t).ok())
+        .unwrap_or_default();
+    // knowledge base excerpts the reply was based on, numbered like in the prompt
+    let hits: Vec<KbHit> = sources
+        .as_deref()
+        .and_then(|t| serde_json::from_str(t).ok())
+        .unwrap_or_default();
+    let source_list = if hits.is_empty() {
+        rsx! { Fragment {} }
+    } else {
+        rsx! {
+            div { class: "kb-sources",
+                span { class: "tool-label", "Sources" }
+                {hits.iter().enumerate().map(|(i, hit)| {
+                    let n = i + 1;
+                    let part = hit.chunk_index + 1;
+                    let score = format!("{:.2}", hit.score);
+                    rsx! { p { class: "kb-source", "[{n}] {hit.path} (part {part}, similarity {score})" } }
+                })}
+            }
+        }
+    };
+    let tool_call_lines = rsx! {
+        {calls.iter().map(|call| {
+            let args = call.function.arguments.to_string();
+            rsx! { p { class: "tool-call", "🔧 Calling {call.function.name}({args})" } }
+        })}
+    };
+
+    // badges naming the persona and model that produced an assistant reply
+    let persona_badge = match &persona {
+        Some((label, accent)) => {
+            rsx! { span { class: "persona-badge", style: "border-color: {accent}; color: {accent};", "{label}" } }
+        }
+        None => rsx! { Fragment {} },
+    };
+    let model_badge = match model.as_deref() {
+        Some(m) if !m.is_empty() => rsx! { {persona_badge} span { class: "model-badge", "{m}" } },
+        _ => persona_badge,
+    };
+
+    if content.contains("<think>") && content.contains("</think>") {
+        let think_start = content.find("<think>").unwrap() + "<think>".len();
+        let think_end = content.find("</think>").unwrap();
+        let think_content = &content[think_start..think_end].trim();
+
+        let before_think = &content[..think_start - "<think>".len()];
+        let after_think = &content[think_end + "</think>".len()..];
+
+        rsx! {
+            div { class: "{class_name}",
+                {model_badge}
+
+                {if !before_think.is_empty() {
+                    rsx! { p { class: "dim-text", "{before_think}" } }
+                } else {
+                    rsx! { Fragment {} }
+                }}
+
+                div { class: "think-bubble",
+                    p { class: "think-label", "🤔 Thinking..." }
+                    div { class: "think-content dim-text",
+                        "\n"
+                        "{think_content}"
+                        "\n"
+                    }
+                }
+
+                {if !after_think.is_empty() {
+                    rsx! { p { class: "dim-text", "{after_think}" } }
+                } else {
+                    rsx! { Fragment {} }
+                }}
+
+                {tool_call_lines}
+                {source_list}
+                {children}
+            }
+        }
+    } else {
+        // structured (JSON object/array) replies are shown as a collapsible tree
+        let json_reply = if role == "assistant" {
+            serde_json::from_str::<Value>(content.trim())
+                .ok()
+                .filter(|v| v.is_object() || v.is_array())
+        } else {
+            None
+        };
+
+        rsx! {
+            div { class: "{class_name}",
+                {model_badge}
+                if let Some(value) = json_reply {
+                    div { class: "json-tree", JsonTree { value, label: None } }
+                } else if !content.is_empty() {
+                    p { class: "dim-text", "{content}" }
+                }
+                {tool_call_lines}
+                {source_list}
+                {children}
+            }
+        }
+    }
+}
+
+// the bubble of a reply still being generated: a `note` (why it is being retried) comes first,
+// then the text streamed so far, then `status` while nothing has arrived
+#[component]
+pub fn PendingReply(note: Option<String>, partial: Option<String>, status: String) -> Element {
+    rsx! {
+        div { class: "message assistant-message loading-message",
+            if let Some(note) = note {
+                p { class: "dim-text warning-text", "{note}" }
+            } else if let Some(partial) = partial.filter(|p| !p.is_empty()) {
+                p { class: "dim-text partial-reply", "{partial}" }
+            } else {
+                p { "{status}" }
+            }
+            div { class: "loading-dots" }
+        }
+    }
+}
@@ -0,0 +1,402 @@
+// the browser page: the shared `ui` components laid out for chatting with a local Ollama. Everything
+// is kept in the page's localStorage; tools, the knowledge base, personas, model comparison, queued
+// messages and the local API need the desktop app
+
+use dioxus::prelude::*;
+use reqwest::Client;
+use uuid::Uuid;
+
+use rusty_chat::backend::{fetch_model_names, read_chat_stream, send_chat, ChatError, OllamaChatRequest, OLLAMA_BASE_URL};
+use rusty_chat::conversation::{build_chat_messages, request_options};
+use rusty_chat::store::{hash_system_prompt, ChatMessage, ChatStore, GenerationMeta, Settings};
+
+use crate::ui::{open_store, or_report, report_error, ErrorBanner, Message, ModelSettings, PendingReply, RenameRow};
+use crate::{FAVICON, MAIN_CSS};
+
+/* ================= APP ================= */
+
+#[component]
+pub fn App() -> Element {
+    let settings = use_signal(|| or_report(open_store().and_then(|s| s.load_settings()), "load settings"));
+    let chats = use_signal(|| or_report(open_store().and_then(|s| s.list_chats()), "load chats"));
+    let current_chat_id = use_signal(|| Option::<String>::None);
+    let messages = use_signal(Vec::<ChatMessage>::new);
+    let show_settings = use_signal(|| false);
+
+    rsx! {
+        document::Link { rel: "icon", href: FAVICON }
+        document::Link { rel: "stylesheet", href: MAIN_CSS }
+
+        div { class: "outer-wrapper", style: "width: 100vw; height: 100vh;",
+            ErrorBanner {}
+            div { class: "app-container",
+                Sidebar { chats, current_chat_id, messages, show_settings }
+                ChatWindow { current_chat_id, messages, settings, chats }
+            }
+
+            if show_settings() {
+                SettingsModal { settings, show_settings }
+            }
+        }
+    }
+}
+
+/* ================= SIDEBAR ================= */
+
+#[component]
+fn Sidebar(
+    chats: Signal<Vec<(String, String)>>,
+    current_chat_id: Signal<Option<String>>,
+    messages: Signal<Vec<ChatMessage>>,
+    show_settings: Signal<bool>,
+) -> Element {
+    let mut editing_chat = use_signal(|| Option::<String>::None);
+    let mut edit_text = use_signal(|| "".to_string());
+
+    rsx! {
+        div { class: "sidebar",
+            h1 { class: "logo", "RustyChat" }
+
+            button {
+                class: "new-chat-btn big",
+                onclick: move |_| {
+                    let new_id = Uuid::new_v4().to_string();
+                    let title = "New Chat".to_string();
+                    if let Err(e) = open_store().and_then(|s| s.create_chat(&new_id, &title)) {
+                        return report_error(format!("Could not create a chat: {}", e));
+                    }
+                    chats.insert(0, (new_id.clone(), title));
+                    current_chat_id.set(Some(new_id));
+                    messages.set(vec![]);
+                },
+                "➕ New Chat"
+            }
+
+            div { class: "chat-list",
+                {chats().into_iter().map(|(id, title)| {
+                    let id_for_open = id.clone();
+                    let id_for_rename = id.clone();
+                    let id_for_delete = id.clone();
+                    let editing = editing_chat().as_deref() == Some(id.as_str());
+
+                    rsx! {
+                        div { key: "{id}", class: "chat-item-row",
+                            div {
+                                class: "chat-item",
+                                onclick: move |_| {
+                                    messages.set(or_report(open_store().and_then(|s| s.load_messages(&id_for_open)), "load the chat"));
+                                    current_chat_id.set(Some(id_for_open.clone()));
+                                },
+
+                                if editing {
+                                    RenameRow { chat_id: id.clone(), chats, editing_chat, edit_text }
+                                } else {
+                                    div { class: "chat-title", "{title}" }
+                                    div { class: "chat-actions",
+                                        button {
+                                            class: "rename-btn",
+                                            onclick: move |e| {
+                                                e.stop_propagation();
+                                                editing_chat.set(Some(id_for_rename.clone()));
+                                                edit_text.set(title.clone());
+                                            },
+                                            "Rename"
+                                        }
+                                        button {
+                                            class: "delete-chat-btn big",
+                                            onclick: move |e| {
+                                                e.stop_propagation();
+                                                if let Err(e) = open_store().and_then(|s| s.delete_chat(&id_for_delete)) {
+                                                    return report_error(format!("Could not delete the chat: {}", e));
+                                                }
+                                                chats.retain(|(cid, _)| cid != &id_for_delete);
+                                                if current_chat_id() == Some(id_for_delete.clone()) {
+                                                    current_chat_id.set(None);
+                                                    messages.set(vec![]);
+                                                }
+                                            },
+                                            "Delete"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                })}
+            }
+
+            div { class: "sidebar-footer",
+                button {
+                    class: "settings-btn big",
+                    onclick: move |_| show_settings.set(!show_settings()),
+                    span { class: "settings-icon", "⚙️" }
+                    span { class: "settings-tooltip", "Settings" }
+                }
+                a { class: "repo-icon", href: "https://github.com/KPCOFGS/RustyChat", target: "_blank", title: "click here to see the repository", "🔗" }
+            }
+        }
+    }
+}
+
+/* ================= SETTINGS ================= */
+
+#[component]
+fn SettingsModal(settings: Signal<Settings>, show_settings: Signal<bool>) -> Element {
+    let local_model = use_signal(|| settings().model.clone());
+    let local_system = use_signal(|| settings().system_prompt.clone());
+    let local_temp = use_signal(|| settings().temperature);
+    let local_top_p = use_signal(|| settings().top_p);
+    let local_max_tokens = use_signal(|| settings().max_tokens);
+
+    let models = use_resource(|| async move { fetch_model_names(&Client::new(), OLLAMA_BASE_URL).await });
+    let (model_names, models_note) = match &*models.read() {
+        Some(Ok(names)) => (names.clone(), None),
+        Some(Err(e)) => (
+            vec![],
+            Some(format!(
+                "Could not load the model list from {OLLAMA_BASE_URL}: {e}. Ollama has to be running and allow this page (OLLAMA_ORIGINS)."
+            )),
+        ),
+        None => (vec![], None),
+    };
+
+    let apply = move |_| {
+        let new_settings = Settings {
+            model: local_model(),
+            system_prompt: local_system(),
+            temperature: local_temp(),
+            top_p: local_top_p(),
+            max_tokens: local_max_tokens(),
+            // fields only the desktop app uses are kept as they are
+            ..settings()
+        };
+        if let Err(e) = open_store().and_then(|s| s.save_settings(&new_settings)) {
+            return report_error(format!("Could not save settings: {}", e));
+        }
+        settings.set(new_settings);
+        show_settings.set(false);
+    };
+
+    rsx! {
+        div { class: "settings-overlay",
+            div { class: "settings-modal",
+                h3 { "Settings" }
+
+                ModelSettings {
+                    model: local_model,
+                    models: model_names,
+                    models_note,
+                    system_prompt: local_system,
+                    temperature: local_temp,
+                    top_p: local_top_p,
+                    max_tokens: local_max_tokens
+                }
+
+                div { class: "modal-actions",
+                    button { onclick: apply, "Apply" }
+                    button { onclick: move |_| show_settings.set(false), "Cancel" }
+                }
+            }
+        }
+    }
+}
+
+/* ================= CHAT WINDOW ================= */
+
+// the reply being generated: its chat and the text so far
+#[derive(Clone, PartialEq)]
+struct RunningReply {
+    chat_id: String,
+    run: u32,
+    text: String,
+}
+
+#[component]
+fn ChatWindow(
+    current_chat_id: Signal<Option<String>>,
+    messages: Signal<Vec<ChatMessage>>,
+    settings: Signal<Settings>,
+    chats: Signal<Vec<(String, String)>>,
+) -> Element {
+    let mut input_text = use_signal(|| "".to_string());
+    let mut pending = use_signal(|| Option::<RunningReply>::None);
+    // failed requests of the open chat; shown but never stored
+    let mut send_error = use_signal(|| Option::<(String, String)>::None);
+    // numbers each send, so a request that was interrupted can't write its reply
+    let mut run_counter = use_signal(|| 0u32);
+
+    let header_title = current_chat_id()
+        .and_then(|cid| chats().into_iter().find(|(id, _)| *id == cid).map(|(_, t)| t))
+        .unwrap_or_else(|| "Select or create a chat".to_string());
+    let model_display = if settings().model.is_empty() {
+        "No model selected".to_string()
+    } else {
+        settings().model.clone()
+    };
+    let busy = pending().is_some();
+    let visible_pending = pending().filter(|p| Some(&p.chat_id) == current_chat_id().as_ref());
+    let visible_error = send_error()
+        .filter(|(cid, _)| Some(cid) == current_chat_id().as_ref())
+        .map(|(_, e)| e);
+
+    let mut send = move |chat_id: String, text: String| {
+        let settings = settings();
+        if settings.model.is_empty() {
+            send_error.set(Some((chat_id, ChatError::NoModel.to_string())));
+            return;
+        }
+        let store = match open_store() {
+            Ok(store) => store,
+            Err(e) => return report_error(e),
+        };
+        let user_message = ChatMessage::new("user", text.clone());
+        if let Err(e) = store.insert_message(&chat_id, &user_message, None) {
+            input_text.set(text);
+            return report_error(format!("Your message was not sent: {}", e));
+        }
+        messages.push(user_message);
+        send_error.set(None);
+        // the store lists chats by activity; keep the sidebar in the same order
+        let position = chats.peek().iter().position(|(id, _)| *id == chat_id);
+        if let Some(at) = position.filter(|at| *at > 0) {
+            let chat = chats.write().remove(at);
+            chats.write().insert(0, chat);
+        }
+
+        let history = messages();
+        let options = request_options(&settings);
+        let request = OllamaChatRequest {
+            model: settings.model.clone(),
+            messages: build_chat_messages(&settings.system_prompt, &history, &text, None),
+            stream: true,
+            options: Some(options.clone()),
+            tools: None,
+            format: None,
+        };
+        let meta = GenerationMeta {
+            endpoint: OLLAMA_BASE_URL.to_string(),
+            system_prompt_hash: hash_system_prompt(&settings.system_prompt),
+            options: Some(options.to_string()),
+        };
+
+        run_counter += 1;
+        let run = run_counter();
+        pending.set(Some(RunningReply {
+            chat_id: chat_id.clone(),
+            run,
+            text: String::new(),
+        }));
+
+        spawn(async move {
+            let still_running = move || pending.peek().as_ref().map(|p| p.run) == Some(run);
+            let result = match send_chat(&Client::new(), OLLAMA_BASE_URL, &request).await {
+                Ok(response) => read_chat_stream(response, |text| {
+                    if still_running() {
+                        if let Some(p) = pending.write().as_mut() {
+                            p.text = text.to_string();
+                        }
+                    }
+                })
+                .await
+                .map_err(ChatError::Stream),
+                Err(e) => Err(e),
+            };
+            if !still_running() {
+                return;
+            }
+            pending.set(None);
+            match result {
+                Ok(streamed) => {
+                    let reply = ChatMessage {
+                        model: Some(request.model.clone()),
+                        ..ChatMessage::new("assistant", streamed.message.content)
+                    };
+                    if let Err(e) = store.insert_message(&chat_id, &reply, Some(&meta)) {
+                        report_error(format!("The reply could not be saved: {}", e));
+                    }
+                    if current_chat_id.peek().as_deref() == Some(chat_id.as_str()) {
+                        messages.push(reply);
+                    }
+                }
+                Err(e) => send_error.set(Some((chat_id, e.to_string()))),
+            }
+        });
+    };
+
+    rsx! {
+        div { class: "chat-window",
+
+            div { class: "chat-header",
+                h2 { "{header_title}" }
+                p { class: "model-indicator", "Model: {model_display}" }
+            }
+
+            div { class: "chat-messages",
+                {messages().into_iter().enumerate().map(|(i, msg)| {
+                    rsx! {
+                        Message {
+                            key: "{i}",
+                            role: msg.role,
+                            content: msg.content,
+                            model: msg.model,
+                            tool_calls: msg.tool_calls,
+                            tool_name: msg.tool_name,
+                            sources: msg.sources,
+                            persona: None
+                        }
+                    }
+                })}
+
+                if let Some(err) = visible_error {
+                    div { class: "message error-message",
+                        p { class: "error-text", "⚠️ {err}" }
+                        div { class: "row",
+                            button { class: "rename-cancel", onclick: move |_| send_error.set(None), "Dismiss" }
+                        }
+                    }
+                }
+
+                if let Some(reply) = visible_pending {
+                    PendingReply { note: None, partial: Some(reply.text), status: "Thinking..." }
+                }
+            }
+
+            div { class: "chat-input-area",
+                textarea {
+                    class: "chat-input",
+                    placeholder: "Send a message...",
+                    value: "{input_text}",
+                    oninput: move |e| input_text.set(e.value()),
+                }
+
+                if busy {
+                    button {
+                        class: "interrupt-button big",
+                        onclick: move |_| {
+                            // the running request finishes in the background but its reply is dropped
+                            pending.set(None);
+                        },
+                        "Interrupt"
+                    }
+                }
+
+                button {
+                    class: "send-button big",
+                    // one reply at a time in the browser build
+                    disabled: current_chat_id().is_none() || input_text().trim().is_empty() || busy,
+                    onclick: move |_| {
+                        if let Some(chat_id) = current_chat_id() {
+                            let text = input_text();
+                            if text.trim().is_empty() {
+                                return;
+                            }
+                            input_text.set("".to_string());
+                            send(chat_id, text);
+                        }
+                    },
+                    "➤ Send"
+                }
+            }
+        }
+    }
+}